@@ -8,11 +8,10 @@ use bevy::log::LogPlugin;
 use std::sync::mpsc;
 use std::{thread, time, io};
 use std::sync::mpsc::{TryRecvError, Receiver};
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use bevy::app::{ScheduleRunnerPlugin, ScheduleRunnerSettings};
-use regex::Regex;
-use cardgame::Card;
-use cardgame::error::{GameError, RoomError};
+use cardgame::{advise_bid, decompose, format_message, thread_rng, Action, Card, CardList, GameState, Locale, Localize, MatchStats, Rng, RoomConfig};
+use cardgame::error::{AuthError, GameError};
 use crate::chat::{ChatMessages, ChatMessage};
 use crate::chat::ChatMessage::SystemMessage;
 use crate::ClientStatus::{Gaming, Idle, NotLoggedIn, WaitingForLandlord};
@@ -98,6 +97,16 @@ pub struct ClientState {
     pub landlord_name: String,
     pub status: ClientStatus,
     pub last_packet_time: SystemTime,
+    pub locale: Locale,
+
+    /// 本局洗牌种子的承诺
+    pub deal_commitment: String,
+
+    /// 本局发到的手牌，公开种子后用于验证
+    pub dealt_cards: Vec<Card>,
+
+    /// 登入后服务器发回的会话令牌，重新连接时使用
+    pub session_token: Option<String>,
 }
 
 impl Default for ClientState {
@@ -108,32 +117,14 @@ impl Default for ClientState {
             landlord_name: String::default(),
             cards: vec![],
             user_name: String::default(),
+            locale: Locale::from_env(),
+            deal_commitment: String::default(),
+            dealt_cards: vec![],
+            session_token: None,
         }
     }
 }
 
-pub fn parse_input(input: &str) -> Option<Vec<Card>> {
-    let input = input
-        .to_ascii_uppercase()
-        .replace("10", "ß")
-        .replace("1", "0")
-        .replace("ß", "1");
-    let cards_regex = Regex::new("([1-9jqkaJQKA]|鬼|王)+").unwrap();
-    let result = cards_regex.find(input.as_str())?;
-    let result = result.as_str().replace("10", "1");
-
-    let mut vec: Vec<Card> = Vec::new();
-    for c in result.chars() {
-        vec.push(Card::from_char(&c));
-    }
-    vec.retain(|&i| i != Card::Unknown);
-    if vec.is_empty() {
-        None
-    } else {
-        Some(vec)
-    }
-}
-
 fn handle_input(
     net: Res<NetworkClient>,
     mut state: ResMut<ClientState>,
@@ -149,6 +140,14 @@ fn handle_input(
                 state.last_packet_time = SystemTime::now();
                 net.send_message(C2SMessage::Ping);
             }
+            "明牌" => {
+                match state.status {
+                    ClientStatus::WaitingForPlayers(_) | ClientStatus::WaitingForLandlord => {
+                        net.send_message(C2SMessage::ShowHand);
+                    }
+                    _ => println!("只能在发牌前或叫地主时明牌！"),
+                }
+            }
             "叫地主" => {
                 if state.status != ClientStatus::WaitingForLandlord {
                     println!("此时还不能叫地主！");
@@ -167,6 +166,27 @@ fn handle_input(
                     net.send_message(C2SMessage::ChooseLandlord(false));
                 }
             }
+            "理牌" => {
+                if state.cards.is_empty() {
+                    println!("你还没有手牌！");
+                } else {
+                    println!("{}", decompose(&state.cards));
+                }
+            }
+            "提示" => {
+                if state.status != ClientStatus::WaitingForLandlord {
+                    println!("只有叫地主时才有提示！");
+                } else {
+                    println!("{}", advise_bid(&state.cards).localize(state.locale));
+                }
+            }
+            "投降" | "不投降" => {
+                if state.status == ClientStatus::Gaming {
+                    net.send_message(C2SMessage::SurrenderVote(line == "投降"));
+                } else {
+                    println!("游戏还没有开始！");
+                }
+            }
             "再来一局" => {
                 if state.status == ClientStatus::WaitingForRematch {
                     net.send_message(C2SMessage::RematchVote(true));
@@ -211,17 +231,19 @@ fn handle_input(
             "游戏列表" => {
                 net.send_message(C2SMessage::QueryRoomList);
             }
+            "history" => {
+                net.send_message(C2SMessage::QueryHistory);
+            }
             _ => {
                 let splitted: Vec<&str> = line.split(" ").collect();
                 match splitted[0] {
                     "出牌" => {
                         if state.status == ClientStatus::Gaming {
-                            let str = splitted[1];
-                            let cards = parse_input(str);
-                            if let Some(cards) = cards {
-                                net.send_message(C2SMessage::SubmitCards(cards));
-                            } else {
-                                println!("你没有出任何牌！")
+                            let str = line.trim_start_matches("出牌");
+                            match str.parse::<CardList>() {
+                                Ok(cards) if cards.0.is_empty() => println!("你没有出任何牌！"),
+                                Ok(cards) => net.send_message(C2SMessage::SubmitCards(cards.0)),
+                                Err(e) => println!("{}", e.localize(state.locale)),
                             }
                         } else {
                             println!("你现在还不能出牌！");
@@ -236,12 +258,32 @@ fn handle_input(
                             net.send_message(C2SMessage::JoinRoom(room.to_string()));
                         }
                     }
-                    "login" => {
-                        if let Some(user_name) = splitted.get(1) {
+                    "create" => {
+                        if state.status != ClientStatus::Idle {
+                            println!("此时无法创建房间。");
+                        } else {
+                            match (splitted.get(1), RoomConfig::from_args(&splitted[splitted.len().min(2)..])) {
+                                (Some(room), Some(config)) => net.send_message(C2SMessage::CreateRoom(room.to_string(), config)),
+                                _ => println!("用法：create 房间名 [不洗牌] [炸弹封顶N] [底分N] [封顶N]"),
+                            }
+                        }
+                    }
+                    "history" => {
+                        match splitted.get(1).and_then(|x| x.trim_start_matches('#').parse::<u64>().ok()) {
+                            Some(id) => net.send_message(C2SMessage::QueryMatch(id)),
+                            None => println!("用法：history [对局编号]"),
+                        }
+                    }
+                    "login" | "register" => {
+                        if let (Some(user_name), Some(password)) = (splitted.get(1), splitted.get(2)) {
                             state.user_name = user_name.to_string();
-                            net.send_message(C2SMessage::Login(user_name.to_string()));
+                            if splitted[0] == "login" {
+                                net.send_message(C2SMessage::Login(user_name.to_string(), password.to_string()));
+                            } else {
+                                net.send_message(C2SMessage::Register(user_name.to_string(), password.to_string()));
+                            }
                         } else {
-                            println!("Please input a username");
+                            println!("用法：{} 用户名 密码", splitted[0]);
                         }
                     }
                     _ => {
@@ -254,6 +296,7 @@ fn handle_input(
 }
 
 fn handle_incoming_messages(
+    net: Res<NetworkClient>,
     mut state: ResMut<ClientState>,
     mut new_messages: EventReader<NetworkData<S2CMessage>>,
 ) {
@@ -269,11 +312,16 @@ fn handle_incoming_messages(
                 let duration = recv_time.duration_since(send_time).expect("Time went backwards");
                 println!("砰！延迟为 {}", humantime::format_duration(duration));
             }
-            S2CMessage::RoomJoined(room) => {
+            S2CMessage::RoomJoined(room, _) => {
                 println!("加入房间：{}", room);
                 if state.status == Idle {
                     state.status = ClientStatus::WaitingForPlayers(room.to_string());
                 }
+                // 服务器已经公布承诺，提交参与洗牌的随机数
+                net.send_message(C2SMessage::ClientSeed(thread_rng().gen()));
+            }
+            S2CMessage::SeedCommitted(_) => {
+                net.send_message(C2SMessage::ClientSeed(thread_rng().gen()));
             }
             S2CMessage::RoomList(lobbies) => {
                 for name in lobbies.iter() {
@@ -283,7 +331,7 @@ fn handle_incoming_messages(
             S2CMessage::LandlordMove(landlord) => {
                 let user_name = &state.user_name;
                 if user_name == landlord {
-                    println!("{} 你是否叫地主？", landlord);
+                    println!("{} 你是否叫地主？输入 提示 查看建议", landlord);
                 } else {
                     println!("等待 {} 叫地主", landlord);
                 }
@@ -302,6 +350,9 @@ fn handle_incoming_messages(
                     print_cards(&cards_mut);
                 }
             }
+            S2CMessage::LastCards(player, remaining) => {
+                println!("!!! {} !!!", format_message(state.locale, "game.last_cards", &[player.to_string(), remaining.to_string()]));
+            }
             S2CMessage::Move(player) => {
                 println!("{} 请出牌", player);
             }
@@ -319,44 +370,24 @@ fn handle_incoming_messages(
                 state.status = Gaming;
             }
             S2CMessage::RoomErr(err) => {
-                match err {
-                    RoomError::NotReady => {
-                        println!("房间未准备好！");
-                    }
-                    RoomError::NotStarted => {
-                        println!("游戏还未开始！");
-                    }
-                    RoomError::NotLandlordPlayer => {
-                        println!("不是你叫地主！");
-                    }
-                    RoomError::RoomFull => {
-                        println!("Room is full");
-                    }
-                }
+                println!("{}", err.localize(state.locale));
+            }
+            S2CMessage::LobbyErr(err) => {
+                println!("{}", err.localize(state.locale));
             }
             S2CMessage::GameErr(err) => {
                 match err {
-                    GameError::NotYourTurn => {
-                        println!("你还不能出牌！");
-                    }
-                    GameError::NoSuchCards => {
-                        println!("你没有这些牌");
-                    }
-                    GameError::WrongRule => {
-                        println!("你出的牌不满足当前规则");
-                    }
-                    GameError::NoRule => {
-                        println!("你出的牌不匹配任何规则")
-                    }
-                    GameError::Win(player, player_type, score) => {
-                        println!("{} 赢了。", player);
+                    GameError::Win(_, _, _) | GameError::Escaped(_, _) => {
+                        println!("{}", err.localize(state.locale));
                         state.status = Idle;
                         state.landlord_name = String::default();
                         state.cards.clear();
 
                         println!("现在你可以输入 再来一局|摸了 来进行重新比赛投票，也可以安全地离开房间。");
                     }
-                    _ => {}
+                    _ => {
+                        println!("{}", err.localize(state.locale));
+                    }
                 }
             }
             S2CMessage::RematchVote(player, rematch, count) => {
@@ -369,22 +400,83 @@ fn handle_incoming_messages(
                     state.cards.clear();
                 }
             }
-            S2CMessage::GameStarted(cards, landlord) => {
+            S2CMessage::SurrenderVote(player, surrender, count) => {
+                if *surrender {
+                    println!("{} 同意投降。({}/2)，输入 投降|不投降 来投票", player, count);
+                } else {
+                    println!("{} 不同意投降，继续游戏。", player);
+                }
+            }
+            S2CMessage::GameStarted(cards, landlord, commitment) => {
                 let user_name = &state.user_name;
                 print!("你的手牌: ");
                 print_cards(&cards);
 
                 print!("游戏开始，");
                 if user_name == landlord {
-                    println!("{} 你是否叫地主？", landlord);
+                    println!("{} 你是否叫地主？输入 提示 查看建议", landlord);
                 } else {
                     println!("等待 {} 叫地主", landlord);
                 }
 
                 state.cards = cards.clone();
+                state.dealt_cards = cards.clone();
+                state.deal_commitment = commitment.to_string();
                 state.landlord_name = landlord.to_string();
                 state.status = WaitingForLandlord;
             }
+            S2CMessage::HandShown(player, cards, multiplier) => {
+                if cards.is_empty() {
+                    println!("{} 明牌开始，当前倍率 {}", player, multiplier);
+                } else {
+                    println!("{} 明牌：{}，当前倍率 {}", player, cards_to_string(cards), multiplier);
+                }
+            }
+            S2CMessage::PlayerDisconnected(player, grace) => {
+                println!("{} 断线了，等待重连 {}s，超时按逃跑结算", player, grace.as_secs());
+            }
+            S2CMessage::PlayerReconnected(player) => {
+                println!("{} 重新连接，游戏继续", player);
+            }
+            S2CMessage::Resync(snapshot, commitment) => {
+                println!("已重新连接到牌局");
+                for (seat, player) in snapshot.players.iter().enumerate() {
+                    match &snapshot.shown[seat] {
+                        Some(cards) => println!("{}：剩余 {} 张，明牌 {}", player, snapshot.cards_left[seat], cards_to_string(cards)),
+                        None => println!("{}：剩余 {} 张", player, snapshot.cards_left[seat]),
+                    }
+                }
+                let landlord = snapshot.players[snapshot.landlord].clone();
+                if snapshot.state == GameState::Running {
+                    println!("地主为 {}，地主牌为 {}，当前倍率 {}", landlord, cards_to_string(&snapshot.landlord_cards), snapshot.multiplier);
+                    if let Some((seat, cards)) = &snapshot.last_play {
+                        println!("{} 出牌：{}", snapshot.players[*seat], cards_to_string(cards));
+                    }
+                    println!("{} 请出牌", snapshot.players[snapshot.current]);
+                    state.status = Gaming;
+                } else {
+                    println!("等待 {} 叫地主，当前倍率 {}", landlord, snapshot.multiplier);
+                    state.status = WaitingForLandlord;
+                }
+                print!("你的手牌：");
+                print_cards(&snapshot.cards);
+
+                // 重启客户端后没有发到的手牌，无法验证这局的洗牌
+                if &state.deal_commitment != commitment {
+                    state.deal_commitment.clear();
+                    state.dealt_cards.clear();
+                }
+                state.cards = snapshot.cards.clone();
+                state.landlord_name = landlord;
+            }
+            S2CMessage::SeedRevealed(reveal) => {
+                if !state.deal_commitment.is_empty() {
+                    match reveal.verify(&state.deal_commitment, &state.user_name, &state.dealt_cards) {
+                        Ok(()) => println!("{}", format_message(state.locale, "fair.verified", &[])),
+                        Err(err) => println!("{}", err.localize(state.locale)),
+                    }
+                }
+            }
             S2CMessage::MatchmakeStatus(count, expected_time, remaining_time) => {
                 if remaining_time.is_zero() {
                     println!("无法匹配到玩家，请重试。");
@@ -393,10 +485,32 @@ fn handle_incoming_messages(
                     println!("当前匹配队列共有 {} 位玩家，剩余匹配时间：{}s", count, remaining_time.as_secs());
                 }
             }
-            S2CMessage::LoggedIn => {
+            S2CMessage::History(matches) => {
+                if matches.is_empty() {
+                    println!("还没有对局记录");
+                }
+                for stats in matches.iter() {
+                    print_match_summary(stats, &state.user_name);
+                }
+            }
+            S2CMessage::MatchRecord(id, record) => {
+                match record {
+                    Some(stats) => print_match_record(stats),
+                    None => println!("找不到对局 #{}", id),
+                }
+            }
+            S2CMessage::LoggedIn(token) => {
                 state.status = Idle;
+                state.session_token = Some(token.clone());
                 println!("Logged in!");
             }
+            S2CMessage::AuthErr(err) => {
+                println!("{}", err.localize(state.locale));
+                if matches!(err, AuthError::InvalidToken | AuthError::SessionTakenOver) {
+                    state.status = NotLoggedIn;
+                    state.session_token = None;
+                }
+            }
             _ => {
                 println!("Other message");
             }
@@ -404,6 +518,57 @@ fn handle_incoming_messages(
     }
 }
 
+/// 对局列表中的一行：编号、时间、身份、胜负与积分变化
+fn print_match_summary(stats: &MatchStats, user: &str) {
+    let seat = match stats.seat_of(user) {
+        Some(seat) => seat,
+        None => return,
+    };
+    let time = humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(stats.ended_at));
+    let role = match stats.landlord {
+        Some(landlord) if landlord == seat => "地主",
+        Some(_) => "农民",
+        None => "未定",
+    };
+    let result = match stats.escaped {
+        Some(escaped) if escaped == seat => "逃跑",
+        _ if stats.is_winner(seat) => "胜",
+        _ => "负",
+    };
+    let others: Vec<&str> = stats.players.iter().filter(|x| *x != user).map(|x| x.as_str()).collect();
+    println!("#{} {} {} {} {:+} 倍率 {} 对手 {}", stats.id, time, role, result, stats.payouts[seat], stats.multiplier.total, others.join("、"));
+}
+
+/// 一局的完整记录：发牌、叫地主、每一步出牌与结算
+fn print_match_record(stats: &MatchStats) {
+    println!("对局 #{}", stats.id);
+    let history = stats.history.as_ref();
+    for (seat, player) in stats.players.iter().enumerate() {
+        let role = match stats.landlord {
+            Some(landlord) if landlord == seat => "地主",
+            Some(_) => "农民",
+            None => "未定",
+        };
+        match history {
+            Some(history) => println!("{}（{}）{:+}：{}", player, role, stats.payouts[seat], cards_to_string(&history.hands[seat])),
+            None => println!("{}（{}）{:+}", player, role, stats.payouts[seat]),
+        }
+    }
+    if let Some(history) = history {
+        println!("地主牌：{}", cards_to_string(&history.landlord_cards));
+        for (seat, action) in history.actions.iter() {
+            match action {
+                Action::Play(cards) => println!("{}：{}", stats.players[*seat], cards_to_string(cards)),
+                _ => println!("{}：{}", stats.players[*seat], action),
+            }
+        }
+    }
+    if let Some(seat) = stats.escaped {
+        println!("{} 逃跑了", stats.players[seat]);
+    }
+    println!("倍率 {}，炸弹 {}，火箭 {}", stats.multiplier.total, stats.multiplier.bombs, stats.multiplier.rockets);
+}
+
 fn print_cards(cards: &[Card]) {
     println!("{}", cards_to_string(cards));
 }
@@ -421,12 +586,17 @@ fn cards_to_string(cards: &[Card]) -> String {
 fn handle_network_events(
     mut new_network_events: EventReader<ClientNetworkEvent>,
     net: Res<NetworkClient>,
+    state: Res<ClientState>,
 ) {
     for event in new_network_events.iter() {
         match event {
             ClientNetworkEvent::Connected => {
                 println!("Connected to server");
                 net.send_message(C2SMessage::Ping);
+                // 重新连接时用会话令牌登入
+                if let Some(token) = state.session_token.as_ref() {
+                    net.send_message(C2SMessage::Resume(token.clone()));
+                }
             }
 
             ClientNetworkEvent::Disconnected => {
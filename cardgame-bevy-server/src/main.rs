@@ -1,19 +1,28 @@
 use bevy::prelude::*;
-use cardgame::{Game, GameState, Lobby, Room, RoomState};
+use cardgame::{open_store, store_config_from_env, Game, GameState, Lobby, Room, RoomConfig, RoomState};
 use cardgame_common::message::{C2SMessage, S2CMessage};
-use bevy::app::ScheduleRunnerSettings;
+use bevy::app::{AppExit, ScheduleRunnerSettings};
 use std::time::Duration;
 use bevy_spicy_networking::{AppNetworkServerMessage, ConnectionId, NetworkData, NetworkServer, ServerNetworkEvent, ServerPlugin};
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use bevy::log::LogPlugin;
-use cardgame::error::{GameError, LobbyError, RoomError};
-use cardgame::user::{User, UserState};
+use cardgame::error::{AuthError, GameError, LobbyError, RoomError};
+use cardgame::user::{legacy_claims_from_env, User, UserId, UserManager, UserState, FLUSH_INTERVAL};
 use crate::lobby::ServerLobby;
 use crate::server_network::MessageTarget;
 
 /// 出卡计时器
 struct SubmitTimer(Timer);
 
+/// 定期保存玩家数据的计时器
+struct FlushTimer(Timer);
+
+/// 控制台输入 stop 后置位，关闭服务器
+#[derive(Default)]
+struct ShutdownFlag(Arc<AtomicBool>);
+
 fn setup_network_system(mut net: ResMut<NetworkServer>) {
     let ip_address = "127.0.0.1".parse().expect("Could not parse ip address");
 
@@ -33,7 +42,74 @@ fn setup_network_system(mut net: ResMut<NetworkServer>) {
 }
 
 fn setup_lobby(mut commands: Commands) {
-    commands.insert_resource(ServerLobby::default());
+    // 命令行参数为大厅的房间设置，例如：底分50 封顶64
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|x| x.as_str()).collect();
+    let mut lobby = ServerLobby::default();
+    lobby.room_config = RoomConfig::from_args(&args).expect("无法识别的房间设置");
+    commands.insert_resource(lobby);
+    // 环境变量 CARDGAME_STORE 选择玩家数据的存储方式，例如：sqlite:users.db
+    let store = open_store(&store_config_from_env()).expect("无法打开玩家数据存储");
+    let mut user_manager = UserManager::with_store(store);
+    // 环境变量 CARDGAME_CLAIM_LEGACY=1 时允许认领没有密码的老用户，只在迁移期间使用
+    user_manager.allow_legacy_claims(legacy_claims_from_env());
+    commands.insert_resource(user_manager);
+}
+
+/// 结算已结束的游戏并写入文件
+fn stats_system(lobby: Res<ServerLobby>, mut user_manager: ResMut<UserManager>) {
+    for stats in lobby.take_stats() {
+        if let Err(err) = user_manager.record_match(&stats) {
+            error!("无法保存对局统计: {}", err);
+        }
+    }
+}
+
+/// 定期保存修改过的玩家数据，关闭服务器时由 `shutdown_system` 保存剩余的修改
+fn flush_system(time: Res<Time>, mut timer: ResMut<FlushTimer>, mut user_manager: ResMut<UserManager>) {
+    if timer.0.tick(time.delta()).just_finished() && user_manager.is_dirty() {
+        match user_manager.flush() {
+            Ok(count) => info!("保存了 {} 位玩家的数据", count),
+            Err(err) => error!("无法保存玩家数据: {}", err),
+        }
+    }
+}
+
+/// 在控制台输入 stop 关闭服务器
+fn setup_console(flag: Res<ShutdownFlag>) {
+    let flag = flag.0.clone();
+    std::thread::spawn(move || {
+        let mut input = String::new();
+        while let Ok(n) = std::io::stdin().read_line(&mut input) {
+            if n == 0 {
+                break;
+            }
+            if input.trim() == "stop" {
+                flag.store(true, Ordering::SeqCst);
+                break;
+            }
+            input.clear();
+        }
+    });
+}
+
+/// 保存剩余的玩家数据后关闭服务器
+fn shutdown_system(flag: Res<ShutdownFlag>, mut user_manager: ResMut<UserManager>, mut exit: EventWriter<AppExit>) {
+    if flag.0.swap(false, Ordering::SeqCst) {
+        match user_manager.flush() {
+            Ok(count) => info!("保存了 {} 位玩家的数据", count),
+            Err(err) => error!("无法保存玩家数据: {}", err),
+        }
+        info!("服务器已关闭");
+        exit.send(AppExit);
+    }
+}
+
+/// 断线超过宽限时间的玩家按逃跑结算
+fn escape_system(net: Res<NetworkServer>, mut lobby: ResMut<ServerLobby>) {
+    if !lobby.disconnected.is_empty() {
+        lobby.check_escapes(&net);
+    }
 }
 
 fn games_system(query: Query<&Game>) {
@@ -62,7 +138,7 @@ fn handle_connection_events(
             }
             ServerNetworkEvent::Disconnected(connection_id) => {
                 println!("{} Disconnected", connection_id.address());
-                lobby.disconnect_by_endpoint(&connection_id);
+                lobby.disconnect_by_endpoint(&net, &connection_id);
             }
             ServerNetworkEvent::Error(err) => {
                 error!("{:?}", err);
@@ -75,19 +151,34 @@ fn handle_connection_events(
 fn handle_messages(
     mut new_messages: EventReader<NetworkData<C2SMessage>>,
     mut lobby: ResMut<ServerLobby>,
+    mut user_manager: ResMut<UserManager>,
     net: Res<NetworkServer>,
 ) {
     for message in new_messages.iter() {
         let user: ConnectionId = message.source();
         let user_id = lobby.get_user(&user).map(|x| x.clone());
+
+        // 登入之前只能登入、注册或断线重连
+        let public = matches!(**message, C2SMessage::Ping | C2SMessage::Ping2 | C2SMessage::Login(..) | C2SMessage::Register(..) | C2SMessage::Resume(..));
+        if !public && user_id.is_none() {
+            net.send_message(user, S2CMessage::AuthErr(AuthError::NotLoggedIn));
+            continue;
+        }
         let err = match &**message {
             C2SMessage::Ping => {
                 net.send_message(user, S2CMessage::Pong);
             }
-            C2SMessage::Login(username) => {
-                println!("玩家 {} 登入", username);
-                lobby.connect(username.to_string(), user);
-                net.send_message(user, S2CMessage::LoggedIn);
+            C2SMessage::Login(username, password) => {
+                let result = user_manager.login(username, password).map(|token| (username.clone(), token));
+                login(&net, &mut lobby, user, result);
+            }
+            C2SMessage::Register(username, password) => {
+                let result = user_manager.register(username, password).map(|token| (username.clone(), token));
+                login(&net, &mut lobby, user, result);
+            }
+            C2SMessage::Resume(token) => {
+                let result = user_manager.authenticate(token).map(|username| (username, token.clone()));
+                login(&net, &mut lobby, user, result);
             }
             // 加入房间
             C2SMessage::JoinRoom(room_name) => {
@@ -98,6 +189,14 @@ fn handle_messages(
                     }
                 }
             }
+            C2SMessage::CreateRoom(room_name, config) => {
+                match lobby.create_room(room_name, config.clone()).and_then(|_| lobby.join_room(&net, room_name, user_id.unwrap().to_string())) {
+                    Ok(_) => {}
+                    Err(e) => {
+                        net.send_message(user, S2CMessage::LobbyErr(e));
+                    }
+                }
+            }
             C2SMessage::StartGame(room_name) => {
                 match lobby.start_game_by_name(&net, room_name) {
                     Ok(_) => {}
@@ -119,7 +218,7 @@ fn handle_messages(
                     } else if &room.game.current_player().user != &user_id.unwrap() {
                         net.send_message(user, S2CMessage::GameErr(GameError::NotYourTurn));
                     } else {
-                        match room.game.pass() {
+                        match room.pass() {
                             Ok(next_player) => {
                                 let room_name = room.name.clone();
                                 lobby.send_to_room_by_name(&net, &room_name, S2CMessage::Move(next_player.clone()));
@@ -151,12 +250,41 @@ fn handle_messages(
             C2SMessage::SubmitCards(cards) => {
                 match lobby.submit_cards(&user, cards.clone()) {
                     Ok(next_player) => {
-                        let room = lobby.get_room_by_user(user_id.as_ref().unwrap()).unwrap();
-                        lobby.send_to_room_by_name(&net, &room.name, S2CMessage::CardsSubmitted(user_id.unwrap().to_string(), cards.clone()));
+                        let user_id = user_id.unwrap();
+                        let room = lobby.get_room_by_user(&user_id).unwrap();
+                        lobby.send_to_room_by_name(&net, &room.name, S2CMessage::CardsSubmitted(user_id.to_string(), cards.clone()));
                         lobby.send_to_room_by_name(&net, &room.name, S2CMessage::Move(next_player.clone()));
+
+                        // 报单、报双
+                        let seat = room.users.iter().position(|x| *x == user_id).unwrap();
+                        if let Some(remaining) = room.game.last_cards(seat) {
+                            for player in room.users.iter() {
+                                lobby.send_message(&net, player, S2CMessage::LastCards(user_id.clone(), remaining));
+                            }
+                        }
                     }
                     Err(e) => {
-                        net.send_message(user, S2CMessage::GameErr(e));
+                        let win = matches!(e, GameError::Win(..));
+                        match lobby.get_room_by_connection_id_mut(&user).filter(|_| win) {
+                            // 这局结束，把最后一手牌与结算发给房间内所有人，等待重新比赛投票
+                            Some(room) => {
+                                room.state = RoomState::WaitingForRematch(0);
+                                let winner = user_id.clone().unwrap();
+                                for player in room.users.clone() {
+                                    lobby.send_message(&net, &player, S2CMessage::CardsSubmitted(winner.clone(), cards.clone()));
+                                    lobby.send_message(&net, &player, S2CMessage::GameErr(e.clone()));
+                                }
+                            }
+                            None => net.send_message(user, S2CMessage::GameErr(e)),
+                        }
+
+                        // 公开洗牌种子
+                        let room_name = lobby.get_room_by_connection_id(&user).map(|x| x.name.clone());
+                        if let Some(reveal) = room_name.filter(|_| win).and_then(|x| lobby.reveal_seed(&net, &x)) {
+                            for player in reveal.players.iter() {
+                                lobby.send_message(&net, player, S2CMessage::SeedRevealed(reveal.clone()));
+                            }
+                        }
                     }
                 }
             }
@@ -170,6 +298,43 @@ fn handle_messages(
                 let data = lobby.rooms.keys().map(|x| x.to_string()).collect();
                 net.send_message(user, S2CMessage::RoomList(data));
             }
+            C2SMessage::ClientSeed(seed) => {
+                if let Some(user_id) = user_id.as_ref() {
+                    if let Err(e) = lobby.client_seed(user_id, *seed) {
+                        println!("{} 的随机数未被接受：{}", user_id, e);
+                    }
+                }
+            }
+            C2SMessage::SurrenderVote(vote) => {
+                if let Some(user_id) = user_id.as_ref() {
+                    if let Err(e) = lobby.surrender_vote(&net, user_id, *vote) {
+                        net.send_message(user, S2CMessage::RoomErr(e));
+                    }
+                }
+            }
+            C2SMessage::ShowHand => {
+                if let Some(user_id) = user_id.as_ref() {
+                    if let Err(e) = lobby.show_hand(&net, user_id) {
+                        net.send_message(user, S2CMessage::GameErr(e));
+                    }
+                }
+            }
+            C2SMessage::QueryHistory => {
+                if let Some(user_id) = user_id.as_ref() {
+                    let matches = user_manager.recent_matches(user_id).unwrap_or_else(|err| {
+                        error!("无法读取对局记录: {}", err);
+                        vec![]
+                    });
+                    net.send_message(user, S2CMessage::History(matches));
+                }
+            }
+            C2SMessage::QueryMatch(id) => {
+                let record = user_manager.find_match(*id).unwrap_or_else(|err| {
+                    error!("无法读取对局记录: {}", err);
+                    None
+                });
+                net.send_message(user, S2CMessage::MatchRecord(*id, record.map(Box::new)));
+            }
             _ => {
                 println!("Unknown message")
             }
@@ -177,6 +342,20 @@ fn handle_messages(
     }
 }
 
+/// 登入成功时把连接与玩家关联，并发回会话令牌
+fn login(net: &NetworkServer, lobby: &mut ServerLobby, connection_id: ConnectionId, result: Result<(UserId, String), AuthError>) {
+    match result {
+        Ok((username, token)) => {
+            println!("玩家 {} 登入", username);
+            net.send_message(connection_id, S2CMessage::LoggedIn(token));
+            lobby.connect(net, username, connection_id);
+        }
+        Err(e) => {
+            net.send_message(connection_id, S2CMessage::AuthErr(e));
+        }
+    }
+}
+
 fn join_room(net: Res<NetworkServer>, room_name: &str, connection_id: &ConnectionId, lobby: &mut ServerLobby) {
     let user_id = lobby.get_user(connection_id).expect("Unknown user").clone();
 
@@ -211,14 +390,21 @@ fn main() {
         .insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_millis(
             50 // 20 tps
         )))
-        .insert_resource(SubmitTimer(Timer::from_seconds(2.0, true)));
+        .insert_resource(SubmitTimer(Timer::from_seconds(2.0, true)))
+        .insert_resource(FlushTimer(Timer::new(FLUSH_INTERVAL, true)))
+        .insert_resource(ShutdownFlag::default());
     register_messages(&mut builder);
     builder.add_startup_system(setup_lobby.system())
         .add_startup_system(setup_network_system.system())
+        .add_startup_system(setup_console.system())
         .add_system(games_system.system())
         .add_system(submit_timer_system.system())
         .add_system(handle_connection_events.system())
-        .add_system(handle_messages.system());
+        .add_system(handle_messages.system())
+        .add_system(escape_system.system())
+        .add_system(stats_system.system())
+        .add_system(flush_system.system())
+        .add_system(shutdown_system.system());
 
     builder.run();
 }
@@ -30,7 +30,8 @@ impl NetworkManager {
     }
 
     pub fn send_to_user(&self, net: &NetworkServer, user_id: &str, message: S2CMessage) -> Result<()> {
-        let endpoint = *self.user_map.get_by_left(user_id).expect("No such client found");
+        // 断线的玩家仍然留在房间里，跳过他们
+        let endpoint = *self.user_map.get_by_left(user_id).ok_or_else(|| Error::msg("No such client found"))?;
         net.send_message(endpoint, message).map_err(Error::from)
     }
 
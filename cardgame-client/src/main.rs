@@ -9,9 +9,12 @@ use message_io::node::*;
 use std::sync::mpsc::*;
 use std::thread::JoinHandle;
 use std::sync::{Arc, Mutex};
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::process::exit;
-use cardgame::error::{GameError, RoomError};
+use cardgame::error::{AuthError, GameError};
+
+/// 保存用户名与会话令牌的文件，客户端重启后用于断线重连
+const SESSION_FILE: &str = ".cardgame_session";
 
 // 共享状态
 type SharedStates = (Arc<Mutex<String>>, Arc<Mutex<ClientState>>, Arc<Mutex<Vec<Card>>>, Arc<Mutex<String>>, Arc<Mutex<SystemTime>>);
@@ -41,7 +44,7 @@ enum ClientState {
 
 
 fn run_network_thread(
-    _server_id: Endpoint,
+    server_id: Endpoint,
     handler: NodeHandler<Signal>,
     listener: NodeListener<Signal>,
     sender: Sender<S2CMessage>,
@@ -49,6 +52,9 @@ fn run_network_thread(
 ) -> JoinHandle<()> {
     std::thread::spawn(move || {
         let (user_name, client_state, cards_mutex, landlord_name, last_packet_time) = mutexs;
+        let locale = Locale::from_env();
+        // 本局的洗牌种子承诺与发到的手牌，公开种子后用于验证
+        let mut deal: Option<(String, Vec<Card>)> = None;
         listener.for_each(move |event| match event {
             NodeEvent::Signal(signal) => match signal {
                 cardgame::Signal::Greet => {
@@ -73,15 +79,22 @@ fn run_network_thread(
                             let duration = recv_time.duration_since(send_time).expect("Time went backwards");
                             println!("砰！延迟为 {}", humantime::format_duration(duration));
                         }
-                        S2CMessage::RoomJoined(room) => {
+                        S2CMessage::RoomJoined(room, _) => {
                             let mut state = client_state.lock().unwrap();
                             println!("加入房间：{}", room);
                             *state = ClientState::WaitingForPlayers(room);
+                            // 服务器已经公布承诺，提交参与洗牌的随机数
+                            let data = bincode::serialize(&C2SMessage::ClientSeed(thread_rng().gen())).unwrap();
+                            handler.network().send(server_id, &data);
+                        }
+                        S2CMessage::SeedCommitted(_) => {
+                            let data = bincode::serialize(&C2SMessage::ClientSeed(thread_rng().gen())).unwrap();
+                            handler.network().send(server_id, &data);
                         }
                         S2CMessage::LandlordMove(landlord) => {
                             let user_name = &*user_name.lock().unwrap();
                             if user_name == &landlord {
-                                println!("{} 你是否叫地主？", landlord);
+                                println!("{} 你是否叫地主？输入 提示 查看建议", landlord);
                             } else {
                                 println!("等待 {} 叫地主", landlord);
                             }
@@ -100,6 +113,9 @@ fn run_network_thread(
                                 print_cards(&cards_mut);
                             }
                         }
+                        S2CMessage::LastCards(player, remaining) => {
+                            println!("!!! {} !!!", format_message(locale, "game.last_cards", &[player, remaining.to_string()]));
+                        }
                         S2CMessage::Move(player) => {
                             println!("{} 请出牌", player);
                         }
@@ -117,45 +133,24 @@ fn run_network_thread(
                             *client_state.lock().unwrap() = ClientState::Gaming;
                         }
                         S2CMessage::RoomErr(err) => {
-                            match err {
-                                RoomError::NotReady => {
-                                    println!("房间未准备好！");
-                                }
-                                RoomError::NotStarted => {
-                                    println!("游戏还未开始！");
-                                }
-                                RoomError::NotLandlordPlayer => {
-                                    println!("不是你叫地主！");
-                                }
-                                RoomError::RoomFull => {
-                                    println!("房间已满！");
-                                }
-                                _ => {}
-                            }
+                            println!("{}", err.localize(locale));
+                        }
+                        S2CMessage::LobbyErr(err) => {
+                            println!("{}", err.localize(locale));
                         }
                         S2CMessage::GameErr(err) => {
                             match err {
-                                GameError::NotYourTurn => {
-                                    println!("你还不能出牌！");
-                                }
-                                GameError::NoSuchCards => {
-                                    println!("你没有这些牌");
-                                }
-                                GameError::WrongRule => {
-                                    println!("你出的牌不满足当前规则");
-                                }
-                                GameError::NoRule => {
-                                    println!("你出的牌不匹配任何规则")
-                                }
-                                GameError::Win(player, player_type, score) => {
-                                    println!("{} 赢了。", player);
+                                GameError::Win(_, _, _) | GameError::Escaped(_, _) => {
+                                    println!("{}", err.localize(locale));
                                     *client_state.lock().unwrap() = ClientState::Idle;
                                     *landlord_name.lock().unwrap() = String::new();
                                     cards_mutex.lock().unwrap().clear();
 
                                     println!("现在你可以输入 再来一局|摸了 来进行重新比赛投票，也可以安全地离开房间。");
                                 }
-                                _ => {}
+                                _ => {
+                                    println!("{}", err.localize(locale));
+                                }
                             }
                         }
                         S2CMessage::RematchVote(player, rematch, count) => {
@@ -167,22 +162,101 @@ fn run_network_thread(
                                 cards_mutex.lock().unwrap().clear();
                             }
                         }
-                        S2CMessage::GameStarted(cards, landlord) => {
+                        S2CMessage::SurrenderVote(player, surrender, count) => {
+                            if surrender {
+                                println!("{} 同意投降。({}/2)，输入 投降|不投降 来投票", player, count);
+                            } else {
+                                println!("{} 不同意投降，继续游戏。", player);
+                            }
+                        }
+                        S2CMessage::GameStarted(cards, landlord, commitment) => {
                             let user_name = &*user_name.lock().unwrap();
                             print!("你的手牌: ");
                             print_cards(&cards);
 
                             print!("游戏开始，");
                             if user_name == &landlord {
-                                println!("{} 你是否叫地主？", landlord);
+                                println!("{} 你是否叫地主？输入 提示 查看建议", landlord);
                             } else {
                                 println!("等待 {} 叫地主", landlord);
                             }
 
+                            deal = Some((commitment, cards.clone()));
                             *cards_mutex.lock().unwrap() = cards;
                             *landlord_name.lock().unwrap() = landlord;
                             *client_state.lock().unwrap() = ClientState::WaitingForLandlord;
                         }
+                        S2CMessage::HandShown(player, cards, multiplier) => {
+                            if cards.is_empty() {
+                                println!("{} 明牌开始，当前倍率 {}", player, multiplier);
+                            } else {
+                                println!("{} 明牌：{}，当前倍率 {}", player, cards_to_string(&cards), multiplier);
+                            }
+                        }
+                        S2CMessage::AuthErr(AuthError::SessionTakenOver) => {
+                            println!("{}", AuthError::SessionTakenOver.localize(locale));
+                            handler.stop();
+                            exit(0);
+                        }
+                        S2CMessage::PlayerDisconnected(player, grace) => {
+                            println!("{} 断线了，等待重连 {}s，超时按逃跑结算", player, grace.as_secs());
+                        }
+                        S2CMessage::PlayerReconnected(player) => {
+                            println!("{} 重新连接，游戏继续", player);
+                        }
+                        S2CMessage::Resync(snapshot, commitment) => {
+                            println!("已重新连接到牌局");
+                            for (seat, player) in snapshot.players.iter().enumerate() {
+                                match &snapshot.shown[seat] {
+                                    Some(cards) => println!("{}：剩余 {} 张，明牌 {}", player, snapshot.cards_left[seat], cards_to_string(cards)),
+                                    None => println!("{}：剩余 {} 张", player, snapshot.cards_left[seat]),
+                                }
+                            }
+                            let landlord = snapshot.players[snapshot.landlord].clone();
+                            if snapshot.state == GameState::Running {
+                                println!("地主为 {}，地主牌为 {}，当前倍率 {}", landlord, cards_to_string(&snapshot.landlord_cards), snapshot.multiplier);
+                                if let Some((seat, cards)) = &snapshot.last_play {
+                                    println!("{} 出牌：{}", snapshot.players[*seat], cards_to_string(cards));
+                                }
+                                println!("{} 请出牌", snapshot.players[snapshot.current]);
+                                *client_state.lock().unwrap() = ClientState::Gaming;
+                            } else {
+                                println!("等待 {} 叫地主，当前倍率 {}", landlord, snapshot.multiplier);
+                                *client_state.lock().unwrap() = ClientState::WaitingForLandlord;
+                            }
+                            print!("你的手牌：");
+                            print_cards(&snapshot.cards);
+
+                            // 重启客户端后没有发到的手牌，无法验证这局的洗牌
+                            if deal.as_ref().map_or(false, |(x, _)| x != &commitment) {
+                                deal = None;
+                            }
+                            *cards_mutex.lock().unwrap() = snapshot.cards;
+                            *landlord_name.lock().unwrap() = landlord;
+                        }
+                        S2CMessage::SeedRevealed(reveal) => {
+                            if let Some((commitment, cards)) = deal.take() {
+                                match reveal.verify(&commitment, &user_name.lock().unwrap(), &cards) {
+                                    Ok(()) => println!("{}", format_message(locale, "fair.verified", &[])),
+                                    Err(err) => println!("{}", err.localize(locale)),
+                                }
+                            }
+                        }
+                        S2CMessage::History(matches) => {
+                            if matches.is_empty() {
+                                println!("还没有对局记录");
+                            }
+                            let user = user_name.lock().unwrap().clone();
+                            for stats in matches.iter() {
+                                print_match_summary(stats, &user);
+                            }
+                        }
+                        S2CMessage::MatchRecord(id, record) => {
+                            match record {
+                                Some(stats) => print_match_record(&stats),
+                                None => println!("找不到对局 #{}", id),
+                            }
+                        }
                         S2CMessage::MatchmakeStatus(count, expected_time, remaining_time) => {
                             if remaining_time.is_zero() {
                                 println!("无法匹配到玩家，请重试。");
@@ -230,32 +304,52 @@ fn run_console_thread(
 ) -> JoinHandle<()> {
     std::thread::spawn(move || {
         let (user_name, client_state, cards_mutex, landlord_name, last_packet_time) = mutexs;
+        let locale = Locale::from_env();
 
         let send_to_server = |msg: &C2SMessage| {
             let data = bincode::serialize(msg).unwrap();
             handler.network().send(server_id, &data);
         };
 
-        let line = read_line("请输入用户名：");
-        let line = line.trim();
-        if line == "exit" {
-            return;
-        }
-        let user = line.to_string();
-        *user_name.lock().unwrap() = user.clone();
-        let data = bincode::serialize(&C2SMessage::Login(user.clone())).unwrap();
-        handler.network().send(server_id, &data);
-        let msg = rx.recv().unwrap();
-        match msg {
-            S2CMessage::LoggedIn => {
-                println!("Logged in!");
-                *client_state.lock().unwrap() = ClientState::Idle;
-            }
-            _ => {
-                println!("Unknown message from server");
-                return;
+        // 上次的会话仍然有效时直接回到游戏
+        let mut session = std::fs::read_to_string(SESSION_FILE)
+            .ok()
+            .and_then(|x| x.split_once('\n').map(|(user, token)| (user.to_string(), token.trim().to_string())));
+        let token = loop {
+            let msg = if let Some((user, token)) = session.take() {
+                *user_name.lock().unwrap() = user;
+                send_to_server(&C2SMessage::Resume(token));
+                rx.recv().unwrap()
+            } else {
+                let line = read_line("请输入用户名：");
+                let line = line.trim();
+                if line == "exit" {
+                    return;
+                }
+                let user = line.to_string();
+                let password = read_line("请输入密码：").trim().to_string();
+                *user_name.lock().unwrap() = user.clone();
+                send_to_server(&C2SMessage::Login(user.clone(), password.clone()));
+                match rx.recv().unwrap() {
+                    S2CMessage::AuthErr(AuthError::UnknownUser) if read_line("用户不存在，输入 注册 用这个密码注册新用户：").trim() == "注册" => {
+                        send_to_server(&C2SMessage::Register(user, password));
+                        rx.recv().unwrap()
+                    }
+                    msg => msg,
+                }
+            };
+            match msg {
+                S2CMessage::LoggedIn(token) => break token,
+                S2CMessage::AuthErr(err) => println!("{}", err.localize(locale)),
+                _ => {
+                    println!("Unknown message from server");
+                    return;
+                }
             }
-        }
+        };
+        println!("Logged in!");
+        let _ = std::fs::write(SESSION_FILE, format!("{}\n{}", user_name.lock().unwrap(), token));
+        *client_state.lock().unwrap() = ClientState::Idle;
         loop {
             let line = read_line("请输入命令：");
             let line = line.trim().to_string();
@@ -272,21 +366,40 @@ fn run_console_thread(
                     let data = bincode::serialize(&C2SMessage::JoinRoom(String::from(room))).unwrap();
                     handler.network().send(server_id, &data);
                 }
+            } else if line.starts_with("创建 ") {
+                let args: Vec<&str> = line.trim_start_matches("创建 ").split_whitespace().collect();
+                if *client_state.lock().unwrap() != ClientState::Idle {
+                    println!("此时无法创建房间。");
+                } else {
+                    match RoomConfig::from_args(&args[1..]) {
+                        Some(config) => send_to_server(&C2SMessage::CreateRoom(args[0].to_string(), config)),
+                        None => println!("用法：创建 房间名 [不洗牌] [炸弹封顶N] [底分N] [封顶N]"),
+                    }
+                }
             } else if line.starts_with("出牌 ") {
                 if *client_state.lock().unwrap() == ClientState::Gaming {
                     let str = line.trim_start_matches("出牌 ");
-                    let cards = parse_input(str);
-                    if let Some(cards) = cards {
-                        let data = bincode::serialize(&C2SMessage::SubmitCards(cards)).unwrap();
-                        handler.network().send(server_id, &data);
-                    } else {
-                        println!("你没有出任何牌！")
+                    match str.parse::<CardList>() {
+                        Ok(cards) if cards.0.is_empty() => println!("你没有出任何牌！"),
+                        Ok(cards) => {
+                            let data = bincode::serialize(&C2SMessage::SubmitCards(cards.0)).unwrap();
+                            handler.network().send(server_id, &data);
+                        }
+                        Err(e) => println!("{}", e.localize(locale)),
                     }
                 } else {
                     println!("你现在还不能出牌！");
                 }
             } else {
                 match line.as_str() {
+                    "明牌" => {
+                        match *client_state.lock().unwrap() {
+                            ClientState::WaitingForPlayers(_) | ClientState::WaitingForLandlord => {
+                                send_to_server(&C2SMessage::ShowHand);
+                            }
+                            _ => println!("只能在发牌前或叫地主时明牌！"),
+                        }
+                    }
                     "叫地主" => {
                         if *client_state.lock().unwrap() != ClientState::WaitingForLandlord {
                             println!("此时还不能叫地主！");
@@ -305,6 +418,28 @@ fn run_console_thread(
                             send_to_server(&C2SMessage::ChooseLandlord(false));
                         }
                     }
+                    "理牌" => {
+                        let cards = cards_mutex.lock().unwrap();
+                        if cards.is_empty() {
+                            println!("你还没有手牌！");
+                        } else {
+                            println!("{}", decompose(&cards));
+                        }
+                    }
+                    "提示" => {
+                        if *client_state.lock().unwrap() != ClientState::WaitingForLandlord {
+                            println!("只有叫地主时才有提示！");
+                        } else {
+                            println!("{}", advise_bid(&cards_mutex.lock().unwrap()).localize(locale));
+                        }
+                    }
+                    "投降" | "不投降" => {
+                        if *client_state.lock().unwrap() == ClientState::Gaming {
+                            send_to_server(&C2SMessage::SurrenderVote(line == "投降"));
+                        } else {
+                            println!("游戏还没有开始！");
+                        }
+                    }
                     "再来一局" => {
                         if client_state.lock().unwrap().clone() == ClientState::WaitingForRematch {
                             let data =
@@ -360,6 +495,15 @@ fn run_console_thread(
                             send_to_server(&C2SMessage::Matchmake);
                         }
                     }
+                    "战绩" | "history" => {
+                        send_to_server(&C2SMessage::QueryHistory);
+                    }
+                    _ if line.starts_with("牌谱 ") || line.starts_with("history ") => {
+                        match line.split_whitespace().nth(1).and_then(|x| x.trim_start_matches('#').parse::<u64>().ok()) {
+                            Some(id) => send_to_server(&C2SMessage::QueryMatch(id)),
+                            None => println!("用法：牌谱 对局编号"),
+                        }
+                    }
                     "游戏列表" => {
                         let data = bincode::serialize(&C2SMessage::QueryRoomList).unwrap();
                         handler.network().send(server_id, &data);
@@ -381,6 +525,57 @@ fn run_console_thread(
     })
 }
 
+/// 对局列表中的一行：编号、时间、身份、胜负与积分变化
+fn print_match_summary(stats: &MatchStats, user: &str) {
+    let seat = match stats.seat_of(user) {
+        Some(seat) => seat,
+        None => return,
+    };
+    let time = humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(stats.ended_at));
+    let role = match stats.landlord {
+        Some(landlord) if landlord == seat => "地主",
+        Some(_) => "农民",
+        None => "未定",
+    };
+    let result = match stats.escaped {
+        Some(escaped) if escaped == seat => "逃跑",
+        _ if stats.is_winner(seat) => "胜",
+        _ => "负",
+    };
+    let others: Vec<&str> = stats.players.iter().filter(|x| *x != user).map(|x| x.as_str()).collect();
+    println!("#{} {} {} {} {:+} 倍率 {} 对手 {}", stats.id, time, role, result, stats.payouts[seat], stats.multiplier.total, others.join("、"));
+}
+
+/// 一局的完整记录：发牌、叫地主、每一步出牌与结算
+fn print_match_record(stats: &MatchStats) {
+    println!("对局 #{}", stats.id);
+    let history = stats.history.as_ref();
+    for (seat, player) in stats.players.iter().enumerate() {
+        let role = match stats.landlord {
+            Some(landlord) if landlord == seat => "地主",
+            Some(_) => "农民",
+            None => "未定",
+        };
+        match history {
+            Some(history) => println!("{}（{}）{:+}：{}", player, role, stats.payouts[seat], cards_to_string(&history.hands[seat])),
+            None => println!("{}（{}）{:+}", player, role, stats.payouts[seat]),
+        }
+    }
+    if let Some(history) = history {
+        println!("地主牌：{}", cards_to_string(&history.landlord_cards));
+        for (seat, action) in history.actions.iter() {
+            match action {
+                Action::Play(cards) => println!("{}：{}", stats.players[*seat], cards_to_string(cards)),
+                _ => println!("{}：{}", stats.players[*seat], action),
+            }
+        }
+    }
+    if let Some(seat) = stats.escaped {
+        println!("{} 逃跑了", stats.players[seat]);
+    }
+    println!("倍率 {}，炸弹 {}，火箭 {}", stats.multiplier.total, stats.multiplier.bombs, stats.multiplier.rockets);
+}
+
 fn print_cards(cards: &[Card]) {
     println!("{}", cards_to_string(cards));
 }
@@ -449,6 +644,13 @@ fn run() {
 }*/
 
 fn main() {
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("puzzle") {
+        let dir = args.next().unwrap_or_else(|| String::from("puzzles"));
+        puzzle::run_puzzle_console(&dir);
+        return;
+    }
+
     let transport = Transport::FramedTcp;
     let remote_addr = "127.0.0.1:3042".to_remote_addr().unwrap();
     let (handler, listener) = node::split();
@@ -486,3 +688,4 @@ fn main() {
 }
 
 pub mod device;
+pub mod puzzle;
@@ -0,0 +1,139 @@
+use cardgame::error::GameError;
+use cardgame::user::UserId;
+use cardgame::*;
+use crate::{cards_to_string, print_cards, read_line};
+
+/// 离线残局模式，无需连接服务器
+pub fn run_puzzle_console(dir: &str) {
+    // 读取时会用求解器验证每个残局，只在启动时读取一次
+    let puzzles = match Puzzle::load_dir(dir) {
+        Ok(puzzles) => puzzles,
+        Err(e) => {
+            println!("无法读取残局目录 {}：{}", dir, e);
+            return;
+        }
+    };
+
+    println!("残局模式：输入 列表 查看所有残局，输入 残局 <编号> 开始挑战，输入 exit 退出。");
+    loop {
+        let line = read_line("请输入命令：");
+        let line = line.trim();
+        if line == "exit" {
+            return;
+        }
+
+        if line == "列表" {
+            for (i, (file, puzzle)) in puzzles.iter().enumerate() {
+                match puzzle {
+                    Ok(puzzle) => println!("{}. {} - {}", i + 1, puzzle.name, puzzle.description),
+                    Err(e) => println!("{}. {}（{}）", i + 1, file, e),
+                }
+            }
+        } else if line.starts_with("残局 ") {
+            let name = line.trim_start_matches("残局 ").trim();
+            let found = puzzles.iter().enumerate().find(|(i, (file, puzzle))| {
+                name == (i + 1).to_string() || name == file.as_str() || puzzle.as_ref().map_or(false, |x| x.name == name)
+            });
+            match found {
+                Some((_, (_, Ok(puzzle)))) => play_puzzle(puzzle),
+                Some((_, (_, Err(e)))) => println!("{}", e),
+                None => println!("没有找到残局 {}", name),
+            }
+        } else {
+            println!("未知指令");
+        }
+    }
+}
+
+fn play_puzzle(puzzle: &Puzzle) {
+    let mut users: [UserId; 3] = [String::from("电脑1"), String::from("电脑2"), String::from("电脑3")];
+    users[puzzle.player] = String::from("你");
+    let mut game = match puzzle.to_game(users) {
+        Ok(game) => game,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    let mut solver = Solver::new();
+
+    println!("{}", puzzle.name);
+    if !puzzle.description.is_empty() {
+        println!("{}", puzzle.description);
+    }
+    println!("输入 出牌 <牌> 出牌，pass 过牌，提示 查看最佳出法，认输 退出残局。");
+
+    loop {
+        let user = game.current_player().user.clone();
+        let result = if game.index == puzzle.player {
+            print_table(&game);
+            let line = read_line("请出牌：");
+            let line = line.trim();
+            if line == "认输" {
+                println!("挑战失败。");
+                return;
+            } else if line == "提示" {
+                match solver.best_move(&Position::from_game(&game)) {
+                    Move::Play(cards) => println!("提示：出 {}", cards_to_string(&cards)),
+                    Move::Pass => println!("提示：过牌"),
+                }
+                continue;
+            } else if line == "pass" {
+                game.pass()
+            } else if line.starts_with("出牌 ") {
+                match line.trim_start_matches("出牌 ").parse::<CardList>() {
+                    Ok(cards) if !cards.0.is_empty() => game.submit_cards(cards.0),
+                    Ok(_) => {
+                        println!("你没有出任何牌！");
+                        continue;
+                    }
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
+                    }
+                }
+            } else {
+                println!("未知指令");
+                continue;
+            }
+        } else {
+            match solver.best_move(&Position::from_game(&game)) {
+                Move::Play(cards) => {
+                    println!("{} 出牌：{}", user, cards_to_string(&cards));
+                    game.submit_cards(cards)
+                }
+                Move::Pass => {
+                    println!("{} 过牌", user);
+                    game.pass()
+                }
+            }
+        };
+
+        match result {
+            Ok(_) => {}
+            Err(GameError::Win(player, player_type, _)) => {
+                println!("{} 出完了所有牌。", player);
+                let landlord_won = player_type == PlayerType::Landlord;
+                if landlord_won == (puzzle.player == puzzle.landlord) {
+                    println!("挑战成功！");
+                } else {
+                    println!("挑战失败。");
+                }
+                return;
+            }
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+/// 残局为明牌，显示所有玩家的手牌
+fn print_table(game: &Game) {
+    for (i, player) in game.players.iter().enumerate() {
+        let role = if i == game.landlord_index { "地主" } else { "农民" };
+        print!("{}（{}）：", player.user, role);
+        print_cards(&player.cards);
+    }
+    if game.last_index != game.index && !game.last_cards.is_empty() {
+        println!("{} 最后出牌：{}", game.players[game.last_index].user, cards_to_string(&game.last_cards));
+    }
+}
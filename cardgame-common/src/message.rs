@@ -1,9 +1,9 @@
-use cardgame::Card;
+use cardgame::{Card, DealReveal, GameSnapshot, MatchStats, RoomConfig};
 use serde::{Deserialize, Serialize};
 use cardgame::user::{User, UserId};
 use std::time::Duration;
 use bevy_spicy_networking::{ClientMessage, NetworkMessage, ServerMessage};
-use cardgame::error::{GameError, LobbyError, RoomError};
+use cardgame::error::{AuthError, GameError, LobbyError, RoomError};
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 pub enum S2CMessage {
@@ -17,15 +17,21 @@ pub enum S2CMessage {
     /// 更新用户数据
     UpdateData(User),
 
-    /// 成功登入
-    // TODO token
-    LoggedIn,
+    /// 成功登入，参数为会话令牌，断线重连时使用
+    LoggedIn(String),
+
+    /// 登入或注册失败
+    AuthErr(AuthError),
 
     /// 房间列表
     RoomList(Vec<String>),
 
-    /// 成功加入房间
-    RoomJoined(String),
+    /// 成功加入房间，参数为房间名以及下一局洗牌种子的承诺
+    /// 收到承诺后可以提交参与洗牌的随机数
+    RoomJoined(String, String),
+
+    /// 公开种子后公布下一局洗牌种子的承诺
+    SeedCommitted(String),
 
     /// 房间相关错误
     RoomErr(RoomError),
@@ -37,8 +43,8 @@ pub enum S2CMessage {
     GameErr(GameError),
 
     /// 游戏开始
-    /// 参数为手牌以及地主ID
-    GameStarted(Vec<Card>, String),
+    /// 参数为手牌、地主ID以及洗牌种子的承诺（SHA-256）
+    GameStarted(Vec<Card>, String, String),
 
     /// 轮到谁叫地主
     LandlordMove(String),
@@ -49,6 +55,9 @@ pub enum S2CMessage {
     /// 出牌
     CardsSubmitted(String, Vec<Card>),
 
+    /// 报单、报双：玩家只剩一张或两张牌，发给房间内所有人
+    LastCards(UserId, u32),
+
     /// 匹配列表信息
     /// 当前在匹配的玩家数量，预期时间，剩余时间
     MatchmakeStatus(u32, Duration, Duration),
@@ -57,7 +66,34 @@ pub enum S2CMessage {
     Move(String),
 
     /// 重新比赛投票
-    RematchVote(UserId, bool, u32)
+    RematchVote(UserId, bool, u32),
+
+    /// 投降投票，参数为投票的玩家、是否同意以及已同意的人数
+    /// 投降成立时改为发送 `GameErr(GameError::Win)`
+    SurrenderVote(UserId, bool, u32),
+
+    /// 游戏结束后公开洗牌种子
+    SeedRevealed(DealReveal),
+
+    /// 玩家明牌，发给房间内所有人
+    /// 参数为玩家、手牌（发牌前明牌时为空，发完牌后再次发送）以及明牌后的倍率
+    HandShown(UserId, Vec<Card>, u32),
+
+    /// 玩家在游戏进行中断线，发给房间内其他人
+    /// 参数为玩家以及保留座位的时间，超过后按逃跑结算
+    PlayerDisconnected(UserId, Duration),
+
+    /// 断线的玩家重新连接，发给房间内其他人
+    PlayerReconnected(UserId),
+
+    /// 断线重连后恢复牌局，参数为玩家视角的牌局以及这局洗牌种子的承诺
+    Resync(GameSnapshot, String),
+
+    /// 最近的对局，最近的在前，不含牌谱
+    History(Vec<MatchStats>),
+
+    /// 一局的完整记录，参数为对局编号以及记录，找不到时为 None
+    MatchRecord(u64, Option<Box<MatchStats>>),
 }
 
 #[derive(Serialize, Deserialize, PartialEq)]
@@ -71,10 +107,22 @@ pub enum C2SMessage {
     ChooseLandlord(bool),
     Ping,
     Ping2,
-    Login(String),
+
+    /// 登入，参数为用户名与密码
+    Login(String, String),
+
+    /// 注册并登入，参数为用户名与密码
+    Register(String, String),
+
+    /// 断线重连时用会话令牌登入
+    Resume(String),
+
     QueryRoomList,
     JoinRoom(String),
 
+    /// 按设置创建房间并加入
+    CreateRoom(String, RoomConfig),
+
     /// 加入匹配列表
     Matchmake,
 
@@ -83,7 +131,22 @@ pub enum C2SMessage {
 
     StartGame(String),
 
-    RematchVote(bool)
+    RematchVote(bool),
+
+    /// 投降投票，地主投降立即成立，农民需要两人都同意
+    SurrenderVote(bool),
+
+    /// 提交参与洗牌的随机数，收到洗牌种子的承诺之后、发牌之前提交
+    ClientSeed(u64),
+
+    /// 明牌，可以在发牌前或叫地主时明牌
+    ShowHand,
+
+    /// 查询自己最近的对局
+    QueryHistory,
+
+    /// 查询一局的完整记录，参数为对局编号
+    QueryMatch(u64),
 }
 
 #[typetag::serde]
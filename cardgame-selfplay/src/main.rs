@@ -0,0 +1,251 @@
+use cardgame::*;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::process::exit;
+use std::sync::mpsc::channel;
+use std::time::Instant;
+use crate::writer::{new_writer, Format, Record, RecordWriter};
+
+/// 训练数据输出
+pub mod writer;
+
+/// 连续不叫地主的次数达到该值时重新发牌
+const MAX_DECLINES: usize = 3;
+
+/// 派生电脑玩家种子时与重新发牌次数错开
+const BOT_SEED_SALT: u64 = 1 << 32;
+
+struct Config {
+    /// 对局数量
+    games: u64,
+
+    /// 三个座位的电脑玩家
+    bots: [String; 3],
+
+    format: Format,
+
+    output: String,
+
+    threads: usize,
+
+    /// 基础种子，每局的种子由此派生
+    seed: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            games: 1000,
+            bots: [String::from("greedy"), String::from("greedy"), String::from("greedy")],
+            format: Format::Jsonl,
+            output: String::from("selfplay.jsonl"),
+            threads: std::thread::available_parallelism().map_or(1, |x| x.get()),
+            seed: 0,
+        }
+    }
+}
+
+fn usage() -> ! {
+    println!("用法：cardgame-selfplay [选项]");
+    println!("  --games <数量>          对局数量，默认 1000");
+    println!("  --bots <a,b,c>          三个座位的电脑玩家，可选 random、greedy");
+    println!("  --format <jsonl|binary> 输出格式，默认 jsonl");
+    println!("  --output <路径>         输出文件，默认 selfplay.jsonl");
+    println!("  --threads <数量>        线程数，默认为 CPU 核心数");
+    println!("  --seed <种子>           基础种子，默认 0");
+    exit(1);
+}
+
+fn parse_args() -> Config {
+    let mut config = Config::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--games" => config.games = value.parse().unwrap_or_else(|_| usage()),
+            "--bots" => {
+                let bots: Vec<&str> = value.split(',').collect();
+                if bots.len() != 3 || bots.iter().any(|x| new_bot(x, 0).is_none()) {
+                    usage();
+                }
+                config.bots = [bots[0].to_string(), bots[1].to_string(), bots[2].to_string()];
+            }
+            "--format" => {
+                config.format = match value.as_str() {
+                    "jsonl" => Format::Jsonl,
+                    "binary" => Format::Binary,
+                    _ => usage(),
+                }
+            }
+            "--output" => config.output = value,
+            "--threads" => config.threads = value.parse().unwrap_or_else(|_| usage()),
+            "--seed" => config.seed = value.parse().unwrap_or_else(|_| usage()),
+            _ => usage(),
+        }
+    }
+    config.threads = config.threads.max(1);
+    config
+}
+
+/// 由基础种子、对局编号与用途派生种子（SplitMix64），与线程数无关
+fn derive_seed(base: u64, game: u64, salt: u64) -> u64 {
+    let mix = |x: u64| {
+        let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+    mix(mix(base ^ mix(game)) ^ salt)
+}
+
+/// 进行第 `game` 局，三人都不叫地主时重新发牌
+/// 发牌与电脑玩家都只由基础种子和对局编号决定
+fn play_seeded_game(env: &mut Env, bot_names: &[String; 3], base_seed: u64, game: u64) -> Vec<Record> {
+    let mut attempt = 0;
+    loop {
+        let mut bots: Vec<Box<dyn Bot>> = bot_names
+            .iter()
+            .enumerate()
+            .map(|(i, x)| new_bot(x, derive_seed(base_seed, game, BOT_SEED_SALT + i as u64)).unwrap())
+            .collect();
+        if let Some(records) = play_game(env, &mut bots, game, derive_seed(base_seed, game, attempt)) {
+            return records;
+        }
+        attempt += 1;
+    }
+}
+
+/// 进行一局自我对弈，若三人都不叫地主则返回 None
+fn play_game(env: &mut Env, bots: &mut [Box<dyn Bot>], game: u64, seed: u64) -> Option<Vec<Record>> {
+    let mut records = vec![];
+    let mut step = env.reset(seed);
+    let mut declines = 0;
+
+    while !step.done {
+        let action = env.bot_action(&mut *bots[step.seat]);
+        if action == ACTION_DECLINE {
+            declines += 1;
+            if declines >= MAX_DECLINES {
+                return None;
+            }
+        }
+
+        records.push(Record {
+            game,
+            seed,
+            seat: step.seat as u8,
+            observation: step.observation,
+            legal: step.mask.iter().enumerate().filter(|(_, x)| **x).map(|(i, _)| i as u32).collect(),
+            action: action as u32,
+            reward: 0.0,
+        });
+
+        step = env.step(action).expect("Bot chose an illegal action");
+    }
+
+    for record in records.iter_mut() {
+        record.reward = step.rewards[record.seat as usize];
+    }
+    Some(records)
+}
+
+/// 多线程进行自我对弈，按对局编号顺序写入，输出与线程数无关
+/// 返回写入的决策点数量
+fn run(config: &Config, writer: &mut dyn RecordWriter) -> usize {
+    let (tx, rx) = channel::<(u64, Vec<Record>)>();
+    let mut handles = vec![];
+    for thread in 0..config.threads {
+        let tx = tx.clone();
+        let bots = config.bots.clone();
+        let games = config.games;
+        let threads = config.threads as u64;
+        let base_seed = config.seed;
+        handles.push(std::thread::spawn(move || {
+            let mut env = Env::new();
+            let mut game = thread as u64;
+            while game < games {
+                let records = play_seeded_game(&mut env, &bots, base_seed, game);
+                tx.send((game, records)).expect("Writer thread stopped");
+                game += threads;
+            }
+        }));
+    }
+    drop(tx);
+
+    // 先完成的对局暂存，轮到它时再写入
+    let mut pending = BTreeMap::new();
+    let mut next = 0;
+    let mut decisions = 0;
+    for (game, records) in rx.iter() {
+        pending.insert(game, records);
+        while let Some(records) = pending.remove(&next) {
+            for record in records.iter() {
+                writer.write(record).expect("Unable to write record");
+            }
+            decisions += records.len();
+            next += 1;
+            if next % 100 == 0 {
+                println!("已完成 {}/{} 局", next, config.games);
+            }
+        }
+    }
+    writer.finish().expect("Unable to flush output");
+
+    for handle in handles {
+        handle.join().expect("Self-play thread panicked");
+    }
+    decisions
+}
+
+fn main() {
+    let config = parse_args();
+    let start = Instant::now();
+    let env = Env::new();
+
+    let file = File::create(&config.output).unwrap_or_else(|e| {
+        println!("无法创建 {}：{}", config.output, e);
+        exit(1);
+    });
+    let mut writer = new_writer(config.format, file, OBSERVATION_SIZE, env.action_count()).expect("Unable to write header");
+    drop(env);
+
+    let games = config.games;
+    let decisions = run(&config, &mut *writer);
+
+    println!(
+        "共 {} 局，{} 个决策点，写入 {}，用时 {:.1}s",
+        games,
+        decisions,
+        config.output,
+        start.elapsed().as_secs_f32()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::tests::SharedBuffer;
+
+    fn generate(threads: usize) -> Vec<u8> {
+        let config = Config {
+            games: 6,
+            bots: [String::from("random"), String::from("greedy"), String::from("random")],
+            threads,
+            seed: 42,
+            ..Config::default()
+        };
+        let buffer = SharedBuffer::default();
+        let mut writer = new_writer(Format::Jsonl, buffer.clone(), OBSERVATION_SIZE, Env::new().action_count()).unwrap();
+        run(&config, &mut *writer);
+        let out = buffer.0.lock().unwrap().clone();
+        out
+    }
+
+    #[test]
+    fn independent_of_threads() {
+        let single = generate(1);
+        assert!(!single.is_empty());
+        assert_eq!(single, generate(3));
+        assert_eq!(single, generate(4));
+    }
+}
@@ -0,0 +1,193 @@
+use serde::Serialize;
+use std::io::{BufWriter, Result, Write};
+
+/// 一个决策点
+#[derive(Serialize)]
+pub struct Record {
+    /// 对局编号
+    pub game: u64,
+
+    /// 发牌种子
+    pub seed: u64,
+
+    /// 行动的座位
+    pub seat: u8,
+
+    /// 行动前的观测
+    pub observation: Vec<f32>,
+
+    /// 所有合法动作的编号
+    pub legal: Vec<u32>,
+
+    /// 选择的动作
+    pub action: u32,
+
+    /// 该座位在本局的最终得分
+    pub reward: f32,
+}
+
+/// 输出格式
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    /// 每行一条 JSON 记录
+    Jsonl,
+
+    /// 紧凑的二进制格式
+    Binary,
+}
+
+pub trait RecordWriter {
+    fn write(&mut self, record: &Record) -> Result<()>;
+
+    fn finish(&mut self) -> Result<()>;
+}
+
+pub fn new_writer<W: Write + 'static>(format: Format, out: W, observation_size: usize, action_count: usize) -> Result<Box<dyn RecordWriter>> {
+    match format {
+        Format::Jsonl => Ok(Box::new(JsonlWriter {
+            out: BufWriter::new(out),
+        })),
+        Format::Binary => {
+            let mut writer = BinaryWriter {
+                out: BufWriter::new(out),
+            };
+            writer.write_header(observation_size as u32, action_count as u32)?;
+            Ok(Box::new(writer))
+        }
+    }
+}
+
+pub struct JsonlWriter<W: Write> {
+    out: BufWriter<W>,
+}
+
+impl<W: Write> RecordWriter for JsonlWriter<W> {
+    fn write(&mut self, record: &Record) -> Result<()> {
+        serde_json::to_writer(&mut self.out, record)?;
+        self.out.write_all(b"\n")
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.out.flush()
+    }
+}
+
+/// 二进制格式，所有数字均为小端序
+///
+/// 文件头：`b"DDZ1"`，观测长度 u32，动作总数 u32
+///
+/// 每条记录：对局编号 u64，种子 u64，座位 u8，动作 u32，得分 f32，
+/// 合法动作数量 u16，合法动作 u32 × 数量，观测 f32 × 观测长度
+pub struct BinaryWriter<W: Write> {
+    out: BufWriter<W>,
+}
+
+impl<W: Write> BinaryWriter<W> {
+    fn write_header(&mut self, observation_size: u32, action_count: u32) -> Result<()> {
+        self.out.write_all(b"DDZ1")?;
+        self.out.write_all(&observation_size.to_le_bytes())?;
+        self.out.write_all(&action_count.to_le_bytes())
+    }
+}
+
+impl<W: Write> RecordWriter for BinaryWriter<W> {
+    fn write(&mut self, record: &Record) -> Result<()> {
+        self.out.write_all(&record.game.to_le_bytes())?;
+        self.out.write_all(&record.seed.to_le_bytes())?;
+        self.out.write_all(&[record.seat])?;
+        self.out.write_all(&record.action.to_le_bytes())?;
+        self.out.write_all(&record.reward.to_le_bytes())?;
+        self.out.write_all(&(record.legal.len() as u16).to_le_bytes())?;
+        for action in record.legal.iter() {
+            self.out.write_all(&action.to_le_bytes())?;
+        }
+        for x in record.observation.iter() {
+            self.out.write_all(&x.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// 可在写入后取回内容的缓冲区
+    #[derive(Clone, Default)]
+    pub(crate) struct SharedBuffer(pub Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn record() -> Record {
+        Record {
+            game: 3,
+            seed: 0x0102,
+            seat: 2,
+            observation: vec![0.0, 0.5, 1.0],
+            legal: vec![0, 7],
+            action: 7,
+            reward: -2.0,
+        }
+    }
+
+    fn write(format: Format) -> Vec<u8> {
+        let buffer = SharedBuffer::default();
+        let mut writer = new_writer(format, buffer.clone(), 3, 9).unwrap();
+        writer.write(&record()).unwrap();
+        writer.write(&record()).unwrap();
+        writer.finish().unwrap();
+        let out = buffer.0.lock().unwrap().clone();
+        out
+    }
+
+    #[test]
+    fn jsonl() {
+        let out = String::from_utf8(write(Format::Jsonl)).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let value: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(value["game"], 3);
+        assert_eq!(value["seat"], 2);
+        assert_eq!(value["legal"], serde_json::json!([0, 7]));
+        assert_eq!(value["observation"], serde_json::json!([0.0, 0.5, 1.0]));
+        assert_eq!(value["reward"], -2.0);
+    }
+
+    #[test]
+    fn binary() {
+        let out = write(Format::Binary);
+        assert_eq!(&out[..4], b"DDZ1");
+        assert_eq!(out[4..8], 3u32.to_le_bytes());
+        assert_eq!(out[8..12], 9u32.to_le_bytes());
+
+        // 8 + 8 + 1 + 4 + 4 + 2 + 2 × 4 + 3 × 4
+        let size = 47;
+        assert_eq!(out.len(), 12 + size * 2);
+        let body = &out[12..12 + size];
+        assert_eq!(body[..8], 3u64.to_le_bytes());
+        assert_eq!(body[8..16], 0x0102u64.to_le_bytes());
+        assert_eq!(body[16], 2);
+        assert_eq!(body[17..21], 7u32.to_le_bytes());
+        assert_eq!(body[21..25], (-2.0f32).to_le_bytes());
+        assert_eq!(body[25..27], 2u16.to_le_bytes());
+        assert_eq!(body[27..31], 0u32.to_le_bytes());
+        assert_eq!(body[31..35], 7u32.to_le_bytes());
+        assert_eq!(body[35..39], 0.0f32.to_le_bytes());
+        assert_eq!(body[43..47], 1.0f32.to_le_bytes());
+        assert_eq!(&out[12 + size..], body);
+    }
+}
@@ -2,14 +2,14 @@ use bimap::BiHashMap;
 use cardgame::*;
 use message_io::network::*;
 use message_io::node;
-use cardgame::user::{User, UserId, UserManager, UserState};
+use cardgame::user::{legacy_claims_from_env, User, UserId, UserManager, UserState, FLUSH_INTERVAL};
 use std::collections::HashMap;
 use crate::server_lobby::ServerLobby;
 use message_io::node::NodeEvent;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::sync::Mutex;
 use std::rc::Rc;
-use cardgame::error::{GameError, RoomError};
+use cardgame::error::{AuthError, GameError, RoomError};
 use crate::server_network::{MessagePacket, MessageTarget};
 
 /// 大厅
@@ -23,6 +23,7 @@ pub fn main() {
     // The 'handler', used to make actions (connect, send messages, signals, stop the node...)
     // The 'listener', used to read events from the network or signals.
     let (handler, listener) = node::split::<cardgame::Signal>();
+    let shutdown = handler.clone();
 
     let network_handle = std::thread::spawn(move || {
         let address = "0.0.0.0:3042";
@@ -35,19 +36,31 @@ pub fn main() {
 
         let signals = handler.signals();
         let network = handler.network();
+        // 收到 Shutdown 信号时停止节点
+        let stopper = handler.clone();
 
         println!("服务器在 {} 上监听", address);
 
         let mut lobby = ServerLobby::new(network);
+        // 命令行参数为大厅的房间设置，例如：底分50 封顶64
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        let args: Vec<&str> = args.iter().map(|x| x.as_str()).collect();
+        lobby.room_config = RoomConfig::from_args(&args).expect("无法识别的房间设置");
         let mut clients: Vec<Endpoint> = vec![];
         let mut client_map: BiHashMap<String, Endpoint> = BiHashMap::new();
-        let mut user_manager = UserManager::new(String::from("users"));
+        // 环境变量 CARDGAME_STORE 选择玩家数据的存储方式，例如：sqlite:users.db
+        let store = open_store(&store_config_from_env()).expect("无法打开玩家数据存储");
+        let mut user_manager = UserManager::with_store(store);
+        // 环境变量 CARDGAME_CLAIM_LEGACY=1 时允许认领没有密码的老用户，只在迁移期间使用
+        user_manager.allow_legacy_claims(legacy_claims_from_env());
         let mut user_states: HashMap<String, UserState> = HashMap::new();
 
         let mut matchmake_timer = 0;
         let mut matchmake_id = 0;
         let mut rng = thread_rng();
 
+        signals.send_with_timer(Signal::FlushUsers, FLUSH_INTERVAL);
+
         println!("初始化完毕");
 
         // Read incoming network events.
@@ -60,7 +73,7 @@ pub fn main() {
                     }
                     NetEvent::Message(endpoint, data) => {
                         let get_user = || -> Option<User> {
-                            client_map.get_by_right(&endpoint).map(|x| user_manager.get_user(x).unwrap())
+                            client_map.get_by_right(&endpoint).and_then(|x| user_manager.get_user(x).ok())
                         };
                         let get_user_id = || -> Option<&UserId> {
                             client_map.get_by_right(&endpoint)
@@ -87,14 +100,28 @@ pub fn main() {
 
                         let message: C2SMessage = bincode::deserialize(data).unwrap();
 
+                        // 登入之前只能登入、注册或断线重连
+                        let public = matches!(message, C2SMessage::Ping | C2SMessage::Login(..) | C2SMessage::Register(..) | C2SMessage::Resume(..));
+                        if !public && lobby.get_user(&endpoint).is_none() {
+                            send_to_client(&S2CMessage::AuthErr(AuthError::NotLoggedIn));
+                            return;
+                        }
+
                         match message {
                             C2SMessage::Ping => {
                                 send_to_client(&S2CMessage::Pong);
                             }
-                            C2SMessage::Login(username) => {
-                                println!("玩家 {} 登入", username);
-                                send_to_client(&S2CMessage::LoggedIn);
-                                lobby.connect(username, endpoint);
+                            C2SMessage::Login(username, password) => {
+                                let result = user_manager.login(&username, &password).map(|token| (username, token));
+                                login(&mut lobby, endpoint, result);
+                            }
+                            C2SMessage::Register(username, password) => {
+                                let result = user_manager.register(&username, &password).map(|token| (username, token));
+                                login(&mut lobby, endpoint, result);
+                            }
+                            C2SMessage::Resume(token) => {
+                                let result = user_manager.authenticate(&token).map(|username| (username, token));
+                                login(&mut lobby, endpoint, result);
                             }
                             // 加入房间
                             C2SMessage::JoinRoom(room_name) => {
@@ -105,6 +132,14 @@ pub fn main() {
                                     }
                                 }
                             }
+                            C2SMessage::CreateRoom(room_name, config) => {
+                                match lobby.create_room(&room_name, config).and_then(|_| lobby.join_room_by_endpoint(&room_name, endpoint)) {
+                                    Ok(_) => {}
+                                    Err(e) => {
+                                        send_to_client(&S2CMessage::LobbyErr(e))
+                                    }
+                                }
+                            }
                             C2SMessage::StartGame(room_name) => {
                                 match lobby.start_game_by_name(&room_name) {
                                     Ok(_) => {}
@@ -126,17 +161,17 @@ pub fn main() {
                                         send_to_client(&S2CMessage::RoomErr(RoomError::NotStarted));
                                     }
                                     // 不是当前的地主玩家
-                                    if room.game.players[room.game.landlord_index].user != get_user().unwrap().id {
+                                    else if room.game.players[room.game.landlord_index].user != get_user().unwrap().id {
                                         send_to_client(&S2CMessage::RoomErr(RoomError::NotLandlordPlayer));
                                     }
-                                    if choose { // 叫地主
-                                        room.game.run().expect("Game cannot run");
+                                    else if choose { // 叫地主
+                                        room.game.bid(true).expect("Game cannot run");
                                         // 通知所有玩家地主人选
                                         for player in room.game.players.iter() {
                                             send_to_user(&player.user, &S2CMessage::LordCards(room.game.current_player().user.clone(), room.game.landlord_cards.clone()))
                                         }
                                     } else { // 不叫
-                                        room.game.move_landlord_index();
+                                        room.game.bid(false).expect("Game cannot run");
                                         // 通知下一个地主
                                         for player in room.game.players.iter() {
                                             send_to_user(&player.user, &S2CMessage::LandlordMove(room.game.landlord_player().user.clone()))
@@ -160,7 +195,7 @@ pub fn main() {
                                     } else if room.game.current_player().user != get_user().unwrap().id {
                                         send_to_client(&S2CMessage::GameErr(GameError::NotYourTurn));
                                     } else {
-                                        match room.game.pass() {
+                                        match room.pass() {
                                             Ok(next_player) => {
                                                 for user in room.users.iter() {
                                                     send_to_user(user, &S2CMessage::Move(next_player.clone()));
@@ -194,51 +229,52 @@ pub fn main() {
                             C2SMessage::SubmitCards(cards) => {
                                 match lobby.submit_cards(&endpoint, cards.clone()) {
                                     Ok(next_player) => {
+                                        let user_id = lobby.get_user(&endpoint).unwrap();
                                         let room = lobby.get_room_by_endpoint(&endpoint).unwrap();
+                                        let seat = room.users.iter().position(|x| x == user_id).unwrap();
                                         for user in room.users.iter() {
-                                            send_to_user(user, &S2CMessage::CardsSubmitted(client_map.get_by_right(&endpoint).unwrap().clone(), cards.clone()));
-                                            send_to_user(user, &S2CMessage::Move(next_player.clone()));
+                                            lobby.send_packet((MessageTarget::User(user.clone()), S2CMessage::CardsSubmitted(user_id.clone(), cards.clone())));
+                                            lobby.send_packet((MessageTarget::User(user.clone()), S2CMessage::Move(next_player.clone())));
+                                        }
+
+                                        // 报单、报双
+                                        if let Some(remaining) = room.game.last_cards(seat) {
+                                            for user in room.users.iter() {
+                                                lobby.send_packet((MessageTarget::User(user.clone()), S2CMessage::LastCards(user_id.clone(), remaining)));
+                                            }
                                         }
                                     }
                                     Err(e) => {
-                                        send_to_client(&S2CMessage::GameErr(e));
-                                    }
-                                }
-                                let room = if let Some(UserState::Playing(room_name)) = user_states.get(client_map.get_by_right(&endpoint).unwrap()) {
-                                    lobby.rooms.get_mut(room_name)
-                                } else {
-                                    None
-                                };
-
-                                if let Some(room) = room {
-                                    if room.game.state != GameState::Running {
-                                        send_to_client(&S2CMessage::RoomErr(RoomError::NotReady));
-                                    } else if room.game.current_player().user != get_user().unwrap().id {
-                                        send_to_client(&S2CMessage::GameErr(GameError::NotYourTurn));
-                                    } else {
-                                        match room.game.submit_cards(cards.clone()) {
-                                            Ok(next_player) => {
-                                                for user in room.users.iter() {
-                                                    send_to_user(user, &S2CMessage::CardsSubmitted(client_map.get_by_right(&endpoint).unwrap().clone(), cards.clone()));
-                                                    send_to_user(user, &S2CMessage::Move(next_player.clone()));
+                                        let win = matches!(e, GameError::Win(..));
+                                        match lobby.get_room_by_endpoint_mut(&endpoint).filter(|_| win) {
+                                            // 这局结束，把最后一手牌与结算发给房间内所有人，等待重新比赛投票
+                                            Some(room) => {
+                                                room.state = RoomState::WaitingForRematch(0);
+                                                let users = room.users.clone();
+                                                let winner = lobby.get_user(&endpoint).cloned().unwrap();
+                                                for user in users {
+                                                    lobby.send_packet((MessageTarget::User(user.clone()), S2CMessage::CardsSubmitted(winner.clone(), cards.clone())));
+                                                    lobby.send_packet((MessageTarget::User(user), S2CMessage::GameErr(e.clone())));
                                                 }
                                             }
-                                            Err(e) => {
-                                                match e.clone() {
-                                                    GameError::Win(user, player_type, score) => {
-                                                        // TODO 结算并写入文件
-
-                                                        user_states.insert(user, UserState::Idle);
-                                                        room.state = RoomState::WaitingForRematch(0);
-                                                    }
-                                                    _ => {}
-                                                }
-                                                send_to_client(&S2CMessage::GameErr(e))
+                                            None => send_to_client(&S2CMessage::GameErr(e)),
+                                        }
+
+                                        // 公开洗牌种子
+                                        let room_name = lobby.get_room_by_endpoint(&endpoint).map(|x| x.name.clone());
+                                        if let Some(reveal) = room_name.filter(|_| win).and_then(|x| lobby.reveal_seed(&x)) {
+                                            for user in reveal.players.iter() {
+                                                lobby.send_packet((MessageTarget::User(user.clone()), S2CMessage::SeedRevealed(reveal.clone())));
                                             }
                                         }
                                     }
-                                } else {
-                                    send_to_client(&S2CMessage::RoomErr(RoomError::NotReady));
+                                }
+
+                                // 结算并写入文件
+                                for stats in lobby.take_stats() {
+                                    if let Err(err) = user_manager.record_match(&stats) {
+                                        println!("无法保存对局统计: {}", err);
+                                    }
                                 }
                             }
                             C2SMessage::Matchmake => {
@@ -252,6 +288,50 @@ pub fn main() {
                                 let data = lobby.rooms.keys().map(|x| x.to_string()).collect();
                                 send_to_client(&S2CMessage::RoomList(data));
                             }
+                            C2SMessage::ClientSeed(seed) => {
+                                if let Some(user) = lobby.get_user(&endpoint).cloned() {
+                                    if let Err(e) = lobby.client_seed(&user, seed) {
+                                        println!("{} 的随机数未被接受：{}", user, e);
+                                    }
+                                }
+                            }
+                            C2SMessage::SurrenderVote(vote) => {
+                                if let Some(user) = lobby.get_user(&endpoint).cloned() {
+                                    if let Err(e) = lobby.surrender_vote(&user, vote) {
+                                        send_to_client(&S2CMessage::RoomErr(e));
+                                    }
+                                }
+
+                                // 结算并写入文件
+                                for stats in lobby.take_stats() {
+                                    if let Err(err) = user_manager.record_match(&stats) {
+                                        println!("无法保存对局统计: {}", err);
+                                    }
+                                }
+                            }
+                            C2SMessage::ShowHand => {
+                                if let Some(user) = lobby.get_user(&endpoint).cloned() {
+                                    if let Err(e) = lobby.show_hand(&user) {
+                                        send_to_client(&S2CMessage::GameErr(e));
+                                    }
+                                }
+                            }
+                            C2SMessage::QueryHistory => {
+                                if let Some(user) = lobby.get_user(&endpoint) {
+                                    let matches = user_manager.recent_matches(user).unwrap_or_else(|err| {
+                                        println!("无法读取对局记录: {}", err);
+                                        vec![]
+                                    });
+                                    send_to_client(&S2CMessage::History(matches));
+                                }
+                            }
+                            C2SMessage::QueryMatch(id) => {
+                                let record = user_manager.find_match(id).unwrap_or_else(|err| {
+                                    println!("无法读取对局记录: {}", err);
+                                    None
+                                });
+                                send_to_client(&S2CMessage::MatchRecord(id, record.map(Box::new)));
+                            }
                             _ => {
                                 println!("Unknown message")
                             }
@@ -259,9 +339,13 @@ pub fn main() {
                     }
                     NetEvent::Disconnected(endpoint) => {
                         println!("{} 已断开", endpoint);
-                        if let Some(user) = client_map.get_by_right(&endpoint).map(|x| user_manager.get_user(x).unwrap()) {
-                            lobby.disconnect(&user.id);
-                            user_states.remove(&user.id);
+                        if let Some(user) = lobby.get_user(&endpoint).cloned() {
+                            lobby.disconnect(&user);
+                            user_states.remove(&user);
+                            // 重连时间过后检查是否逃跑
+                            if let Some(deadline) = lobby.disconnected.get(&user) {
+                                signals.send_with_timer(Signal::CheckEscapes, deadline.saturating_duration_since(Instant::now()));
+                            }
                         }
                         let client = clients
                             .iter()
@@ -272,6 +356,32 @@ pub fn main() {
                     },
                 }
             },
+            NodeEvent::Signal(cardgame::Signal::CheckEscapes) => {
+                lobby.check_escapes();
+
+                // 结算并写入文件
+                for stats in lobby.take_stats() {
+                    if let Err(err) = user_manager.record_match(&stats) {
+                        println!("无法保存对局统计: {}", err);
+                    }
+                }
+            }
+            NodeEvent::Signal(cardgame::Signal::FlushUsers) => {
+                match user_manager.flush() {
+                    Ok(0) => {}
+                    Ok(count) => println!("保存了 {} 位玩家的数据", count),
+                    Err(err) => println!("无法保存玩家数据: {}", err),
+                }
+                signals.send_with_timer(Signal::FlushUsers, FLUSH_INTERVAL);
+            }
+            NodeEvent::Signal(cardgame::Signal::Shutdown) => {
+                match user_manager.flush() {
+                    Ok(count) => println!("保存了 {} 位玩家的数据", count),
+                    Err(err) => println!("无法保存玩家数据: {}", err),
+                }
+                println!("服务器已关闭");
+                stopper.stop();
+            }
             NodeEvent::Signal(signal) => if let cardgame::Signal::Matchmake = signal {
                 let send_to_user = |user: &UserId, msg: &S2CMessage| {
                     let to_send = bincode::serialize(msg).unwrap();
@@ -326,9 +436,36 @@ pub fn main() {
         });
     });
 
+    // 在控制台输入 stop 关闭服务器，关闭前保存玩家数据
+    let mut input = String::new();
+    while let Ok(n) = std::io::stdin().read_line(&mut input) {
+        if n == 0 {
+            break;
+        }
+        if input.trim() == "stop" {
+            shutdown.signals().send(Signal::Shutdown);
+            break;
+        }
+        input.clear();
+    }
+
     network_handle.join().unwrap();
 }
 
+/// 登入成功时把连接与玩家关联，并发回会话令牌
+fn login(lobby: &mut ServerLobby, endpoint: Endpoint, result: Result<(UserId, String), AuthError>) {
+    match result {
+        Ok((username, token)) => {
+            println!("玩家 {} 登入", username);
+            lobby.send_packet((MessageTarget::Endpoint(endpoint), S2CMessage::LoggedIn(token)));
+            lobby.connect(username, endpoint);
+        }
+        Err(e) => {
+            lobby.send_packet((MessageTarget::Endpoint(endpoint), S2CMessage::AuthErr(e)));
+        }
+    }
+}
+
 /*
 fn get_line(prompt: &str) -> String {
     println!("{}", prompt);
@@ -1,12 +1,14 @@
 use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::Instant;
 
 use message_io::network::{Endpoint, NetworkController, SendStatus};
 
 use cardgame::user::{UserId, UserState};
-use cardgame::{Card, Game, GameState, Lobby, Room, RoomState, S2CMessage};
+use cardgame::{Card, DealReveal, Game, GameState, Lobby, MatchStats, Room, RoomConfig, RoomState, S2CMessage, StatsObserver, SurrenderVote};
 use bimap::{BiHashMap, BiMap};
 use anyhow::Error;
-use cardgame::error::{GameError, LobbyError, RoomError};
+use cardgame::error::{AuthError, FairnessError, GameError, LobbyError, RoomError};
 use crate::server_network::{MessagePacket, MessageTarget, NetworkManager};
 
 /// 大厅的服务器实现。
@@ -27,21 +29,42 @@ pub struct ServerLobby<'a> {
     pub games: HashMap<String, Game>,
 
     network: NetworkManager<'a>,
+
+    /// 加入不存在的房间时自动创建房间使用的设置，不同级别的大厅可以使用不同的底分与封顶
+    pub room_config: RoomConfig,
+
+    /// 游戏进行中断线的玩家与按逃跑结算的时间
+    pub disconnected: HashMap<UserId, Instant>,
+
+    /// 房间内游戏结束时发出的统计数据
+    stats_sender: Sender<MatchStats>,
+    stats_receiver: Receiver<MatchStats>,
 }
 
 impl ServerLobby<'_> {
     pub fn new(controller: &NetworkController) -> ServerLobby {
+        let (stats_sender, stats_receiver) = channel();
         ServerLobby {
             users: vec![],
             user_states: HashMap::new(),
             waiting_list: vec![],
             rooms: HashMap::new(),
             games: HashMap::new(),
+            room_config: RoomConfig::default(),
+            disconnected: HashMap::new(),
             network: NetworkManager::new(controller),
+            stats_sender,
+            stats_receiver,
         }
     }
 
     pub fn connect(&mut self, user_id: UserId, endpoint: Endpoint) {
+        // 同名玩家已经在线时接管会话，通知原来的连接
+        let old = self.network.user_map.get_by_left(&user_id).copied();
+        let took_over = old.map_or(false, |x| x != endpoint);
+        if let Some(old) = old.filter(|_| took_over) {
+            self.network.send_to_endpoint(&S2CMessage::AuthErr(AuthError::SessionTakenOver), old);
+        }
         self.login(user_id.clone());
         self.network.connect(user_id.clone(), endpoint);
 
@@ -57,8 +80,11 @@ impl ServerLobby<'_> {
                         // 断线后取消匹配
                         self.user_states.insert(user_id, UserState::Idle);
                     }
-                    UserState::Playing(_room) => {
-                        // TODO 断线重连
+                    UserState::Playing(room_name) => {
+                        let room_name = room_name.clone();
+                        if self.disconnected.remove(&user_id).is_some() || took_over {
+                            self.reconnect(&user_id, &room_name);
+                        }
                     }
                 }
             }
@@ -69,11 +95,21 @@ impl ServerLobby<'_> {
         self.join_room(room_name, self.get_user(&endpoint).expect("User not found").to_string())
     }
 
+    /// 按设置创建房间
+    pub fn create_room(&mut self, room_name: &str, config: RoomConfig) -> Result<(), LobbyError> {
+        if self.rooms.contains_key(room_name) {
+            return Err(LobbyError::RoomExists);
+        }
+        let mut room = Room::with_config(room_name.to_string(), config);
+        room.game.add_observer(Box::new(StatsObserver::new(self.stats_sender.clone())));
+        self.rooms.insert(room_name.to_string(), room);
+        println!("创建房间: {}", room_name);
+        Ok(())
+    }
+
     pub fn join_room(&mut self, room_name: &str, user: UserId) -> Result<MessagePacket, LobbyError> {
         if !self.rooms.contains_key(room_name) {
-            self.rooms
-                .insert(room_name.to_string(), Room::new(room_name.to_string()));
-            println!("创建房间: {}", room_name);
+            self.create_room(room_name, self.room_config.clone())?;
         };
         let mut room = self.rooms.get_mut(room_name).unwrap();
         if room.users.contains(&user) {
@@ -81,15 +117,18 @@ impl ServerLobby<'_> {
         } else if room.users.len() == 3 {
             Err(LobbyError::RoomFull)
         } else {
+            // 先公布承诺，玩家收到后才能提交参与洗牌的随机数
+            let next_commitment = room.seed.commit();
             room.push(user);
 
             if room.users.len() == 3 {
                 room.state = RoomState::Ready;
 
+                let commitment = room.seed.commitment();
                 match room.start_game() {
                     Ok((landlord_player, players)) => {
                         for player in players {
-                            self.network.send_to_user(&player.user, &S2CMessage::GameStarted(player.cards.clone(), landlord_player.user.clone()));
+                            self.network.send_to_user(&player.user, &S2CMessage::GameStarted(player.cards.clone(), landlord_player.user.clone(), commitment.clone()));
                             self.user_states.insert(player.user.clone(), UserState::Playing(room_name.to_string()));
                         }
                     }
@@ -97,17 +136,19 @@ impl ServerLobby<'_> {
                         return Err(LobbyError::RoomErr(err));
                     }
                 }
+                send_shown_hands(&self.network, room);
             }
-            Ok((MessageTarget::Reply, S2CMessage::RoomJoined(room_name.to_string())))
+            Ok((MessageTarget::Reply, S2CMessage::RoomJoined(room_name.to_string(), next_commitment)))
         }
     }
 
     pub fn start_game(&mut self, room: &mut Room) -> Result<(), Error> {
         let room_name = room.name.clone();
+        let commitment = room.seed.commitment();
         match room.start_game() {
             Ok((landlord_player, players)) => {
                 for player in players {
-                    self.network.send_to_user(&player.user, &S2CMessage::GameStarted(player.cards.clone(), landlord_player.user.clone()));
+                    self.network.send_to_user(&player.user, &S2CMessage::GameStarted(player.cards.clone(), landlord_player.user.clone(), commitment.clone()));
                     self.user_states.insert(player.user.clone(), UserState::Playing(room_name.clone()));
                 }
             }
@@ -116,6 +157,7 @@ impl ServerLobby<'_> {
                 return Err(Error::from(err));
             }
         }
+        send_shown_hands(&self.network, room);
         Ok(())
     }
 
@@ -125,10 +167,11 @@ impl ServerLobby<'_> {
             return Err(RoomError::NotReady);
         }
         let room = room.unwrap();
+        let commitment = room.seed.commitment();
         match room.start_game() {
             Ok((landlord_player, players)) => {
                 for player in players {
-                    self.network.send_to_user(&player.user, &S2CMessage::GameStarted(player.cards.clone(), landlord_player.user.clone()));
+                    self.network.send_to_user(&player.user, &S2CMessage::GameStarted(player.cards.clone(), landlord_player.user.clone(), commitment.clone()));
                     self.user_states.insert(player.user.clone(), UserState::Playing(room_name.to_string()));
                 }
             }
@@ -136,6 +179,7 @@ impl ServerLobby<'_> {
                 return Err(err);
             }
         }
+        send_shown_hands(&self.network, room);
         Ok(room)
     }
 
@@ -153,6 +197,116 @@ impl ServerLobby<'_> {
         Err(RoomError::NotReady)
     }
 
+    /// 公开房间这局的洗牌种子，并向房间内所有人公布下一局种子的承诺
+    pub fn reveal_seed(&mut self, room_name: &str) -> Option<DealReveal> {
+        let room = self.rooms.get_mut(room_name)?;
+        let reveal = room.reveal_seed();
+        let commitment = room.seed.commit();
+        for user in room.users.iter() {
+            self.network.send_to_user(user, &S2CMessage::SeedCommitted(commitment.clone()));
+        }
+        Some(reveal)
+    }
+
+    /// 记录玩家提交的参与洗牌的随机数
+    /// 只接受所在房间公布承诺之后、发牌之前提交的随机数
+    pub fn client_seed(&mut self, user: &str, seed: u64) -> Result<(), FairnessError> {
+        let room = self.rooms
+            .values_mut()
+            .find(|x| x.users.iter().any(|u| u == user))
+            .ok_or(FairnessError::NotCommitted)?;
+        room.seed.add_client_seed(user.to_string(), seed)
+    }
+
+    /// 投降投票，把投票结果发给房间内所有人，投降成立时按对方获胜结算并公开洗牌种子
+    pub fn surrender_vote(&mut self, user: &str, vote: bool) -> Result<(), RoomError> {
+        let room = self.rooms
+            .values_mut()
+            .find(|x| x.users.iter().any(|u| u == user))
+            .ok_or(RoomError::NotStarted)?;
+        let room_name = room.name.clone();
+        let (message, accepted) = match room.surrender_vote(user, vote)? {
+            SurrenderVote::Pending(count) => (S2CMessage::SurrenderVote(user.to_string(), true, count), false),
+            SurrenderVote::Rejected => (S2CMessage::SurrenderVote(user.to_string(), false, 0), false),
+            SurrenderVote::Accepted(win) => {
+                room.state = RoomState::WaitingForRematch(0);
+                (S2CMessage::GameErr(win), true)
+            }
+        };
+        for player in room.users.iter() {
+            self.network.send_to_user(player, &message);
+        }
+
+        if accepted {
+            if let Some(reveal) = self.reveal_seed(&room_name) {
+                for player in reveal.players.iter() {
+                    self.network.send_to_user(player, &S2CMessage::SeedRevealed(reveal.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 玩家明牌，并把手牌发给房间内所有人
+    pub fn show_hand(&mut self, user: &str) -> Result<(), GameError> {
+        let room = self.rooms
+            .values_mut()
+            .find(|x| x.users.iter().any(|u| u == user))
+            .ok_or(GameError::NotRunning)?;
+        let seat = room.users.iter().position(|x| x == user).unwrap();
+        let multiplier = room.game.show_hand(seat)?;
+        let cards = room.game.players[seat].cards.clone();
+        for player in room.users.iter() {
+            self.network.send_to_user(player, &S2CMessage::HandShown(user.to_string(), cards.clone(), multiplier));
+        }
+        Ok(())
+    }
+
+    /// 断线重连：把玩家视角的牌局发给玩家，并通知房间内其他人
+    fn reconnect(&self, user: &str, room_name: &str) {
+        let room = match self.rooms.get(room_name) {
+            Some(room) => room,
+            None => return,
+        };
+        if let Some(snapshot) = room.snapshot(user) {
+            self.network.send_to_user(user, &S2CMessage::Resync(snapshot, room.seed.commitment()));
+        }
+        for player in room.users.iter().filter(|x| *x != user) {
+            self.network.send_to_user(player, &S2CMessage::PlayerReconnected(user.to_string()));
+        }
+    }
+
+    /// 断线超过房间重连时间的玩家按逃跑结算，并关闭所在的房间
+    pub fn check_escapes(&mut self) {
+        let expired: Vec<UserId> = self.disconnected
+            .iter()
+            .filter(|(_, deadline)| Instant::now() >= **deadline)
+            .map(|(user, _)| user.clone())
+            .collect();
+        for user in expired {
+            self.disconnected.remove(&user);
+            let room_name = match self.user_states.get(&user) {
+                Some(UserState::Playing(room_name)) => room_name.clone(),
+                _ => continue,
+            };
+            if let Some(mut room) = self.rooms.remove(&room_name) {
+                if let Some(escaped @ GameError::Escaped(..)) = room.escape(&user) {
+                    for player in room.users.iter() {
+                        self.network.send_to_user(player, &S2CMessage::GameErr(escaped.clone()));
+                    }
+                }
+                for player in room.users {
+                    self.user_states.insert(player, UserState::Idle);
+                }
+            }
+        }
+    }
+
+    /// 取出所有已结束游戏的统计数据
+    pub fn take_stats(&self) -> Vec<MatchStats> {
+        self.stats_receiver.try_iter().collect()
+    }
+
     pub fn send_packet(&self, packet: MessagePacket) -> Option<SendStatus> {
         match packet.0 {
             MessageTarget::All => {
@@ -181,7 +335,7 @@ impl ServerLobby<'_> {
         } else if room.game.current_player().user != user_id {
             Err(GameError::NotYourTurn)
         } else {
-            room.game.submit_cards(cards)
+            room.submit_cards(cards)
         }
     }
 
@@ -197,12 +351,12 @@ impl ServerLobby<'_> {
                 return Err(Error::from(RoomError::NotLandlordPlayer));
             }
             if choose { // 叫地主
-                room.game.run().expect("Game cannot run");
+                room.game.bid(true).expect("Game cannot run");
                 // 通知所有玩家地主人选
 
                 Ok((MessageTarget::Room(room.name.clone()), S2CMessage::LordCards(room.game.current_player().user.clone(), room.game.landlord_cards.clone())))
             } else { // 不叫
-                room.game.move_landlord_index();
+                room.game.bid(false).expect("Game cannot run");
                 // 通知下一个地主
 
                 Ok((MessageTarget::Room(room.name.clone()), S2CMessage::LandlordMove(room.game.landlord_player().user.clone())))
@@ -259,16 +413,39 @@ impl Lobby for ServerLobby<'_> {
         if !self.user_states.contains_key(&user) {
              self.user_states.insert(user.clone(), UserState::Idle);
         }
-        self.users.push(user);
+        if !self.users.contains(&user) {
+            self.users.push(user);
+        }
     }
 
     fn disconnect(&mut self, user: &str) {
         if let Some(UserState::Playing(game_name)) = self.user_states.get(user) {
-            self.rooms.remove(game_name);
+            // 游戏进行中断线时保留座位，超过重连时间仍未回来按逃跑结算
+            let grace = self.rooms
+                .get(game_name)
+                .filter(|x| x.game.state != GameState::WaitingForPlayers)
+                .map(|x| x.config.reconnect_grace);
+            if let Some(grace) = grace {
+                self.disconnected.insert(user.to_string(), Instant::now() + grace);
+                for player in self.rooms[game_name].users.iter().filter(|x| *x != user) {
+                    self.network.send_to_user(player, &S2CMessage::PlayerDisconnected(user.to_string(), grace));
+                }
+            } else {
+                self.rooms.remove(game_name);
+            }
         }
         if let Some(pos) = self.users.iter().position(|x| x == user) {
             self.users.remove(pos);
             self.network.user_map.remove_by_left(user);
         }
     }
-}
\ No newline at end of file
+}
+
+/// 发完牌后公开发牌前明牌的玩家的手牌
+fn send_shown_hands(network: &NetworkManager, room: &Room) {
+    for (user, cards) in room.shown_hands() {
+        for player in room.users.iter() {
+            network.send_to_user(player, &S2CMessage::HandShown(user.clone(), cards.clone(), room.game.score_multiplier));
+        }
+    }
+}
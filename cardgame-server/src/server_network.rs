@@ -43,7 +43,8 @@ impl NetworkManager<'_> {
     }
 
     pub fn send_to_user(&self, user_id: &str, message: &S2CMessage) -> Option<SendStatus> {
-        let endpoint = self.user_map.get_by_left(user_id).expect("No such client found").clone();
+        // 断线的玩家仍然留在房间里，跳过他们
+        let endpoint = *self.user_map.get_by_left(user_id)?;
         send_to_endpoint(self.controller, message, endpoint)
     }
 
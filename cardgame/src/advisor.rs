@@ -0,0 +1,129 @@
+use crate::card::*;
+use crate::decompose::decompose;
+use crate::game::new_deck;
+use crate::i18n::{format_message, Locale, Localize};
+use serde::{Deserialize, Serialize};
+
+/// 高于这个分数时建议叫地主
+const CALL_THRESHOLD: f64 = -2.0;
+
+/// 分数与阈值相差多少时把握接近 100%
+const CONFIDENCE_SCALE: f64 = 4.0;
+
+/// 手牌强度评估
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct HandEvaluation {
+    /// 最少需要几手出完
+    pub min_plays: u32,
+
+    /// 控制牌数量：2、大小王与炸弹
+    pub controls: u32,
+
+    /// 最少手数拆牌后 A 以下的单牌数量
+    pub weak_singles: u32,
+
+    /// 手牌中控制牌的分值
+    pub control_value: f64,
+
+    /// 3 张地主牌的期望分值
+    pub bottom_value: f64,
+
+    /// 综合得分，越高越适合当地主
+    pub score: f64,
+}
+
+/// 叫地主建议
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct BidAdvice {
+    /// 是否建议叫地主
+    pub call: bool,
+
+    /// 把握，0.5 到 1 之间
+    pub confidence: f64,
+
+    /// 建议所依据的评估
+    pub evaluation: HandEvaluation,
+}
+
+impl Localize for BidAdvice {
+    fn code(&self) -> &'static str {
+        "advisor.hint"
+    }
+
+    fn args(&self, locale: Locale) -> Vec<String> {
+        let code = if self.call { "advisor.call" } else { "advisor.decline" };
+        vec![
+            format_message(locale, code, &[]),
+            format!("{:.0}", self.confidence * 100.0),
+            self.evaluation.min_plays.to_string(),
+            self.evaluation.controls.to_string(),
+            self.evaluation.weak_singles.to_string(),
+        ]
+    }
+}
+
+/// 单张牌作为控制牌的分值
+fn card_value(card: Card) -> f64 {
+    match card {
+        Card::CardKing => 4.0,
+        Card::CardGhost => 3.0,
+        Card::Card2 => 2.0,
+        _ => 0.0,
+    }
+}
+
+/// 评估手牌强度
+/// 17 张手牌时计入地主牌的期望，已经拿到地主牌时 `bottom_value` 为 0
+pub fn evaluate_hand(hand: &[Card]) -> HandEvaluation {
+    let decomposition = decompose(hand);
+    let min_plays = decomposition.len() as u32;
+    let groups = to_card_groups(hand);
+    let bombs = groups.groups.iter().filter(|x| x.count == 4).count() as u32;
+    let controls = hand.iter().filter(|x| card_value(**x) > 0.0).count() as u32 + bombs;
+    let weak_singles = decomposition.singles().iter().filter(|x| **x < Card::CardA).count() as u32;
+    let control_value = hand.iter().map(|x| card_value(*x)).sum::<f64>() + bombs as f64 * 4.0;
+
+    // 地主牌从其余的牌中等概率抽取
+    let bottom_value = if hand.len() <= 17 {
+        let mut unseen = CardStore::from_cards(&new_deck());
+        for card in hand {
+            unseen.remove(card);
+        }
+        let unseen = unseen.get_cards();
+        let total: f64 = unseen.iter().map(|x| card_value(*x)).sum();
+        3.0 * total / unseen.len().max(1) as f64
+    } else {
+        0.0
+    };
+
+    let score = control_value + bottom_value - min_plays as f64 - 0.5 * weak_singles as f64;
+    HandEvaluation {
+        min_plays,
+        controls,
+        weak_singles,
+        control_value,
+        bottom_value,
+        score,
+    }
+}
+
+/// 根据手牌强度给出叫地主建议
+///
+/// # Examples
+///
+/// ```
+/// use cardgame::{advise_bid, parse_input};
+/// let advice = advise_bid(&parse_input("鬼王22223456789JQKA").unwrap());
+/// assert!(advice.call);
+/// let advice = advise_bid(&parse_input("3345567899JJQK6104").unwrap());
+/// assert!(!advice.call);
+/// ```
+pub fn advise_bid(hand: &[Card]) -> BidAdvice {
+    let evaluation = evaluate_hand(hand);
+    let margin = evaluation.score - CALL_THRESHOLD;
+    BidAdvice {
+        call: margin >= 0.0,
+        confidence: 0.5 + 0.5 * (margin.abs() / CONFIDENCE_SCALE).tanh(),
+        evaluation,
+    }
+}
@@ -0,0 +1,86 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::fair::{from_hex, to_hex};
+
+/// 密码哈希的迭代次数
+const HASH_ROUNDS: u32 = 10_000;
+
+/// 用户名的最大长度（字符数）
+pub const MAX_NAME_LENGTH: usize = 16;
+
+/// 加盐的密码哈希，十六进制表示
+/// 由 `UserManager` 保存，不随用户数据发给客户端
+///
+/// # Examples
+///
+/// ```
+/// use cardgame::auth::Credentials;
+/// let credentials = Credentials::new("hunter2");
+/// assert!(credentials.verify("hunter2"));
+/// assert!(!credentials.verify("hunter3"));
+/// ```
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Credentials {
+    salt: String,
+    hash: String,
+}
+
+impl Credentials {
+    pub fn new(password: &str) -> Credentials {
+        Credentials::with_rng(password, &mut rand::thread_rng())
+    }
+
+    pub fn with_rng<R: Rng + ?Sized>(password: &str, rng: &mut R) -> Credentials {
+        let mut salt = [0u8; 16];
+        rng.fill(&mut salt);
+        Credentials {
+            salt: to_hex(&salt),
+            hash: to_hex(&hash_password(password, &salt)),
+        }
+    }
+
+    /// 密码是否正确
+    pub fn verify(&self, password: &str) -> bool {
+        let (salt, hash) = match (from_hex(&self.salt), from_hex(&self.hash)) {
+            (Some(salt), Some(hash)) => (salt, hash),
+            _ => return false,
+        };
+        // 逐字节比较全部内容，避免通过耗时猜测哈希
+        let actual = hash_password(password, &salt);
+        actual.len() == hash.len() && actual.iter().zip(hash.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+    }
+}
+
+/// 迭代 `HASH_ROUNDS` 次的加盐 SHA-256
+fn hash_password(password: &str, salt: &[u8]) -> Vec<u8> {
+    let mut hash = Sha256::new().chain_update(salt).chain_update(password.as_bytes()).finalize();
+    for _ in 1..HASH_ROUNDS {
+        hash = Sha256::new().chain_update(salt).chain_update(hash).finalize();
+    }
+    hash.to_vec()
+}
+
+/// 生成新的会话令牌
+pub fn new_session_token() -> String {
+    let mut token = [0u8; 32];
+    rand::thread_rng().fill(&mut token);
+    to_hex(&token)
+}
+
+/// 用户名是否可以注册：不为空，不超过 `MAX_NAME_LENGTH` 个字符，只包含文字、数字与下划线
+/// 用户名同时用作用户数据的文件名，不能包含路径分隔符
+///
+/// # Examples
+///
+/// ```
+/// use cardgame::auth::is_valid_name;
+/// assert!(is_valid_name("地主_01"));
+/// assert!(!is_valid_name(""));
+/// assert!(!is_valid_name("../users"));
+/// ```
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().count() <= MAX_NAME_LENGTH
+        && name.chars().all(|x| x.is_alphanumeric() || x == '_')
+}
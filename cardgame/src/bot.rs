@@ -0,0 +1,137 @@
+use crate::advisor::advise_bid;
+use crate::card::*;
+use crate::game::*;
+use crate::rule::*;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+/// 轮到电脑出牌时能看到的信息
+pub struct Turn<'a> {
+    /// 手牌
+    pub hand: &'a [Card],
+
+    /// 需要压过的牌，自由出牌时为 None
+    pub last: Option<&'a [Card]>,
+
+    /// 自己是否为地主
+    pub is_landlord: bool,
+
+    /// 最后出牌的是否为队友
+    pub partner_played: bool,
+
+    /// 自己、下家、上家的剩余手牌数
+    pub remaining: [usize; 3],
+}
+
+impl<'a> Turn<'a> {
+    /// 当前出牌玩家的视角
+    pub fn from_game(game: &'a Game) -> Turn<'a> {
+        let seat = game.index;
+        let leading = game.index == game.last_index || game.last_cards.is_empty();
+        let is_landlord = seat == game.landlord_index;
+        let last_is_landlord = game.last_index == game.landlord_index;
+        Turn {
+            hand: &game.players[seat].cards,
+            last: if leading { None } else { Some(&game.last_cards) },
+            is_landlord,
+            partner_played: !leading && !is_landlord && !last_is_landlord,
+            remaining: [
+                game.players[seat].cards.len(),
+                game.players[(seat + 1) % 3].cards.len(),
+                game.players[(seat + 2) % 3].cards.len(),
+            ],
+        }
+    }
+
+    /// 所有可以出的牌
+    pub fn plays(&self) -> Vec<Vec<Card>> {
+        match self.last {
+            None => all_plays(self.hand),
+            Some(last) => valid_plays(&*match_rule(last), self.hand),
+        }
+    }
+}
+
+/// 电脑玩家
+pub trait Bot: Send {
+    /// 是否叫地主
+    fn bid(&mut self, hand: &[Card]) -> bool;
+
+    /// 出牌，返回 None 表示过牌
+    /// 自由出牌时必须出牌
+    fn play(&mut self, turn: &Turn) -> Option<Vec<Card>>;
+}
+
+/// 根据名称创建电脑玩家
+///
+/// * `random`: 随机出牌
+/// * `greedy`: 总是出最小的牌
+pub fn new_bot(name: &str, seed: u64) -> Option<Box<dyn Bot>> {
+    match name {
+        "random" => Some(Box::new(RandomBot::new(seed))),
+        "greedy" => Some(Box::new(GreedyBot)),
+        _ => None,
+    }
+}
+
+/// 在所有合法操作中随机选择
+pub struct RandomBot {
+    rng: StdRng,
+}
+
+impl RandomBot {
+    pub fn new(seed: u64) -> RandomBot {
+        RandomBot {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Bot for RandomBot {
+    fn bid(&mut self, _hand: &[Card]) -> bool {
+        self.rng.gen_bool(0.5)
+    }
+
+    fn play(&mut self, turn: &Turn) -> Option<Vec<Card>> {
+        let plays = turn.plays();
+        if turn.last.is_some() && self.rng.gen_range(0..=plays.len()) == 0 {
+            return None;
+        }
+        plays.choose(&mut self.rng).cloned()
+    }
+}
+
+/// 按手牌强度叫地主，总是出能出的最小的牌，不压队友，炸弹留到最后
+pub struct GreedyBot;
+
+impl GreedyBot {
+    /// 牌型越小越先出，同样大小时先出张数多的
+    fn weight(play: &[Card]) -> (u32, u32, usize) {
+        let rule = match_rule(play);
+        let lowest = play.iter().map(|x| x.value()).min().unwrap_or(0);
+        (rule.bomb_priority(), lowest, 20 - play.len())
+    }
+}
+
+impl Bot for GreedyBot {
+    fn bid(&mut self, hand: &[Card]) -> bool {
+        advise_bid(hand).call
+    }
+
+    fn play(&mut self, turn: &Turn) -> Option<Vec<Card>> {
+        let mut plays = turn.plays();
+        if let Some(finish) = plays.iter().find(|x| x.len() == turn.hand.len()) {
+            return Some(finish.clone());
+        }
+        if turn.partner_played {
+            return None;
+        }
+        plays.sort_by_key(|x| GreedyBot::weight(x));
+        let play = plays.into_iter().next()?;
+        if turn.last.is_some() && match_rule(&play).bomb_priority() > 0 && turn.remaining.iter().skip(1).all(|x| *x > 4) {
+            return None;
+        }
+        Some(play)
+    }
+}
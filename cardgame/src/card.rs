@@ -2,7 +2,10 @@ use num_derive::{FromPrimitive, ToPrimitive};
 use num_traits::{FromPrimitive, ToPrimitive};
 use serde::{Deserialize, Serialize};
 use std::cmp::{Ord, Ordering, PartialEq, PartialOrd};
+use std::fmt::{Display, Formatter};
 use std::slice::Iter;
+use std::str::FromStr;
+use crate::error::ParseCardsError;
 
 #[derive(Eq, Copy, Clone, FromPrimitive, ToPrimitive, Serialize, Deserialize, Debug)]
 pub enum Card {
@@ -100,6 +103,131 @@ impl Card {
     }
 }
 
+/// 牌的写法，不区分大小写，较长的写法排在前面以便优先匹配
+const CARD_TOKENS: [(&str, Card); 22] = [
+    ("10", Card::Card10),
+    ("BJ", Card::CardGhost),
+    ("RJ", Card::CardKing),
+    ("小王", Card::CardGhost),
+    ("大王", Card::CardKing),
+    ("3", Card::Card3),
+    ("4", Card::Card4),
+    ("5", Card::Card5),
+    ("6", Card::Card6),
+    ("7", Card::Card7),
+    ("8", Card::Card8),
+    ("9", Card::Card9),
+    ("T", Card::Card10),
+    ("J", Card::CardJ),
+    ("Q", Card::CardQ),
+    ("K", Card::CardK),
+    ("A", Card::CardA),
+    ("2", Card::Card2),
+    ("X", Card::CardGhost),
+    ("D", Card::CardKing),
+    ("鬼", Card::CardGhost),
+    ("王", Card::CardKing),
+];
+
+/// 牌之间可以使用的分隔符
+const CARD_SEPARATORS: [char; 7] = [',', '，', '、', ';', '；', '[', ']'];
+
+/// 依次解析输入中的牌
+/// `lenient` 为 true 时跳过无法识别的字符，否则返回第一个无法识别的字符
+pub(crate) fn parse_card_list(input: &str, lenient: bool) -> Result<Vec<Card>, ParseCardsError> {
+    let mut cards = vec![];
+    let mut rest = input;
+    let mut position = 0;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() || CARD_SEPARATORS.contains(&c) {
+            rest = &rest[c.len_utf8()..];
+            position += 1;
+            continue;
+        }
+
+        let token = CARD_TOKENS
+            .iter()
+            .find(|(token, _)| rest.get(..token.len()).is_some_and(|x| x.eq_ignore_ascii_case(token)));
+        match token {
+            Some((token, card)) => {
+                cards.push(*card);
+                rest = &rest[token.len()..];
+                position += token.chars().count();
+            }
+            None if lenient => {
+                rest = &rest[c.len_utf8()..];
+                position += 1;
+            }
+            None => {
+                return Err(ParseCardsError {
+                    token: c.to_string(),
+                    position,
+                })
+            }
+        }
+    }
+    Ok(cards)
+}
+
+/// 一组牌的文本形式
+///
+/// 解析时不区分大小写，10 可以写作 `10` 或 `T`，小王可以写作 `鬼`、`小王`、`X` 或 `BJ`，
+/// 大王可以写作 `王`、`大王`、`D` 或 `RJ`，牌之间可以用空格、逗号、顿号、分号或方括号分隔。
+/// 显示时按原顺序输出，不加分隔符，解析后得到相同的牌。
+///
+/// ```
+/// use cardgame::{Card, CardList};
+/// let list: CardList = "10 j q k a".parse().unwrap();
+/// assert_eq!(list.to_string(), "10JQKA");
+/// assert_eq!("[X][RJ]".parse::<CardList>().unwrap().0, vec![Card::CardGhost, Card::CardKing]);
+///
+/// let error = "33P".parse::<CardList>().unwrap_err();
+/// assert_eq!((error.token.as_str(), error.position), ("P", 2));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CardList(pub Vec<Card>);
+
+impl FromStr for CardList {
+    type Err = ParseCardsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_card_list(s, false).map(CardList)
+    }
+}
+
+impl CardList {
+    /// 宽松地解析，跳过无法识别的字符，例如从聊天记录中粘贴的牌
+    ///
+    /// ```
+    /// use cardgame::CardList;
+    /// assert_eq!(CardList::parse_lenient("出 3、4 和 5").to_string(), "345");
+    /// ```
+    pub fn parse_lenient(input: &str) -> CardList {
+        CardList(parse_card_list(input, true).unwrap_or_default())
+    }
+}
+
+impl Display for CardList {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for card in self.0.iter() {
+            write!(f, "{}", card.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Vec<Card>> for CardList {
+    fn from(cards: Vec<Card>) -> Self {
+        CardList(cards)
+    }
+}
+
+impl From<CardList> for Vec<Card> {
+    fn from(list: CardList) -> Self {
+        list.0
+    }
+}
+
 #[derive(Eq, Clone, Default, Copy)]
 pub struct CardGroup {
     pub card: Card,
@@ -139,8 +267,8 @@ impl std::ops::Sub<CardGroups> for CardGroups {
         let mut groups = CardGroups {
             groups: self.groups,
         };
-        for c in groups.groups.iter_mut() {
-            let group = rhs.find_group_by_card(c.card)?;
+        for group in rhs.groups.iter() {
+            let c = groups.groups.iter_mut().find(|x| x.card == group.card)?;
             if c.count < group.count {
                 return None;
             }
@@ -210,12 +338,44 @@ pub fn to_card_groups(vec: &[Card]) -> CardGroups {
 
 /// Compact card storage
 /// A deck has only 54 cards so 63 bits are more than adequate
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct CardStore {
     pub raw: i64
 }
 
 impl CardStore {
+    pub fn from_cards(cards: &[Card]) -> CardStore {
+        let mut store = CardStore::default();
+        for card in cards.iter() {
+            store.add(card);
+        }
+        store
+    }
+
+    /// 移除一张牌，若不存在则返回 false
+    pub fn remove(&mut self, card: &Card) -> bool {
+        if *card == Card::Unknown {
+            return false;
+        }
+        let offset: i64 = ((card.value() - 1) * 4) as i64;
+        let count = self.get_card_count(card);
+        if count == 0 {
+            return false;
+        }
+        self.raw &= !(15i64 << offset);
+        self.raw |= (count - 1) << offset;
+        true
+    }
+
+    /// 牌的总张数
+    pub fn len(&self) -> usize {
+        self.get_card_groups().iter().map(|x| x.count as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.raw == 0
+    }
+
     pub fn get_card_count(&self, card: &Card) -> i64 {
         let mask = 15;
         let offset: i64 = ((card.value() - 1) * 4) as i64;
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use crate::card::*;
+use crate::rule::*;
+
+/// 拆牌的代价，按字段顺序比较，越小越好
+/// 各字段都可以相加，所以子问题的最优解组合起来仍是最优解
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+struct Cost {
+    /// 手数
+    plays: u32,
+
+    /// 拆散的炸弹与火箭，保留得越多越好
+    broken_bombs: u32,
+
+    /// 单牌数
+    singles: u32,
+
+    /// 对子数
+    pairs: u32,
+
+    /// 单牌与对子的大小，越小的牌代价越高
+    weakness: u32,
+}
+
+impl std::ops::Add for Cost {
+    type Output = Cost;
+    fn add(self, rhs: Cost) -> Cost {
+        Cost {
+            plays: self.plays + rhs.plays,
+            broken_bombs: self.broken_bombs + rhs.broken_bombs,
+            singles: self.singles + rhs.singles,
+            pairs: self.pairs + rhs.pairs,
+            weakness: self.weakness + rhs.weakness,
+        }
+    }
+}
+
+impl Cost {
+    fn of(play: &[Card], hand: &CardStore) -> Cost {
+        let rule = match_rule(play);
+        let weakness = Card::CardKing.value() + 1 - rule.rank().value();
+        let mut cost = Cost {
+            plays: 1,
+            ..Default::default()
+        };
+        match rule.kind() {
+            RuleKind::One => {
+                cost.singles = 1;
+                cost.weakness = weakness;
+            }
+            RuleKind::Two => {
+                cost.pairs = 1;
+                cost.weakness = weakness;
+            }
+            _ => {}
+        }
+
+        // 炸弹的牌没有作为炸弹打出，或者拆了火箭
+        let bomb_broken = rule.kind() != RuleKind::Bomb
+            && play.iter().any(|x| hand.get_card_count(x) == 4);
+        let has_rocket = hand.get_card_count(&Card::CardGhost) > 0 && hand.get_card_count(&Card::CardKing) > 0;
+        let rocket_broken = has_rocket
+            && rule.kind() != RuleKind::Rocket
+            && play.iter().any(|x| *x == Card::CardGhost || *x == Card::CardKing);
+        cost.broken_bombs = bomb_broken as u32 + rocket_broken as u32;
+        cost
+    }
+}
+
+/// 把手牌拆成若干手合法的出牌
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Decomposition {
+    /// 每一手出牌，按拆出的顺序排列
+    pub plays: Vec<Vec<Card>>,
+}
+
+impl Decomposition {
+    /// 手数
+    pub fn len(&self) -> usize {
+        self.plays.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plays.is_empty()
+    }
+
+    /// 拆出的单牌
+    pub fn singles(&self) -> Vec<Card> {
+        self.plays.iter().filter(|x| x.len() == 1).map(|x| x[0]).collect()
+    }
+
+    /// 拆出的某种牌型
+    pub fn plays_of(&self, kind: RuleKind) -> Vec<&Vec<Card>> {
+        self.plays.iter().filter(|x| match_rule(x).kind() == kind).collect()
+    }
+}
+
+impl Display for Decomposition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let plays: Vec<String> = self.plays
+            .iter()
+            .map(|x| CardList(x.clone()).to_string())
+            .collect();
+        write!(f, "{}", plays.join(" "))
+    }
+}
+
+/// 最少手数拆牌
+/// 每次只考虑包含最小的一张牌的出牌，用置换表记录剩余手牌的最优拆法
+///
+/// 手数相同时依次比较：
+/// 1. 拆散的炸弹与火箭更少
+/// 2. 单牌更少
+/// 3. 对子更少
+/// 4. 单牌与对子更大
+pub struct Decomposer {
+    /// 置换表，记录剩余手牌的最优代价与第一手出牌
+    memo: HashMap<CardStore, (Cost, Vec<Card>)>,
+}
+
+impl Decomposer {
+    pub fn new() -> Decomposer {
+        Decomposer {
+            memo: HashMap::new(),
+        }
+    }
+
+    /// # Examples
+    ///
+    /// ```
+    /// use cardgame::{parse_input, Decomposer};
+    /// let decomposition = Decomposer::new().decompose(&parse_input("345678JJJQ").unwrap());
+    /// assert_eq!(decomposition.len(), 2);
+    /// assert_eq!(decomposition.to_string(), "345678 JJJQ");
+    /// ```
+    pub fn decompose(&mut self, hand: &[Card]) -> Decomposition {
+        let mut store = CardStore::from_cards(hand);
+        self.search(store);
+
+        let mut plays = vec![];
+        while !store.is_empty() {
+            let (_, play) = self.memo[&store].clone();
+            for card in play.iter() {
+                store.remove(card);
+            }
+            plays.push(play);
+        }
+        Decomposition { plays }
+    }
+
+    /// 最少需要几手出完
+    pub fn min_plays(&mut self, hand: &[Card]) -> u32 {
+        self.search(CardStore::from_cards(hand)).plays
+    }
+
+    fn search(&mut self, store: CardStore) -> Cost {
+        if store.is_empty() {
+            return Cost::default();
+        }
+        if let Some((cost, _)) = self.memo.get(&store) {
+            return *cost;
+        }
+
+        let cards = store.get_cards();
+        let lowest = cards[0];
+        let mut best: Option<(Cost, Vec<Card>)> = None;
+        for play in all_plays(&cards).into_iter().filter(|x| x.contains(&lowest)) {
+            let mut rest = store;
+            for card in play.iter() {
+                rest.remove(card);
+            }
+            let cost = Cost::of(&play, &store) + self.search(rest);
+            if best.as_ref().is_none_or(|(x, _)| cost < *x) {
+                best = Some((cost, play));
+            }
+        }
+
+        // 手中的牌总能作为单牌打出
+        let best = best.expect("A single card is always a valid play");
+        let cost = best.0;
+        self.memo.insert(store, best);
+        cost
+    }
+}
+
+impl Default for Decomposer {
+    fn default() -> Self {
+        Decomposer::new()
+    }
+}
+
+/// 使用新的置换表拆牌
+pub fn decompose(hand: &[Card]) -> Decomposition {
+    Decomposer::new().decompose(hand)
+}
@@ -0,0 +1,108 @@
+use crate::card::*;
+use crate::rule::*;
+use serde::{Deserialize, Serialize};
+
+/// 出牌被拒绝时的诊断信息
+/// 随 `GameError::NoRule` 与 `GameError::WrongRule` 一起返回，帮助新手理解规则
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct PlayDiagnostic {
+    /// 出的牌
+    pub cards: Vec<Card>,
+
+    /// 识别出的牌型，无法识别时为最接近的牌型
+    pub kind: RuleKind,
+
+    /// 出的牌是否完全符合 `kind`
+    pub recognized: bool,
+
+    /// 需要压过的牌，自由出牌时为空
+    pub last: Vec<Card>,
+
+    /// 需要压过的牌型
+    pub last_kind: RuleKind,
+
+    /// 需要压过的点数
+    pub last_rank: Card,
+
+    /// 需要压过的顺子、连对或飞机的长度
+    pub last_length: u32,
+
+    /// 手牌中能压过上一手牌的最小的炸弹或火箭
+    pub bomb: Option<Vec<Card>>,
+}
+
+impl PlayDiagnostic {
+    /// # Arguments
+    ///
+    /// * `cards`: 出的牌
+    /// * `last`: 需要压过的牌，自由出牌时为空
+    /// * `hand`: 出牌玩家的手牌
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cardgame::{parse_input, PlayDiagnostic, RuleKind, Card};
+    /// let hand = parse_input("3344445").unwrap();
+    /// let diagnostic = PlayDiagnostic::new(&parse_input("33").unwrap(), &parse_input("KK").unwrap(), &hand);
+    /// assert_eq!(diagnostic.kind, RuleKind::Two);
+    /// assert_eq!((diagnostic.last_kind, diagnostic.last_rank), (RuleKind::Two, Card::CardK));
+    /// assert_eq!(diagnostic.bomb, parse_input("4444"));
+    /// ```
+    pub fn new(cards: &[Card], last: &[Card], hand: &[Card]) -> PlayDiagnostic {
+        let rule = match_rule(cards);
+        let mut diagnostic = PlayDiagnostic {
+            cards: cards.to_vec(),
+            kind: closest_kind(cards),
+            recognized: !rule.is_none(),
+            ..Default::default()
+        };
+
+        if !last.is_empty() {
+            let last_rule = match_rule(last);
+            diagnostic.last = last.to_vec();
+            diagnostic.last_kind = last_rule.kind();
+            diagnostic.last_rank = last_rule.rank();
+            diagnostic.last_length = last_rule.length();
+            diagnostic.bomb = valid_plays(&*last_rule, hand)
+                .into_iter()
+                .filter(|x| match_rule(x).bomb_priority() > 0)
+                .min_by_key(|x| {
+                    let rule = match_rule(x);
+                    (rule.bomb_priority(), rule.rank())
+                });
+        }
+        diagnostic
+    }
+}
+
+/// 出的牌所属的牌型，不符合任何牌型时猜测最接近的一种
+pub fn closest_kind(cards: &[Card]) -> RuleKind {
+    let rule = match_rule(cards);
+    if !rule.is_none() {
+        return rule.kind();
+    }
+
+    let groups = to_card_groups(cards);
+    let count_of = |count: u32| groups.groups.iter().filter(|x| x.count == count).count();
+    let max = groups.groups.iter().map(|x| x.count).max().unwrap_or(0);
+    match max {
+        0 => RuleKind::None,
+        1 if cards.len() >= 3 => RuleKind::Chain,
+        1 => RuleKind::One,
+        2 if count_of(2) >= 2 => RuleKind::PairChain,
+        2 => RuleKind::Two,
+        3 if count_of(3) >= 2 => {
+            if count_of(3) == groups.type_len() {
+                RuleKind::TripleChain
+            } else if count_of(1) == 0 {
+                RuleKind::AirplaneWithTwoWings
+            } else {
+                RuleKind::AirplaneWithOneWing
+            }
+        }
+        3 if cards.len() <= 4 => RuleKind::ThreeWithOne,
+        3 => RuleKind::ThreeWithTwo,
+        _ if cards.len() <= 5 => RuleKind::Bomb,
+        _ => RuleKind::FourWithTwo,
+    }
+}
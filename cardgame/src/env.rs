@@ -0,0 +1,392 @@
+use crate::bot::{Bot, Turn};
+use crate::card::*;
+use crate::diagnostic::PlayDiagnostic;
+use crate::error::GameError;
+use crate::game::*;
+use crate::lobby::RoomConfig;
+use crate::player::{Player, PlayerType};
+use crate::rule::*;
+use crate::stats::Settlement;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::HashMap;
+
+/// 过牌
+pub const ACTION_PASS: usize = 0;
+
+/// 叫地主
+pub const ACTION_CALL: usize = 1;
+
+/// 不叫
+pub const ACTION_DECLINE: usize = 2;
+
+/// 第一个出牌动作的编号
+pub const ACTION_PLAY_OFFSET: usize = 3;
+
+/// 点数的种类数，3 到大王
+const RANKS: usize = 15;
+
+/// 观测向量长度
+///
+/// | 偏移 | 长度 | 内容 |
+/// |-----|------|-----|
+/// | 0   | 15   | 自己的手牌 |
+/// | 15  | 45   | 自己、下家、上家已出的牌 |
+/// | 60  | 15   | 未出现的牌（不含自己的手牌） |
+/// | 75  | 15   | 桌面上最后出的牌 |
+/// | 90  | 4    | 最后出牌者（自己、下家、上家）以及是否可以任意出牌 |
+/// | 94  | 3    | 自己、下家、上家的剩余手牌数 |
+/// | 97  | 3    | 地主是自己、下家还是上家 |
+/// | 100 | 1    | 是否处于叫地主阶段 |
+/// | 101 | 1    | 积分倍率 |
+/// | 102 | 15   | 地主牌（叫地主后公开） |
+/// | 117 | 30   | 下家、上家明牌时的手牌，没有明牌时为 0 |
+///
+/// 张数均除以 4，手牌数除以 20，倍率取以 2 为底的对数后除以 16
+pub const OBSERVATION_SIZE: usize = 147;
+
+/// 单步结果
+#[derive(Clone, Debug)]
+pub struct Step {
+    /// 下一个行动座位的观测
+    pub observation: Vec<f32>,
+
+    /// 下一个行动座位的合法动作
+    pub mask: Vec<bool>,
+
+    /// 下一个行动的座位
+    pub seat: usize,
+
+    /// 每个座位本步获得的奖励，仅在游戏结束时非零
+    pub rewards: [f32; 3],
+
+    /// 游戏是否结束
+    pub done: bool,
+}
+
+/// 强化学习环境
+///
+/// 动作空间为固定的编号：0 为过牌，1 为叫地主，2 为不叫，
+/// 其后为一副牌中所有合法的出牌组合。
+/// 内部直接驱动 `Game`，因此规则与服务器完全一致，奖励即按房间设置结算的积分。
+/// 三家都不叫时本局结束，所有座位的奖励为 0。
+///
+/// # Examples
+///
+/// ```
+/// use cardgame::Env;
+/// let mut env = Env::new();
+/// let mut step = env.reset(42);
+/// while !step.done {
+///     let action = step.mask.iter().position(|x| *x).unwrap();
+///     step = env.step(action).unwrap();
+/// }
+/// assert_eq!(step.rewards.iter().sum::<f32>(), 0.0);
+/// ```
+pub struct Env {
+    game: Game,
+
+    /// 房间设置，每次 `reset` 时应用到新的牌局
+    config: RoomConfig,
+
+    /// 本局连续不叫的次数
+    declines: u32,
+
+    /// 出牌动作，下标为动作编号减去 `ACTION_PLAY_OFFSET`
+    actions: Vec<Vec<Card>>,
+
+    /// 出牌组合到动作编号的映射
+    action_index: HashMap<CardStore, usize>,
+
+    /// 每个座位已出的牌
+    played: [CardStore; 3],
+
+    done: bool,
+}
+
+impl Env {
+    pub fn new() -> Env {
+        Env::with_config(RoomConfig::default())
+    }
+
+    pub fn with_config(config: RoomConfig) -> Env {
+        let mut deck = gen_cards();
+        deck.sort();
+        let mut actions: Vec<Vec<Card>> = all_plays(&deck)
+            .into_iter()
+            .filter(|x| x.len() <= 20)
+            .collect();
+        actions.sort_by(|a, b| a.len().cmp(&b.len()).then(a.cmp(b)));
+
+        let action_index = actions
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (CardStore::from_cards(x), i + ACTION_PLAY_OFFSET))
+            .collect();
+
+        Env {
+            game: Game::new(),
+            config,
+            declines: 0,
+            actions,
+            action_index,
+            played: [CardStore::default(); 3],
+            done: true,
+        }
+    }
+
+    /// 动作总数
+    pub fn action_count(&self) -> usize {
+        self.actions.len() + ACTION_PLAY_OFFSET
+    }
+
+    /// 出牌动作对应的牌
+    pub fn action_cards(&self, action: usize) -> Option<&[Card]> {
+        if action < ACTION_PLAY_OFFSET {
+            None
+        } else {
+            self.actions.get(action - ACTION_PLAY_OFFSET).map(|x| x.as_slice())
+        }
+    }
+
+    /// 出牌组合对应的动作编号
+    pub fn action_of(&self, cards: &[Card]) -> Option<usize> {
+        self.action_index.get(&CardStore::from_cards(cards)).copied()
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// 重新发牌，相同的种子总会得到相同的牌局
+    pub fn reset(&mut self, seed: u64) -> Step {
+        self.game = Game::new();
+        self.config.apply(&mut self.game);
+        for i in 0..3 {
+            self.game.add_player(Player::new(i.to_string()));
+        }
+        self.game
+            .start_with_rng(&mut StdRng::seed_from_u64(seed))
+            .map(|_| ())
+            .expect("Cannot start game");
+        self.played = [CardStore::default(); 3];
+        self.declines = 0;
+        self.done = false;
+        self.make_step([0.0; 3])
+    }
+
+    /// 座位在叫地主时明牌，之后其他座位的观测包含它的手牌
+    pub fn show_hand(&mut self, seat: usize) -> Result<Step, GameError> {
+        self.game.show_hand(seat)?;
+        Ok(self.make_step([0.0; 3]))
+    }
+
+    /// 让电脑玩家为当前座位选择动作
+    pub fn bot_action(&self, bot: &mut dyn Bot) -> usize {
+        match self.game.state {
+            GameState::WaitingForLandlord => {
+                if bot.bid(&self.game.landlord_player().cards) {
+                    ACTION_CALL
+                } else {
+                    ACTION_DECLINE
+                }
+            }
+            _ => match bot.play(&Turn::from_game(&self.game)) {
+                Some(cards) => self.action_of(&cards).unwrap_or(ACTION_PASS),
+                None => ACTION_PASS,
+            },
+        }
+    }
+
+    /// 当前应当行动的座位
+    pub fn current_seat(&self) -> usize {
+        if self.game.state == GameState::WaitingForLandlord {
+            self.game.landlord_index
+        } else {
+            self.game.index
+        }
+    }
+
+    /// 执行动作
+    /// 非法动作会返回游戏错误，环境状态不变
+    pub fn step(&mut self, action: usize) -> Result<Step, GameError> {
+        if self.done {
+            return Err(GameError::NotRunning);
+        }
+        let seat = self.current_seat();
+
+        match self.game.state {
+            GameState::WaitingForLandlord => match action {
+                ACTION_CALL => self.game.bid(true)?,
+                ACTION_DECLINE => {
+                    self.game.bid(false)?;
+                    self.declines += 1;
+                    if self.declines == 3 {
+                        self.done = true;
+                    }
+                }
+                _ => return Err(GameError::NotRunning),
+            },
+            GameState::Running => {
+                let result = if action == ACTION_PASS {
+                    self.game.pass()
+                } else {
+                    let cards = self.action_cards(action).ok_or_else(|| GameError::NoRule(PlayDiagnostic::default()))?.to_vec();
+                    let result = self.game.submit_cards(cards.clone());
+                    if result.is_ok() || matches!(result, Err(GameError::Win(_, _, _))) {
+                        for c in cards.iter() {
+                            self.played[seat].add(c);
+                        }
+                    }
+                    result
+                };
+
+                match result {
+                    Ok(_) => {}
+                    Err(GameError::Win(_, player_type, settlement)) => {
+                        self.done = true;
+                        return Ok(self.make_step(self.rewards(&player_type, &settlement)));
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            GameState::WaitingForPlayers => return Err(GameError::NotRunning),
+        }
+
+        Ok(self.make_step([0.0; 3]))
+    }
+
+    /// 胜负结算，与房间的积分相同，地主的得失为农民的两倍
+    fn rewards(&self, winner: &PlayerType, settlement: &Settlement) -> [f32; 3] {
+        let score = settlement.points as f32;
+        let mut rewards = [0.0; 3];
+        for (i, reward) in rewards.iter_mut().enumerate() {
+            let is_landlord = i == self.game.landlord_index;
+            let won = is_landlord == (*winner == PlayerType::Landlord);
+            let amount = if is_landlord { score * 2.0 } else { score };
+            *reward = if won { amount } else { -amount };
+        }
+        rewards
+    }
+
+    fn make_step(&self, rewards: [f32; 3]) -> Step {
+        let seat = self.current_seat();
+        Step {
+            observation: self.observation(seat),
+            mask: self.mask(),
+            seat,
+            rewards,
+            done: self.done,
+        }
+    }
+
+    /// 当前行动座位的合法动作
+    pub fn mask(&self) -> Vec<bool> {
+        let mut mask = vec![false; self.action_count()];
+        if self.done {
+            return mask;
+        }
+        match self.game.state {
+            GameState::WaitingForLandlord => {
+                mask[ACTION_CALL] = true;
+                mask[ACTION_DECLINE] = true;
+            }
+            GameState::Running => {
+                let hand = &self.game.current_player().cards;
+                let plays = if self.game.index == self.game.last_index {
+                    all_plays(hand)
+                } else {
+                    mask[ACTION_PASS] = true;
+                    valid_plays(&*self.game.last_rule, hand)
+                };
+                for play in plays.iter() {
+                    if let Some(i) = self.action_of(play) {
+                        mask[i] = true;
+                    }
+                }
+            }
+            GameState::WaitingForPlayers => {}
+        }
+        mask
+    }
+
+    /// 指定座位能看到的信息，不包含其他玩家未明牌的手牌
+    pub fn observation(&self, seat: usize) -> Vec<f32> {
+        let mut obs = Vec::with_capacity(OBSERVATION_SIZE);
+        let relative = |i: usize| (i + 3 - seat) % 3;
+        let seats = [seat, (seat + 1) % 3, (seat + 2) % 3];
+
+        let hand = CardStore::from_cards(&self.game.players[seat].cards);
+        push_counts(&mut obs, &hand);
+        for i in seats.iter() {
+            push_counts(&mut obs, &self.played[*i]);
+        }
+
+        let mut unseen = [4i64; RANKS];
+        unseen[RANKS - 2] = 1;
+        unseen[RANKS - 1] = 1;
+        for store in self.played.iter().chain(std::iter::once(&hand)) {
+            for (i, group) in store.get_card_groups().iter().enumerate() {
+                unseen[i] -= group.count as i64;
+            }
+        }
+        obs.extend(unseen.iter().map(|x| *x as f32 / 4.0));
+
+        let running = self.game.state == GameState::Running;
+        let leading = !running || self.game.index == self.game.last_index;
+        if leading {
+            push_counts(&mut obs, &CardStore::default());
+        } else {
+            push_counts(&mut obs, &CardStore::from_cards(&self.game.last_cards));
+        }
+        let mut last = [0.0; 4];
+        if leading {
+            last[3] = 1.0;
+        } else {
+            last[relative(self.game.last_index)] = 1.0;
+        }
+        obs.extend_from_slice(&last);
+
+        for i in seats.iter() {
+            obs.push(self.game.players[*i].cards.len() as f32 / 20.0);
+        }
+
+        let mut landlord = [0.0; 3];
+        if running || self.done {
+            landlord[relative(self.game.landlord_index)] = 1.0;
+        }
+        obs.extend_from_slice(&landlord);
+
+        obs.push(if self.game.state == GameState::WaitingForLandlord { 1.0 } else { 0.0 });
+        obs.push((self.game.score_multiplier as f32).log2() / 16.0);
+
+        if running || self.done {
+            push_counts(&mut obs, &CardStore::from_cards(&self.game.landlord_cards));
+        } else {
+            push_counts(&mut obs, &CardStore::default());
+        }
+
+        for i in seats[1..].iter() {
+            let player = &self.game.players[*i];
+            if player.shown {
+                push_counts(&mut obs, &CardStore::from_cards(&player.cards));
+            } else {
+                push_counts(&mut obs, &CardStore::default());
+            }
+        }
+
+        obs
+    }
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        Env::new()
+    }
+}
+
+fn push_counts(obs: &mut Vec<f32>, store: &CardStore) {
+    for group in store.get_card_groups().iter() {
+        obs.push(group.count as f32 / 4.0);
+    }
+}
@@ -1,6 +1,7 @@
 use std::fmt::{Debug, Display, Formatter};
 use serde::{Serialize, Deserialize};
-use crate::PlayerType;
+use crate::{Card, CardList, PlayDiagnostic, PlayerType, Settlement};
+use crate::i18n::{format_message, Locale, Localize};
 use crate::user::UserId;
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, thiserror::Error)]
@@ -16,6 +17,12 @@ pub enum RoomError {
 
     /// 房间已满
     RoomFull,
+
+    /// 没有进行中的投降投票
+    NoSurrenderVote,
+
+    /// 只有发起投降的一方可以否决投降
+    NotConcedingSide,
 }
 
 impl Debug for RoomError {
@@ -33,26 +40,32 @@ impl Debug for RoomError {
             RoomError::RoomFull => {
                 write!(f, "RoomError::RoomFull")
             }
+            RoomError::NoSurrenderVote => {
+                write!(f, "RoomError::NoSurrenderVote")
+            }
+            RoomError::NotConcedingSide => {
+                write!(f, "RoomError::NotConcedingSide")
+            }
+        }
+    }
+}
+
+impl Localize for RoomError {
+    fn code(&self) -> &'static str {
+        match self {
+            RoomError::NotReady => "room.not_ready",
+            RoomError::NotStarted => "room.not_started",
+            RoomError::NotLandlordPlayer => "room.not_landlord_player",
+            RoomError::RoomFull => "room.full",
+            RoomError::NoSurrenderVote => "room.no_surrender_vote",
+            RoomError::NotConcedingSide => "room.not_conceding_side",
         }
     }
 }
 
 impl Display for RoomError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match *self {
-            RoomError::NotReady => {
-                write!(f, "房间未准备好")
-            }
-            RoomError::NotStarted => {
-                write!(f, "游戏尚未开始")
-            }
-            RoomError::NotLandlordPlayer => {
-                write!(f, "不是你叫地主")
-            }
-            RoomError::RoomFull => {
-                write!(f, "房间已满")
-            }
-        }
+        write!(f, "{}", self.localize(Locale::default()))
     }
 }
 
@@ -64,20 +77,28 @@ pub enum LobbyError {
     /// 房间已满
     RoomFull,
 
+    /// 房间已存在
+    RoomExists,
+
     RoomErr(RoomError),
     OtherError
 }
 
+impl Localize for LobbyError {
+    fn code(&self) -> &'static str {
+        match self {
+            LobbyError::HasJoinedRoom => "lobby.joined",
+            LobbyError::RoomFull => "lobby.room_full",
+            LobbyError::RoomExists => "lobby.room_exists",
+            LobbyError::RoomErr(e) => e.code(),
+            LobbyError::OtherError => "lobby.other",
+        }
+    }
+}
+
 impl Display for LobbyError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match *self {
-            LobbyError::RoomFull => {
-                write!(f, "Room is full")
-            }
-            _ => {
-                write!(f, "Lobby error")
-            }
-        }
+        write!(f, "{}", self.localize(Locale::default()))
     }
 }
 
@@ -88,24 +109,320 @@ pub enum GameError {
     IsRunning,
     NotReady,
     NotYourTurn,
-    NoSuchCards,
-    WrongRule,
-    NoRule,
+
+    /// 手牌中没有这些牌，参数为缺少的牌
+    NoSuchCards(Vec<Card>),
+
+    /// 压不过上一手牌
+    WrongRule(PlayDiagnostic),
+
+    /// 出的牌不符合任何牌型
+    NoRule(PlayDiagnostic),
 
     /// 这把赢了
-    /// 参数：最后出掉牌的玩家，玩家类型，获得的积分
-    Win(UserId, PlayerType, u32),
+    /// 参数：最后出掉牌的玩家，玩家类型，结算
+    Win(UserId, PlayerType, Settlement),
 
     /// 过你马呢
-    YourTurn
+    YourTurn,
+
+    /// 已经明牌
+    AlreadyShown,
+
+    /// 有玩家逃跑，游戏结束
+    /// 参数：逃跑的玩家，按最高倍率的结算
+    Escaped(UserId, Settlement),
+}
+
+impl Localize for GameError {
+    fn code(&self) -> &'static str {
+        match self {
+            GameError::NotEnoughPlayers => "game.not_enough_players",
+            GameError::NotRunning => "game.not_running",
+            GameError::IsRunning => "game.is_running",
+            GameError::NotReady => "game.not_ready",
+            GameError::NotYourTurn => "game.not_your_turn",
+            GameError::NoSuchCards(_) => "game.no_such_cards",
+            GameError::WrongRule(_) => "game.wrong_rule",
+            GameError::NoRule(_) => "game.no_rule",
+            GameError::Win(_, _, _) => "game.win",
+            GameError::YourTurn => "game.your_turn",
+            GameError::AlreadyShown => "game.already_shown",
+            GameError::Escaped(_, _) => "game.escaped",
+        }
+    }
+
+    fn args(&self, locale: Locale) -> Vec<String> {
+        match self {
+            GameError::NoSuchCards(cards) => {
+                vec![CardList(cards.clone()).to_string()]
+            }
+            GameError::WrongRule(diagnostic) => {
+                let bomb = match &diagnostic.bomb {
+                    Some(bomb) => format_message(locale, "game.bomb_hint", &[CardList(bomb.clone()).to_string()]),
+                    None => String::new(),
+                };
+                vec![
+                    diagnostic.kind.localize(locale),
+                    CardList(diagnostic.last.clone()).to_string(),
+                    String::from(diagnostic.last_rank.to_string()),
+                    diagnostic.last_kind.localize(locale),
+                    bomb,
+                ]
+            }
+            GameError::NoRule(diagnostic) => {
+                vec![diagnostic.kind.localize(locale)]
+            }
+            GameError::Win(user, player_type, settlement) => {
+                vec![
+                    user.clone(),
+                    player_type.localize(locale),
+                    settlement.points.to_string(),
+                    settlement.localize(locale),
+                ]
+            }
+            GameError::Escaped(user, settlement) => {
+                vec![
+                    user.clone(),
+                    (settlement.points as u64 * 2).to_string(),
+                    settlement.points.to_string(),
+                ]
+            }
+            _ => vec![],
+        }
+    }
 }
 
 impl Display for GameError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match *self {
-            _ => {
-                write!(f, "Game error")
+        write!(f, "{}", self.localize(Locale::default()))
+    }
+}
+
+/// 无法识别的牌
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub struct ParseCardsError {
+    /// 无法识别的字符
+    pub token: String,
+
+    /// 该字符在输入中的位置，从 0 开始按字符计数
+    pub position: usize,
+}
+
+impl Localize for ParseCardsError {
+    fn code(&self) -> &'static str {
+        "cards.invalid_token"
+    }
+
+    fn args(&self, _locale: Locale) -> Vec<String> {
+        vec![(self.position + 1).to_string(), self.token.clone()]
+    }
+}
+
+impl Display for ParseCardsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.localize(Locale::default()))
+    }
+}
+
+/// 残局读取或验证失败
+#[derive(Debug, thiserror::Error)]
+pub enum PuzzleError {
+    /// 无法读取残局文件
+    Io(#[from] std::io::Error),
+
+    /// 残局文件格式错误
+    Format(#[from] serde_json::Error),
+
+    /// 座位编号超出范围
+    InvalidSeat(usize),
+
+    /// 无法识别的牌
+    Cards(#[from] ParseCardsError),
+
+    /// 不符合任何牌型的牌，参数为原始输入
+    InvalidCards(String),
+
+    /// 牌的数量超出一副牌
+    TooManyCards(Card),
+
+    /// 玩家无法必胜
+    Unsolvable,
+}
+
+impl Display for PuzzleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PuzzleError::Io(e) => {
+                write!(f, "无法读取残局：{}", e)
+            }
+            PuzzleError::Format(e) => {
+                write!(f, "残局格式错误：{}", e)
+            }
+            PuzzleError::InvalidSeat(seat) => {
+                write!(f, "座位 {} 不存在", seat)
+            }
+            PuzzleError::Cards(e) => {
+                write!(f, "{}", e)
+            }
+            PuzzleError::InvalidCards(input) => {
+                write!(f, "{} 不符合任何牌型", input)
+            }
+            PuzzleError::TooManyCards(card) => {
+                write!(f, "[{}] 的数量超出一副牌", card.to_string())
+            }
+            PuzzleError::Unsolvable => {
+                write!(f, "残局无解")
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum HistoryError {
+    /// 无法解析的行，参数为行号
+    Syntax(usize),
+
+    /// 缺少必需的标签
+    MissingTag(&'static str),
+
+    /// 标签的值无法识别
+    InvalidTag(&'static str),
+
+    /// 无法识别的牌
+    Cards(#[from] ParseCardsError),
+
+    /// 第几步不该由该座位操作
+    /// 参数：步数，座位
+    WrongSeat(usize, usize),
+
+    /// 第几步不合法
+    Game(usize, GameError),
+
+    /// 牌局结束后仍有操作，参数为步数
+    AfterResult(usize),
+
+    /// 重放的结果与记录不一致
+    ResultMismatch,
+}
+
+impl Display for HistoryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HistoryError::Syntax(line) => {
+                write!(f, "第 {} 行格式错误", line)
+            }
+            HistoryError::MissingTag(name) => {
+                write!(f, "缺少标签 {}", name)
+            }
+            HistoryError::InvalidTag(name) => {
+                write!(f, "标签 {} 的值无效", name)
+            }
+            HistoryError::Cards(e) => {
+                write!(f, "{}", e)
+            }
+            HistoryError::WrongSeat(step, seat) => {
+                write!(f, "第 {} 步不该由座位 {} 操作", step, seat)
+            }
+            HistoryError::Game(step, e) => {
+                write!(f, "第 {} 步不合法：{}", step, e)
+            }
+            HistoryError::AfterResult(step) => {
+                write!(f, "第 {} 步发生在牌局结束之后", step)
+            }
+            HistoryError::ResultMismatch => {
+                write!(f, "重放结果与记录不一致")
+            }
+        }
+    }
+}
+
+/// 洗牌种子验证失败
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, thiserror::Error)]
+pub enum FairnessError {
+    /// 公开的种子无法解析
+    InvalidSeed,
+
+    /// 公开的种子与开局时的承诺不符
+    CommitmentMismatch,
+
+    /// 玩家不在这局游戏中
+    UnknownPlayer,
+
+    /// 按种子发出的牌与收到的手牌不同
+    DealMismatch,
+
+    /// 服务器还没有公布这局的承诺，不能提交随机数
+    NotCommitted,
+
+    /// 这局已经发牌，不能再提交随机数
+    AlreadyDealt,
+}
+
+impl Localize for FairnessError {
+    fn code(&self) -> &'static str {
+        match self {
+            FairnessError::InvalidSeed => "fair.invalid_seed",
+            FairnessError::CommitmentMismatch => "fair.commitment_mismatch",
+            FairnessError::UnknownPlayer => "fair.unknown_player",
+            FairnessError::DealMismatch => "fair.deal_mismatch",
+            FairnessError::NotCommitted => "fair.not_committed",
+            FairnessError::AlreadyDealt => "fair.already_dealt",
+        }
+    }
+}
+
+impl Display for FairnessError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.localize(Locale::default()))
+    }
+}
+
+/// 登入与注册失败
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, thiserror::Error)]
+pub enum AuthError {
+    /// 用户不存在或没有设置密码
+    UnknownUser,
+
+    /// 密码错误
+    WrongPassword,
+
+    /// 用户名已被注册
+    UserExists,
+
+    /// 用户名不合法
+    InvalidName,
+
+    /// 会话令牌无效或已过期
+    InvalidToken,
+
+    /// 尚未登入
+    NotLoggedIn,
+
+    /// 账号在其他地方登入，当前连接失效
+    SessionTakenOver,
+
+    /// 服务器无法保存账号数据
+    StorageFailed,
+}
+
+impl Localize for AuthError {
+    fn code(&self) -> &'static str {
+        match self {
+            AuthError::UnknownUser => "auth.unknown_user",
+            AuthError::WrongPassword => "auth.wrong_password",
+            AuthError::UserExists => "auth.user_exists",
+            AuthError::InvalidName => "auth.invalid_name",
+            AuthError::InvalidToken => "auth.invalid_token",
+            AuthError::NotLoggedIn => "auth.not_logged_in",
+            AuthError::SessionTakenOver => "auth.session_taken_over",
+            AuthError::StorageFailed => "auth.storage_failed",
+        }
+    }
+}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.localize(Locale::default()))
+    }
+}
@@ -0,0 +1,193 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::card::Card;
+use crate::error::FairnessError;
+use crate::game::{DealMode, Game};
+use crate::player::Player;
+use crate::user::UserId;
+
+/// 可验证的洗牌种子
+/// 服务器生成秘密种子后先公布其哈希作为承诺，游戏结束后公开种子，
+/// 玩家可以据此重新发牌，确认服务器没有更换牌局。
+/// 玩家可以在公布承诺之后、发牌之前提交自己的随机数一起决定洗牌结果，
+/// 这样服务器选择种子时不知道玩家的随机数，玩家提交随机数时也不知道服务器的种子
+#[derive(Clone, Debug)]
+pub struct FairSeed {
+    /// 服务器的秘密种子
+    server_seed: [u8; 32],
+
+    /// 玩家提交的随机数
+    client_seeds: Vec<(UserId, u64)>,
+
+    /// 是否已经公布承诺
+    committed: bool,
+
+    /// 是否已经用于发牌
+    dealt: bool,
+}
+
+impl FairSeed {
+    pub fn new() -> FairSeed {
+        FairSeed::with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn with_rng<R: Rng + ?Sized>(rng: &mut R) -> FairSeed {
+        let mut server_seed = [0u8; 32];
+        rng.fill(&mut server_seed);
+        FairSeed {
+            server_seed,
+            client_seeds: vec![],
+            committed: false,
+            dealt: false,
+        }
+    }
+
+    /// 服务器种子的 SHA-256，十六进制表示
+    pub fn commitment(&self) -> String {
+        to_hex(&Sha256::digest(self.server_seed))
+    }
+
+    /// 公布承诺，之后才接受玩家的随机数
+    /// 返回承诺，可以重复调用
+    pub fn commit(&mut self) -> String {
+        self.committed = true;
+        self.commitment()
+    }
+
+    /// 记录玩家提交的随机数，同一玩家重复提交时使用最后一次
+    /// 公布承诺之前或发牌之后提交的随机数会被拒绝
+    pub fn add_client_seed(&mut self, user: UserId, seed: u64) -> Result<(), FairnessError> {
+        if !self.committed {
+            return Err(FairnessError::NotCommitted);
+        }
+        if self.dealt {
+            return Err(FairnessError::AlreadyDealt);
+        }
+        self.client_seeds.retain(|(x, _)| *x != user);
+        self.client_seeds.push((user, seed));
+        self.client_seeds.sort();
+        Ok(())
+    }
+
+    /// 实际用于洗牌的种子
+    pub fn seed(&self) -> u64 {
+        derive_seed(&self.server_seed, &self.client_seeds)
+    }
+
+    /// 取得发牌用的种子，之后不再接受玩家的随机数
+    pub fn deal_seed(&mut self) -> u64 {
+        self.dealt = true;
+        self.seed()
+    }
+
+    /// 公开种子，参数为按座位排列的玩家与房间的发牌方式
+    pub fn reveal(&self, players: Vec<UserId>, deal_mode: DealMode) -> DealReveal {
+        DealReveal {
+            server_seed: to_hex(&self.server_seed),
+            client_seeds: self.client_seeds.clone(),
+            players,
+            deal_mode,
+        }
+    }
+}
+
+impl Default for FairSeed {
+    fn default() -> Self {
+        FairSeed::new()
+    }
+}
+
+/// 游戏结束后公开的洗牌种子
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct DealReveal {
+    /// 服务器的秘密种子，十六进制表示
+    pub server_seed: String,
+
+    /// 玩家提交的随机数，按玩家排序
+    pub client_seeds: Vec<(UserId, u64)>,
+
+    /// 按座位排列的玩家
+    pub players: Vec<UserId>,
+
+    /// 发牌方式
+    pub deal_mode: DealMode,
+}
+
+impl DealReveal {
+    /// 按公开的种子重新发牌，返回等待叫地主的游戏
+    pub fn deal(&self) -> Result<Game, FairnessError> {
+        let server_seed = from_hex(&self.server_seed).ok_or(FairnessError::InvalidSeed)?;
+        let mut game = Game::new();
+        game.deal_mode = self.deal_mode;
+        for user in self.players.iter() {
+            game.add_player(Player::new(user.clone()));
+        }
+        let seed = derive_seed(&server_seed, &self.client_seeds);
+        game.start_with_rng(&mut StdRng::seed_from_u64(seed))
+            .map(|_| ())
+            .map_err(|_| FairnessError::InvalidSeed)?;
+        Ok(game)
+    }
+
+    /// 验证种子与开局时的承诺一致，并且按种子发给 `user` 的正是 `cards`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cardgame::{DealMode, FairSeed, HandHistory};
+    /// let mut seed = FairSeed::new();
+    /// let commitment = seed.commit();
+    /// seed.add_client_seed("b".to_string(), 42).unwrap();
+    /// let players = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+    /// let history = HandHistory::from_seed(["a".into(), "b".into(), "c".into()], seed.seed());
+    ///
+    /// let reveal = seed.reveal(players, DealMode::Shuffle);
+    /// assert!(reveal.verify(&commitment, "b", &history.hands[1]).is_ok());
+    /// assert!(reveal.verify(&commitment, "b", &history.hands[0]).is_err());
+    /// ```
+    pub fn verify(&self, commitment: &str, user: &str, cards: &[Card]) -> Result<(), FairnessError> {
+        let server_seed = from_hex(&self.server_seed).ok_or(FairnessError::InvalidSeed)?;
+        if to_hex(&Sha256::digest(server_seed)) != commitment.to_lowercase() {
+            return Err(FairnessError::CommitmentMismatch);
+        }
+
+        let seat = self.players.iter().position(|x| x == user).ok_or(FairnessError::UnknownPlayer)?;
+        let game = self.deal()?;
+        let mut cards = cards.to_vec();
+        cards.sort();
+        if game.players[seat].cards != cards {
+            return Err(FairnessError::DealMismatch);
+        }
+        Ok(())
+    }
+}
+
+/// 由服务器种子与玩家随机数得到洗牌种子
+fn derive_seed(server_seed: &[u8], client_seeds: &[(UserId, u64)]) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(server_seed);
+    for (user, seed) in client_seeds {
+        hasher.update(user.as_bytes());
+        hasher.update(seed.to_le_bytes());
+    }
+    let hash = hasher.finalize();
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&hash[..8]);
+    u64::from_le_bytes(bytes)
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
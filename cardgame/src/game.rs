@@ -8,12 +8,39 @@ use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::slice::Iter;
 use crate::error::GameError;
+use crate::diagnostic::PlayDiagnostic;
+use crate::observer::GameObserver;
+use crate::stats::{MultiplierBreakdown, Settlement};
 use crate::user::UserId;
 
-/// 基础积分
-const BASE_POINTS: u32 = 100;
+/// 默认底分
+pub const BASE_POINTS: u32 = 100;
 
-#[derive(PartialEq, Eq)]
+/// 发牌前明牌的倍数
+pub const SHOW_BEFORE_DEAL_MULTIPLIER: u32 = 4;
+
+/// 叫地主时明牌的倍数
+pub const SHOW_AT_BID_MULTIPLIER: u32 = 2;
+
+/// 逃跑时的最低结算倍率，当前倍率更高时按当前倍率结算，房间设置了封顶倍率时不超过封顶
+pub const ESCAPE_MULTIPLIER: u32 = 64;
+
+/// 每次连续发给一位玩家的牌数，不洗牌模式使用
+const DEAL_CLUMP: usize = 4;
+
+/// 发牌方式
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum DealMode {
+    /// 完全洗牌，每次发一张
+    #[default]
+    Shuffle,
+
+    /// 不洗牌：只切牌并轻微打乱，大致保持点数顺序，每次发几张
+    /// 炸弹与顺子明显更多
+    NoShuffle,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
 pub enum GameState {
     WaitingForPlayers,
     WaitingForLandlord,
@@ -48,7 +75,70 @@ pub struct Game {
     /// 积分倍率
     /// 炸弹，火箭会*=2
     /// 加倍 *=2，超级加倍 *=4
+    /// 明牌按 `show_multiplier` 计算
     pub score_multiplier: u32,
+
+    /// 发牌方式
+    pub deal_mode: DealMode,
+
+    /// 炸弹封顶，炸弹与火箭最多翻倍的次数，None 为不封顶
+    pub bomb_cap: Option<u32>,
+
+    /// 本局翻倍的炸弹数
+    pub bombs: u32,
+
+    /// 本局翻倍的火箭数
+    pub rockets: u32,
+
+    /// 明牌的倍数，多位玩家明牌时取最大的，没有明牌时为 1
+    pub show_multiplier: u32,
+
+    /// 底分
+    pub base_points: u32,
+
+    /// 结算时的封顶倍率，None 为不封顶
+    pub max_multiplier: Option<u32>,
+
+    /// 游戏事件的观察者
+    observers: Vec<Box<dyn GameObserver + Send + Sync>>,
+}
+
+/// 某位玩家视角的牌局，断线重连时用于恢复客户端
+/// 只包含这位玩家能看到的信息
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct GameSnapshot {
+    /// 按座位排列的玩家
+    pub players: Vec<UserId>,
+
+    /// 自己的座位
+    pub seat: usize,
+
+    /// 自己的手牌
+    pub cards: Vec<Card>,
+
+    /// 每个座位剩余的牌数
+    pub cards_left: Vec<u32>,
+
+    /// 明牌玩家的手牌，按座位排列，没有明牌的为 None
+    pub shown: Vec<Option<Vec<Card>>>,
+
+    /// 游戏状态，叫地主或出牌
+    pub state: GameState,
+
+    /// 叫地主时为正在选择是否叫地主的座位，之后为地主的座位
+    pub landlord: usize,
+
+    /// 地主牌，叫地主时为空
+    pub landlord_cards: Vec<Card>,
+
+    /// 当前出牌的座位
+    pub current: usize,
+
+    /// 需要跟的牌与出牌的座位，自由出牌时为 None
+    pub last_play: Option<(usize, Vec<Card>)>,
+
+    /// 积分倍率
+    pub multiplier: u32,
 }
 
 impl Game {
@@ -63,9 +153,35 @@ impl Game {
             landlord_cards: vec![],
             last_rule: Box::new(RuleNone),
             score_multiplier: 1,
+            deal_mode: DealMode::Shuffle,
+            bomb_cap: None,
+            bombs: 0,
+            rockets: 0,
+            show_multiplier: 1,
+            base_points: BASE_POINTS,
+            max_multiplier: None,
+            observers: vec![],
         }
     }
 
+    /// 注册游戏事件的观察者，重置游戏后仍然保留
+    pub fn add_observer(&mut self, observer: Box<dyn GameObserver + Send + Sync>) {
+        self.observers.push(observer);
+    }
+
+    pub fn clear_observers(&mut self) {
+        self.observers.clear();
+    }
+
+    /// 依次通知所有观察者
+    fn notify<F: FnMut(&mut dyn GameObserver, &Game)>(&mut self, mut f: F) {
+        let mut observers = std::mem::take(&mut self.observers);
+        for observer in observers.iter_mut() {
+            f(observer.as_mut(), self);
+        }
+        self.observers = observers;
+    }
+
     /// “上桌”
     pub fn add_player(&mut self, player: Player) -> bool {
         if self.players.len() < 3 {
@@ -95,28 +211,6 @@ impl Game {
         }
     }
 
-    #[cfg(debug_assertions)]
-    pub fn print_cards(&self) {
-        for p in self.players.iter() {
-            print!("{}: ", p.user);
-            for c in p.cards.iter() {
-                print!("[{}]", c.to_string());
-            }
-            println!();
-        }
-    }
-
-    #[cfg(not(debug_assertions))]
-    pub fn print_cards(&self) {}
-
-    #[cfg(debug_assertions)]
-    pub fn print_player(&self) {
-        println!("轮到 {} 出牌", self.current_player().user);
-    }
-
-    #[cfg(not(debug_assertions))]
-    pub fn print_player(&self) {}
-
     pub fn landlord_player(&self) -> &Player {
         &self.players[self.landlord_index]
     }
@@ -129,10 +223,22 @@ impl Game {
         self.landlord_index = 0;
         self.landlord_cards.clear();
         self.score_multiplier = 1;
+        self.bombs = 0;
+        self.rockets = 0;
+        self.show_multiplier = 1;
+        for player in self.players.iter_mut() {
+            player.shown = false;
+        }
         self.state = GameState::WaitingForLandlord;
     }
 
     pub fn start(&mut self) -> Result<(&Player, Iter<Player>), GameError> {
+        self.start_with_rng(&mut thread_rng())
+    }
+
+    /// 使用指定的随机数生成器决定叫地主顺序并发牌
+    /// 相同状态的生成器总会得到相同的牌局
+    pub fn start_with_rng<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Result<(&Player, Iter<Player>), GameError> {
         if self.players.len() != 3 {
             return Err(GameError::NotEnoughPlayers);
         }
@@ -141,12 +247,12 @@ impl Game {
         }
 
         self.state = GameState::WaitingForLandlord;
-        self.landlord_index = thread_rng().gen_range(0..3);
+        self.landlord_index = rng.gen_range(0..3);
 
-        let cards = gen_cards();
+        let cards = gen_cards_with_mode(self.deal_mode, rng);
 
         for i in 0..3 {
-            for j in 0..18 {
+            for j in 0..17 {
                 self.players[i].cards.push(cards[i * 17 + j]);
             }
             self.players[i].cards.sort();
@@ -154,25 +260,125 @@ impl Game {
         for i in 51..54 {
             self.landlord_cards.push(cards[i]);
         }
-        self.print_cards();
+        self.notify(|o, g| o.on_deal(g));
 
         Ok((&self.players[self.landlord_index], self.players.iter()))
     }
 
     pub fn win(&mut self) -> GameError {
-        #[cfg(debug_assertions)]
-        match self.current_player().player_type {
-            PlayerType::Landlord => {
-                println!("地主赢了！");
-            }
-            PlayerType::Farmer => {
-                println!("农民赢了！");
-            }
+        self.finish(self.index)
+    }
+
+    /// 一方投降，按另一方获胜正常结算
+    /// 返回 `GameError::Win`，游戏没有在进行时返回 `GameError::NotRunning`
+    pub fn surrender(&mut self, side: PlayerType) -> GameError {
+        if self.state != GameState::Running {
+            return GameError::NotRunning;
+        }
+        let seat = match side {
+            PlayerType::Landlord => (self.landlord_index + 1) % 3,
+            PlayerType::Farmer => self.landlord_index,
+        };
+        self.notify(|o, g| o.on_surrender(g, &side));
+        self.finish(seat)
+    }
+
+    /// 玩家逃跑，按当前倍率与 `ESCAPE_MULTIPLIER` 中较大的判负，逃跑的玩家独自承担另外两位玩家获得的积分
+    /// 返回 `GameError::Escaped`，游戏没有开始时返回 `GameError::NotRunning`
+    pub fn escape(&mut self, seat: usize) -> GameError {
+        if self.state == GameState::WaitingForPlayers || seat >= self.players.len() {
+            return GameError::NotRunning;
         }
-        self.print_cards();
+        self.state = GameState::WaitingForPlayers;
+
+        // 逃跑不能比打完这局输得更少，封顶由 `Settlement` 处理
+        let current = self.settle().multiplier;
+        let multiplier = MultiplierBreakdown {
+            total: current.total.max(ESCAPE_MULTIPLIER),
+            ..current
+        };
+        let settlement = Settlement::new(self.base_points, multiplier, self.max_multiplier);
+        let points = settlement.points;
+        self.notify(|o, g| o.on_escape(g, seat, points));
+
+        GameError::Escaped(self.players[seat].user.clone(), settlement)
+    }
 
+    /// 游戏结束，`seat` 所在的一方获胜
+    fn finish(&mut self, seat: usize) -> GameError {
         self.state = GameState::WaitingForPlayers;
-        GameError::Win(self.current_player().user.clone(), self.current_player().player_type.clone(), BASE_POINTS * self.score_multiplier)
+
+        let winner = self.players[seat].player_type.clone();
+        let settlement = self.settle();
+        let points = settlement.points;
+        self.notify(|o, g| o.on_game_end(g, seat, &winner, points));
+
+        GameError::Win(self.players[seat].user.clone(), winner, settlement)
+    }
+
+    /// 玩家明牌
+    /// 发牌前明牌的倍数为 `SHOW_BEFORE_DEAL_MULTIPLIER`，叫地主时为 `SHOW_AT_BID_MULTIPLIER`，出牌开始后不能再明牌
+    /// 返回明牌后的积分倍率
+    pub fn show_hand(&mut self, seat: usize) -> Result<u32, GameError> {
+        let multiplier = match self.state {
+            GameState::WaitingForPlayers => SHOW_BEFORE_DEAL_MULTIPLIER,
+            GameState::WaitingForLandlord => SHOW_AT_BID_MULTIPLIER,
+            GameState::Running => return Err(GameError::IsRunning),
+        };
+        let player = self.players.get_mut(seat).ok_or(GameError::NotEnoughPlayers)?;
+        if player.shown {
+            return Err(GameError::AlreadyShown);
+        }
+        player.shown = true;
+
+        if multiplier > self.show_multiplier {
+            self.score_multiplier = self.score_multiplier / self.show_multiplier * multiplier;
+            self.show_multiplier = multiplier;
+            self.notify(|o, g| o.on_show_hand(g, seat));
+            self.notify(|o, g| o.on_multiplier(g, g.score_multiplier));
+        } else {
+            self.notify(|o, g| o.on_show_hand(g, seat));
+        }
+        Ok(self.score_multiplier)
+    }
+
+    /// `seat` 座位的玩家视角的牌局，游戏没有开始时返回 None
+    pub fn snapshot(&self, seat: usize) -> Option<GameSnapshot> {
+        if self.state == GameState::WaitingForPlayers || seat >= self.players.len() {
+            return None;
+        }
+        let running = self.state == GameState::Running;
+        Some(GameSnapshot {
+            players: self.players.iter().map(|x| x.user.clone()).collect(),
+            seat,
+            cards: self.players[seat].cards.clone(),
+            cards_left: self.players.iter().map(|x| x.cards.len() as u32).collect(),
+            shown: self.players
+                .iter()
+                .map(|x| if x.shown { Some(x.cards.clone()) } else { None })
+                .collect(),
+            state: self.state,
+            landlord: self.landlord_index,
+            landlord_cards: if running { self.landlord_cards.clone() } else { vec![] },
+            current: if running { self.index } else { self.landlord_index },
+            last_play: if running && self.index != self.last_index {
+                Some((self.last_index, self.last_cards.clone()))
+            } else {
+                None
+            },
+            multiplier: self.score_multiplier,
+        })
+    }
+
+    /// 按当前倍率结算，封顶只在结算时生效
+    pub fn settle(&self) -> Settlement {
+        let multiplier = MultiplierBreakdown {
+            show_hand: self.show_multiplier,
+            bombs: self.bombs,
+            rockets: self.rockets,
+            total: self.score_multiplier,
+        };
+        Settlement::new(self.base_points, multiplier, self.max_multiplier)
     }
 
     pub fn pass(&mut self) -> Result<String, GameError> {
@@ -181,8 +387,9 @@ impl Game {
         } else if self.index == self.last_index {
             Err(GameError::YourTurn)
         } else {
+            let seat = self.index;
             self.move_index();
-            self.print_player();
+            self.notify(|o, g| o.on_pass(g, seat));
             Ok(self.current_player().user.clone())
         }
     }
@@ -190,47 +397,75 @@ impl Game {
     pub fn submit_cards(&mut self, cards: Vec<Card>) -> Result<String, GameError> {
         let rule = match_rule(&cards);
         if rule.is_none() {
-            Err(GameError::NoRule)
+            Err(GameError::NoRule(self.diagnose(&cards)))
         } else if rule_matches(&*self.last_rule, &cards) || self.index == self.last_index {
             let option = to_card_groups(&self.current_player().cards) - to_card_groups(&cards);
             if option.is_none() {
-                return Err(GameError::NoSuchCards);
+                return Err(GameError::NoSuchCards(self.missing_cards(&cards)));
             }
 
-            #[cfg(debug_assertions)] {
-                print!("{} 出牌：", self.current_player().user);
-                for c in cards.iter() {
-                    print!("[{}]", c.to_string());
-                }
-                println!();
-            }
+            let rocket = rule.kind() == RuleKind::Rocket;
+            let bomb = rule.bomb_priority() == 1 || rule.bomb_priority() == 2;
+            let bomb = bomb && self.bomb_cap.is_none_or(|x| self.bombs + self.rockets < x);
+            let seat = self.index;
+            self.players[seat].cards = option.unwrap().into_cards();
+            self.last_rule = rule;
+            self.last_cards = cards;
+            self.last_index = seat;
 
             // 炸弹积分翻倍
-            if rule.bomb_priority() == 1 || rule.bomb_priority() == 2 {
+            if bomb {
                 self.score_multiplier *= 2;
+                if rocket {
+                    self.rockets += 1;
+                } else {
+                    self.bombs += 1;
+                }
+            }
+
+            let finished = self.players[seat].cards.is_empty();
+            if !finished {
+                self.move_index();
             }
 
-            self.players[self.index].cards = option.unwrap().into_cards();
+            self.notify(|o, g| o.on_play(g, seat, &g.last_cards));
+            if let Some(remaining) = self.last_cards(seat) {
+                self.notify(|o, g| o.on_last_cards(g, seat, remaining));
+            }
+            if bomb {
+                self.notify(|o, g| o.on_multiplier(g, g.score_multiplier));
+            }
 
             // 赢得胜利
-            if self.current_player().cards.is_empty() {
+            if finished {
                 return Err(self.win());
             }
-
-            self.last_rule = rule;
-            self.last_cards = cards;
-            self.last_index = self.index;
-
-            self.move_index();
-
-            self.print_cards();
-            self.print_player();
             Ok(self.current_player().user.clone())
         } else {
-            Err(GameError::WrongRule)
+            Err(GameError::WrongRule(self.diagnose(&cards)))
         }
     }
 
+    /// 报单、报双：座位只剩一张或两张牌时返回剩余的牌数
+    pub fn last_cards(&self, seat: usize) -> Option<u32> {
+        match self.players[seat].cards.len() {
+            remaining @ 1..=2 => Some(remaining as u32),
+            _ => None,
+        }
+    }
+
+    /// 分析当前玩家出的牌为什么不能出
+    pub fn diagnose(&self, cards: &[Card]) -> PlayDiagnostic {
+        let last: &[Card] = if self.index == self.last_index { &[] } else { &self.last_cards };
+        PlayDiagnostic::new(cards, last, &self.current_player().cards)
+    }
+
+    /// 当前玩家手牌中缺少的牌
+    fn missing_cards(&self, cards: &[Card]) -> Vec<Card> {
+        let mut hand = CardStore::from_cards(&self.current_player().cards);
+        cards.iter().filter(|x| !hand.remove(x)).copied().collect()
+    }
+
     pub fn run(&mut self) -> Result<(), GameError> {
         if self.players.len() != 3 {
             return Err(GameError::NotEnoughPlayers);
@@ -250,12 +485,28 @@ impl Game {
 
         self.state = GameState::Running;
 
-        // 显示调试信息
-        self.print_cards();
-        self.print_player();
+        let seat = self.landlord_index;
+        self.notify(|o, g| o.on_landlord(g, seat));
 
         Ok(())
     }
+
+    /// 当前叫地主的玩家选择叫或不叫
+    /// 叫地主时开始游戏，不叫时轮到下一位玩家
+    pub fn bid(&mut self, call: bool) -> Result<(), GameError> {
+        if self.state != GameState::WaitingForLandlord {
+            return Err(GameError::NotReady);
+        }
+
+        let seat = self.landlord_index;
+        self.notify(|o, g| o.on_bid(g, seat, call));
+        if call {
+            self.run()
+        } else {
+            self.move_landlord_index();
+            Ok(())
+        }
+    }
 }
 
 impl Default for Game {
@@ -265,6 +516,62 @@ impl Default for Game {
 }
 
 pub fn gen_cards() -> Vec<Card> {
+    gen_cards_with_rng(&mut thread_rng())
+}
+
+/// 使用指定的随机数生成器洗牌
+pub fn gen_cards_with_rng<R: Rng + ?Sized>(rng: &mut R) -> Vec<Card> {
+    let mut cards = new_deck();
+    cards.shuffle(rng);
+    cards
+}
+
+/// 按发牌方式生成牌堆，前 51 张依次为三位玩家的手牌，最后 3 张为地主牌
+pub fn gen_cards_with_mode<R: Rng + ?Sized>(mode: DealMode, rng: &mut R) -> Vec<Card> {
+    match mode {
+        DealMode::Shuffle => gen_cards_with_rng(rng),
+        DealMode::NoShuffle => gen_cards_no_shuffle_with_rng(rng),
+    }
+}
+
+/// 模拟不洗牌
+/// 把按点数排列的牌分成小段后打乱段的顺序，再交换少量相邻的牌并切牌，
+/// 最后留下底部 3 张作为地主牌，其余每次 `DEAL_CLUMP` 张轮流发给三位玩家
+pub fn gen_cards_no_shuffle_with_rng<R: Rng + ?Sized>(rng: &mut R) -> Vec<Card> {
+    let deck = new_deck();
+    let mut segments = vec![];
+    let mut start = 0;
+    while start < deck.len() {
+        let end = (start + rng.gen_range(1..=6)).min(deck.len());
+        segments.push(&deck[start..end]);
+        start = end;
+    }
+    segments.shuffle(rng);
+    let mut deck: Vec<Card> = segments.concat();
+
+    for _ in 0..rng.gen_range(3..8) {
+        let i = rng.gen_range(0..deck.len() - 1);
+        deck.swap(i, i + 1);
+    }
+    let cut = rng.gen_range(0..deck.len());
+    deck.rotate_left(cut);
+
+    let mut hands: [Vec<Card>; 3] = Default::default();
+    let mut rest = &deck[..51];
+    let mut seat = 0;
+    while !rest.is_empty() {
+        let n = DEAL_CLUMP.min(17 - hands[seat].len());
+        hands[seat].extend_from_slice(&rest[..n]);
+        rest = &rest[n..];
+        seat = (seat + 1) % 3;
+    }
+    let mut cards = hands.concat();
+    cards.extend_from_slice(&deck[51..]);
+    cards
+}
+
+/// 未洗过的一副牌，按点数从小到大排列
+pub fn new_deck() -> Vec<Card> {
     let mut cards: Vec<Card> = Vec::with_capacity(54);
     for i in 1..14 {
         for _ in 0..4 {
@@ -273,11 +580,11 @@ pub fn gen_cards() -> Vec<Card> {
     }
     cards.push(Card::CardGhost);
     cards.push(Card::CardKing);
-
-    cards.shuffle(&mut thread_rng());
     cards
 }
 
+/// 解析玩家输入的牌，只取第一段连续的牌，遇到无法识别的字符即停止
+/// 需要报告错误时使用 `CardList`，需要跳过无法识别的字符时使用 `CardList::parse_lenient`
 pub fn parse_input(input: &str) -> Option<Vec<Card>> {
     let input = input
         .to_ascii_uppercase()
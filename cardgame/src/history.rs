@@ -0,0 +1,505 @@
+use crate::card::*;
+use crate::error::{GameError, HistoryError};
+use crate::game::{DealMode, Game, GameState};
+use crate::lobby::RoomConfig;
+use crate::player::{Player, PlayerType};
+use crate::user::UserId;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// 牌谱中的一步操作
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// 叫地主
+    Call,
+
+    /// 不叫
+    Decline,
+
+    /// 出牌
+    Play(Vec<Card>),
+
+    /// 过牌
+    Pass,
+
+    /// 叫地主时明牌，发牌前明牌记录在 `ShowBeforeDeal` 标签中
+    ShowHand,
+
+    /// 投降，座位为投降一方的任意一位玩家
+    Surrender,
+
+    /// 逃跑
+    Escape,
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::Call => write!(f, "叫地主"),
+            Action::Decline => write!(f, "不叫"),
+            Action::Play(cards) => write!(f, "{}", cards_to_string(cards)),
+            Action::Pass => write!(f, "pass"),
+            Action::ShowHand => write!(f, "明牌"),
+            Action::Surrender => write!(f, "投降"),
+            Action::Escape => write!(f, "逃跑"),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = HistoryError;
+
+    /// 与客户端的输入相同：`叫地主`、`不叫`、`pass`、`明牌`、`投降`、`逃跑` 或者牌
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "叫地主" | "叫" => Ok(Action::Call),
+            "不叫" => Ok(Action::Decline),
+            "pass" | "过" => Ok(Action::Pass),
+            "明牌" => Ok(Action::ShowHand),
+            "投降" => Ok(Action::Surrender),
+            "逃跑" => Ok(Action::Escape),
+            cards => parse_cards(cards).map(Action::Play),
+        }
+    }
+}
+
+/// 一局的结果
+#[derive(Clone, Debug, PartialEq)]
+pub struct HandResult {
+    /// 获胜的一方
+    pub winner: PlayerType,
+
+    /// 获得的积分
+    pub points: u32,
+}
+
+/// 牌谱
+/// 记录一局的座位、发牌、叫地主、每一次出牌和过牌以及结果，
+/// 文本格式类似国际象棋的 PGN：
+///
+/// ```text
+/// [Event "好友房"]
+/// [Seat0 "alice"]
+/// [Seat1 "bob"]
+/// [Seat2 "carol"]
+/// [Seed "42"]
+/// [BasePoints "50"]
+/// [MaxMultiplier "64"]
+/// [ShowBeforeDeal "1"]
+/// [Hand0 "33445566778899101010"]
+/// [Hand1 "..."]
+/// [Hand2 "..."]
+/// [Bottom "JQK"]
+/// [FirstBidder "0"]
+/// [Result "地主 200"]
+///
+/// 0 叫地主
+/// 0 33
+/// 1 pass
+/// ```
+///
+/// 牌的写法与 `CardList` 相同。给出 `Seed` 时可以省略 `Hand0`..`Bottom` 和 `FirstBidder`，
+/// 发牌会按照种子重新生成。以 `#` 开头的行是注释。
+/// 房间设置与默认值不同时记录在 `DealMode`、`BombCap`、`BasePoints`、`MaxMultiplier` 标签中。
+#[derive(Clone, Debug, PartialEq)]
+pub struct HandHistory {
+    /// 其余的标签，例如 `Event`、`Date`，按出现顺序保存
+    pub tags: Vec<(String, String)>,
+
+    /// 按座位排列的玩家
+    pub seats: [UserId; 3],
+
+    /// 发牌种子，与 `Game::start_with_rng(&mut StdRng::seed_from_u64(seed))` 一致
+    pub seed: Option<u64>,
+
+    /// 三家各自发到的 17 张牌
+    pub hands: [Vec<Card>; 3],
+
+    /// 地主牌
+    pub landlord_cards: Vec<Card>,
+
+    /// 第一个叫地主的座位
+    pub first_bidder: usize,
+
+    /// 发牌与结算规则，不包括断线重连时间
+    pub config: RoomConfig,
+
+    /// 发牌前明牌的座位
+    pub shown_before_deal: Vec<usize>,
+
+    /// 参数：座位，操作
+    pub actions: Vec<(usize, Action)>,
+
+    pub result: Option<HandResult>,
+}
+
+impl HandHistory {
+    /// 从刚发完牌、尚未叫地主的游戏开始记录，同时记录游戏的规则与发牌前明牌的玩家
+    pub fn from_game(game: &Game) -> HandHistory {
+        HandHistory {
+            tags: vec![],
+            seats: [
+                game.players[0].user.clone(),
+                game.players[1].user.clone(),
+                game.players[2].user.clone(),
+            ],
+            seed: None,
+            hands: [
+                game.players[0].cards.clone(),
+                game.players[1].cards.clone(),
+                game.players[2].cards.clone(),
+            ],
+            landlord_cards: game.landlord_cards.clone(),
+            first_bidder: game.landlord_index,
+            config: RoomConfig {
+                deal_mode: game.deal_mode,
+                bomb_cap: game.bomb_cap,
+                base_points: game.base_points,
+                max_multiplier: game.max_multiplier,
+                ..Default::default()
+            },
+            shown_before_deal: game.players.iter()
+                .enumerate()
+                .filter(|(_, x)| x.shown)
+                .map(|(i, _)| i)
+                .collect(),
+            actions: vec![],
+            result: None,
+        }
+    }
+
+    /// 按照种子发牌
+    pub fn from_seed(seats: [UserId; 3], seed: u64) -> HandHistory {
+        HandHistory::from_seed_with_config(seats, seed, &RoomConfig::default())
+    }
+
+    /// 按照种子与房间设置发牌
+    pub fn from_seed_with_config(seats: [UserId; 3], seed: u64, config: &RoomConfig) -> HandHistory {
+        let mut game = Game::new();
+        config.apply(&mut game);
+        for user in seats.iter() {
+            game.add_player(Player::new(user.clone()));
+        }
+        game.start_with_rng(&mut StdRng::seed_from_u64(seed))
+            .map(|_| ())
+            .expect("Cannot start game");
+
+        let mut history = HandHistory::from_game(&game);
+        history.seed = Some(seed);
+        history
+    }
+
+    /// 记录一步操作
+    pub fn push(&mut self, seat: usize, action: Action) {
+        self.actions.push((seat, action));
+    }
+
+    /// 获取标签的值
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    /// 创建发完牌、等待叫地主的游戏
+    pub fn to_game(&self) -> Game {
+        let mut game = Game::new();
+        self.config.apply(&mut game);
+        for (i, user) in self.seats.iter().enumerate() {
+            let mut player = Player::new(user.clone());
+            player.cards = self.hands[i].clone();
+            player.cards.sort();
+            game.add_player(player);
+        }
+        for seat in self.shown_before_deal.iter() {
+            let _ = game.show_hand(*seat);
+        }
+        game.landlord_cards = self.landlord_cards.clone();
+        game.landlord_index = self.first_bidder;
+        game.state = GameState::WaitingForLandlord;
+        game
+    }
+
+    /// 按顺序重放所有操作，检查每一步是否合法以及结果是否与记录一致
+    /// 返回重放后的游戏
+    pub fn replay(&self) -> Result<Game, HistoryError> {
+        let mut game = self.to_game();
+        let mut result = None;
+        let mut ended = false;
+
+        for (i, (seat, action)) in self.actions.iter().enumerate() {
+            if ended {
+                return Err(HistoryError::AfterResult(i + 1));
+            }
+            let bidding = game.state == GameState::WaitingForLandlord;
+            // 明牌、投降与逃跑不需要轮到自己
+            let expected = match (action, game.state) {
+                (Action::ShowHand, _) | (Action::Surrender, _) | (Action::Escape, _) => *seat,
+                (_, GameState::WaitingForLandlord) => game.landlord_index,
+                _ => game.index,
+            };
+            if *seat != expected || *seat >= 3 {
+                return Err(HistoryError::WrongSeat(i + 1, *seat));
+            }
+
+            let outcome = match (action, &game.state) {
+                (Action::Call, GameState::WaitingForLandlord) => game.bid(true),
+                (Action::Decline, GameState::WaitingForLandlord) => game.bid(false),
+                (Action::Play(cards), _) => game.submit_cards(cards.clone()).map(|_| ()),
+                (Action::Pass, _) => game.pass().map(|_| ()),
+                (Action::ShowHand, _) => game.show_hand(*seat).map(|_| ()),
+                (Action::Surrender, _) => Err(game.surrender(game.players[*seat].player_type.clone())),
+                (Action::Escape, _) => Err(game.escape(*seat)),
+                _ => Err(GameError::NotReady),
+            };
+            match outcome {
+                Ok(()) => {}
+                Err(GameError::Win(_, winner, settlement)) => {
+                    result = Some(HandResult { winner, points: settlement.points });
+                    ended = true;
+                }
+                // 叫地主时逃跑没有获胜的一方
+                Err(GameError::Escaped(_, _)) if bidding => ended = true,
+                Err(GameError::Escaped(_, settlement)) => {
+                    let winner = escape_winner(&game.players[*seat].player_type);
+                    result = Some(HandResult { winner, points: settlement.points });
+                    ended = true;
+                }
+                Err(e) => return Err(HistoryError::Game(i + 1, e)),
+            }
+        }
+
+        if self.result.is_some() && result != self.result {
+            return Err(HistoryError::ResultMismatch);
+        }
+        Ok(game)
+    }
+}
+
+impl Display for HandHistory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (name, value) in self.tags.iter() {
+            writeln!(f, "[{} \"{}\"]", name, escape(value))?;
+        }
+        for (i, user) in self.seats.iter().enumerate() {
+            writeln!(f, "[Seat{} \"{}\"]", i, escape(user))?;
+        }
+        if let Some(seed) = self.seed {
+            writeln!(f, "[Seed \"{}\"]", seed)?;
+        }
+        let default = RoomConfig::default();
+        if self.config.deal_mode != default.deal_mode {
+            writeln!(f, "[DealMode \"{}\"]", deal_mode_to_str(self.config.deal_mode))?;
+        }
+        if let Some(cap) = self.config.bomb_cap {
+            writeln!(f, "[BombCap \"{}\"]", cap)?;
+        }
+        if self.config.base_points != default.base_points {
+            writeln!(f, "[BasePoints \"{}\"]", self.config.base_points)?;
+        }
+        if let Some(max) = self.config.max_multiplier {
+            writeln!(f, "[MaxMultiplier \"{}\"]", max)?;
+        }
+        if !self.shown_before_deal.is_empty() {
+            let seats: Vec<String> = self.shown_before_deal.iter().map(|x| x.to_string()).collect();
+            writeln!(f, "[ShowBeforeDeal \"{}\"]", seats.join(" "))?;
+        }
+        for (i, hand) in self.hands.iter().enumerate() {
+            writeln!(f, "[Hand{} \"{}\"]", i, cards_to_string(hand))?;
+        }
+        writeln!(f, "[Bottom \"{}\"]", cards_to_string(&self.landlord_cards))?;
+        writeln!(f, "[FirstBidder \"{}\"]", self.first_bidder)?;
+        if let Some(result) = &self.result {
+            let winner = match result.winner {
+                PlayerType::Landlord => "地主",
+                PlayerType::Farmer => "农民",
+            };
+            writeln!(f, "[Result \"{} {}\"]", winner, result.points)?;
+        }
+
+        writeln!(f)?;
+        for (seat, action) in self.actions.iter() {
+            writeln!(f, "{} {}", seat, action)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for HandHistory {
+    type Err = HistoryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tags: Vec<(String, String)> = vec![];
+        let mut actions = vec![];
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                if !actions.is_empty() {
+                    return Err(HistoryError::Syntax(i + 1));
+                }
+                tags.push(parse_tag(line).ok_or(HistoryError::Syntax(i + 1))?);
+            } else {
+                let (seat, action) = line.split_once(' ').ok_or(HistoryError::Syntax(i + 1))?;
+                let seat = parse_seat(seat).ok_or(HistoryError::Syntax(i + 1))?;
+                actions.push((seat, action.parse::<Action>()?));
+            }
+        }
+
+        let mut take = |name: &str| -> Option<String> {
+            let index = tags.iter().position(|(k, _)| k == name)?;
+            Some(tags.remove(index).1)
+        };
+
+        let seats = [
+            take("Seat0").ok_or(HistoryError::MissingTag("Seat0"))?,
+            take("Seat1").ok_or(HistoryError::MissingTag("Seat1"))?,
+            take("Seat2").ok_or(HistoryError::MissingTag("Seat2"))?,
+        ];
+        let seed = match take("Seed") {
+            Some(seed) => Some(seed.trim().parse::<u64>().map_err(|_| HistoryError::InvalidTag("Seed"))?),
+            None => None,
+        };
+
+        // 发牌方式影响按种子生成的发牌，需要在发牌前读取
+        let mut config = RoomConfig::default();
+        if let Some(mode) = take("DealMode") {
+            config.deal_mode = parse_deal_mode(&mode).ok_or(HistoryError::InvalidTag("DealMode"))?;
+        }
+        if let Some(cap) = take("BombCap") {
+            config.bomb_cap = Some(cap.trim().parse().map_err(|_| HistoryError::InvalidTag("BombCap"))?);
+        }
+        if let Some(points) = take("BasePoints") {
+            config.base_points = points.trim().parse().map_err(|_| HistoryError::InvalidTag("BasePoints"))?;
+        }
+        if let Some(max) = take("MaxMultiplier") {
+            config.max_multiplier = Some(max.trim().parse().map_err(|_| HistoryError::InvalidTag("MaxMultiplier"))?);
+        }
+
+        let mut history = match seed {
+            Some(seed) => HandHistory::from_seed_with_config(seats, seed, &config),
+            None => HandHistory {
+                tags: vec![],
+                seats,
+                seed: None,
+                hands: Default::default(),
+                landlord_cards: vec![],
+                first_bidder: 0,
+                config,
+                shown_before_deal: vec![],
+                actions: vec![],
+                result: None,
+            },
+        };
+        if let Some(seats) = take("ShowBeforeDeal") {
+            history.shown_before_deal = seats
+                .split_whitespace()
+                .map(parse_seat)
+                .collect::<Option<Vec<usize>>>()
+                .ok_or(HistoryError::InvalidTag("ShowBeforeDeal"))?;
+        }
+
+        // 发牌和种子同时给出时以发牌为准
+        for (i, name) in ["Hand0", "Hand1", "Hand2"].iter().enumerate() {
+            match take(name) {
+                Some(hand) => history.hands[i] = parse_cards(&hand)?,
+                None if seed.is_none() => return Err(HistoryError::MissingTag(name)),
+                None => {}
+            }
+        }
+        match take("Bottom") {
+            Some(bottom) => history.landlord_cards = parse_cards(&bottom)?,
+            None if seed.is_none() => return Err(HistoryError::MissingTag("Bottom")),
+            None => {}
+        }
+        if let Some(first) = take("FirstBidder") {
+            history.first_bidder = parse_seat(&first).ok_or(HistoryError::InvalidTag("FirstBidder"))?;
+        }
+        if let Some(result) = take("Result") {
+            history.result = Some(parse_result(&result).ok_or(HistoryError::InvalidTag("Result"))?);
+        }
+
+        history.tags = tags;
+        history.actions = actions;
+        Ok(history)
+    }
+}
+
+/// 以文本格式保存
+impl Serialize for HandHistory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for HandHistory {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+fn cards_to_string(cards: &[Card]) -> String {
+    CardList(cards.to_vec()).to_string()
+}
+
+fn parse_cards(input: &str) -> Result<Vec<Card>, HistoryError> {
+    Ok(input.parse::<CardList>()?.0)
+}
+
+fn parse_seat(input: &str) -> Option<usize> {
+    input.trim().parse::<usize>().ok().filter(|x| *x < 3)
+}
+
+/// `[Name "value"]`
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    let (name, value) = inner.split_once(' ')?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((name.to_string(), value.replace("\\\"", "\"").replace("\\\\", "\\")))
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn deal_mode_to_str(mode: DealMode) -> &'static str {
+    match mode {
+        DealMode::Shuffle => "洗牌",
+        DealMode::NoShuffle => "不洗牌",
+    }
+}
+
+fn parse_deal_mode(input: &str) -> Option<DealMode> {
+    match input.trim() {
+        "洗牌" => Some(DealMode::Shuffle),
+        "不洗牌" => Some(DealMode::NoShuffle),
+        _ => None,
+    }
+}
+
+/// 确定地主后逃跑的玩家判负，另一方记为获胜
+pub(crate) fn escape_winner(escaped: &PlayerType) -> PlayerType {
+    match escaped {
+        PlayerType::Landlord => PlayerType::Farmer,
+        PlayerType::Farmer => PlayerType::Landlord,
+    }
+}
+
+/// `地主 200`
+fn parse_result(input: &str) -> Option<HandResult> {
+    let (winner, points) = input.trim().split_once(' ')?;
+    let winner = match winner {
+        "地主" => PlayerType::Landlord,
+        "农民" => PlayerType::Farmer,
+        _ => return None,
+    };
+    Some(HandResult {
+        winner,
+        points: points.trim().parse().ok()?,
+    })
+}
@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// 语言
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Locale {
+    /// 简体中文
+    #[default]
+    ZhCn,
+
+    /// 美式英语
+    EnUs,
+}
+
+impl Locale {
+    /// BCP 47 语言标签
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Locale::ZhCn => "zh-CN",
+            Locale::EnUs => "en-US",
+        }
+    }
+
+    /// 依次读取环境变量 `CARDGAME_LANG`、`LANG`，无法识别时使用默认语言
+    pub fn from_env() -> Locale {
+        ["CARDGAME_LANG", "LANG"]
+            .iter()
+            .filter_map(|x| std::env::var(x).ok())
+            .find_map(|x| x.parse().ok())
+            .unwrap_or_default()
+    }
+
+    fn catalog(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Locale::ZhCn => ZH_CN,
+            Locale::EnUs => EN_US,
+        }
+    }
+}
+
+impl FromStr for Locale {
+    type Err = ();
+
+    /// 接受 `zh-CN`、`zh_CN.UTF-8`、`en`、`en_US` 等写法
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lang = s.split(['-', '_', '.']).next().unwrap_or("");
+        match lang.to_ascii_lowercase().as_str() {
+            "zh" => Ok(Locale::ZhCn),
+            "en" => Ok(Locale::EnUs),
+            _ => Err(()),
+        }
+    }
+}
+
+/// 可以按语言显示的消息，例如错误
+pub trait Localize {
+    /// 稳定的消息代码，同时作为消息目录的键
+    fn code(&self) -> &'static str;
+
+    /// 填入消息模板 `{0}`、`{1}` 等位置的参数
+    fn args(&self, _locale: Locale) -> Vec<String> {
+        vec![]
+    }
+
+    fn localize(&self, locale: Locale) -> String {
+        format_message(locale, self.code(), &self.args(locale))
+    }
+}
+
+/// 查找消息模板，当前语言缺少该消息时使用默认语言
+pub fn message(locale: Locale, code: &str) -> Option<&'static str> {
+    let find = |locale: Locale| locale.catalog().iter().find(|(k, _)| *k == code).map(|(_, v)| *v);
+    find(locale).or_else(|| find(Locale::default()))
+}
+
+/// 按代码取得消息并填入参数，找不到消息时返回代码本身
+pub fn format_message(locale: Locale, code: &str, args: &[String]) -> String {
+    let mut text = message(locale, code).unwrap_or(code).to_string();
+    for (i, arg) in args.iter().enumerate() {
+        text = text.replace(&format!("{{{}}}", i), arg);
+    }
+    text
+}
+
+const ZH_CN: &[(&str, &str)] = &[
+    ("player.farmer", "农民"),
+    ("player.landlord", "地主"),
+    ("rule.none", "无"),
+    ("rule.one", "单牌"),
+    ("rule.two", "对子"),
+    ("rule.three_with_one", "三带一"),
+    ("rule.three_with_two", "三带二"),
+    ("rule.four_with_two", "四带二"),
+    ("rule.bomb", "炸弹"),
+    ("rule.rocket", "火箭"),
+    ("rule.chain", "顺子"),
+    ("rule.pair_chain", "连对"),
+    ("rule.triple_chain", "飞机"),
+    ("rule.airplane_one_wing", "单翼飞机"),
+    ("rule.airplane_two_wings", "双翼飞机"),
+    ("cards.invalid_token", "无法识别第 {0} 个字符“{1}”"),
+    ("room.not_ready", "房间未准备好"),
+    ("room.not_started", "游戏尚未开始"),
+    ("room.not_landlord_player", "不是你叫地主"),
+    ("room.full", "房间已满"),
+    ("room.no_surrender_vote", "现在没有投降投票"),
+    ("room.not_conceding_side", "只有投降的一方可以否决投降"),
+    ("lobby.joined", "你已经加入了房间"),
+    ("lobby.room_full", "房间已满"),
+    ("lobby.room_exists", "房间已存在"),
+    ("lobby.other", "大厅错误"),
+    ("game.not_enough_players", "玩家人数不足"),
+    ("game.not_running", "游戏尚未开始"),
+    ("game.is_running", "游戏已经开始"),
+    ("game.not_ready", "现在不是叫地主的时候"),
+    ("game.not_your_turn", "还没轮到你出牌"),
+    ("game.no_such_cards", "你没有这些牌：{0}"),
+    ("game.wrong_rule", "你出的{0}压不过 {1}，需要比 {2} 大的{3}{4}"),
+    ("game.bomb_hint", "，也可以出炸弹 {0}"),
+    ("game.no_rule", "你出的牌不匹配任何规则，最接近的牌型是{0}"),
+    ("game.win", "{0}（{1}）赢了，获得 {2} 分：{3}"),
+    ("settlement.breakdown", "底分 {0} × {1} 倍（炸弹 {2} 次，火箭 {3} 次{4}）"),
+    ("settlement.show_hand", "，明牌 {0} 倍"),
+    ("settlement.capped", "，{0} 倍已封顶"),
+    ("game.your_turn", "现在由你出牌，不能过牌"),
+    ("game.already_shown", "你已经明牌了"),
+    ("game.last_cards", "注意：{0} 只剩 {1} 张牌了！"),
+    ("game.escaped", "{0} 逃跑了，按最高倍率扣除 {1} 分，其他玩家各获得 {2} 分"),
+    ("advisor.hint", "建议{0}（把握 {1}%）：最少 {2} 手出完，{3} 张控制牌，{4} 张小单牌"),
+    ("advisor.call", "叫地主"),
+    ("advisor.decline", "不叫"),
+    ("fair.verified", "洗牌验证通过，种子与开局时的承诺一致"),
+    ("fair.invalid_seed", "公开的洗牌种子无效"),
+    ("fair.commitment_mismatch", "洗牌种子与开局时的承诺不符"),
+    ("fair.unknown_player", "你不在这局游戏中"),
+    ("fair.deal_mismatch", "按公开的种子发出的牌与你的手牌不同"),
+    ("fair.not_committed", "服务器还没有公布洗牌承诺，不能提交随机数"),
+    ("fair.already_dealt", "这局已经发牌，随机数没有参与洗牌"),
+    ("auth.unknown_user", "用户不存在"),
+    ("auth.wrong_password", "密码错误"),
+    ("auth.user_exists", "用户名已被注册"),
+    ("auth.invalid_name", "用户名只能包含文字、数字与下划线，最多 16 个字符"),
+    ("auth.invalid_token", "登入已失效，请重新登入"),
+    ("auth.not_logged_in", "请先登入"),
+    ("auth.session_taken_over", "你的账号在其他地方登入了"),
+    ("auth.storage_failed", "服务器暂时无法保存账号，请稍后再试"),
+];
+
+const EN_US: &[(&str, &str)] = &[
+    ("player.farmer", "farmer"),
+    ("player.landlord", "landlord"),
+    ("rule.none", "nothing"),
+    ("rule.one", "single"),
+    ("rule.two", "pair"),
+    ("rule.three_with_one", "trio with a single"),
+    ("rule.three_with_two", "trio with a pair"),
+    ("rule.four_with_two", "four with two"),
+    ("rule.bomb", "bomb"),
+    ("rule.rocket", "rocket"),
+    ("rule.chain", "straight"),
+    ("rule.pair_chain", "pair straight"),
+    ("rule.triple_chain", "airplane"),
+    ("rule.airplane_one_wing", "airplane with singles"),
+    ("rule.airplane_two_wings", "airplane with pairs"),
+    ("cards.invalid_token", "Unrecognized character \"{1}\" at position {0}"),
+    ("room.not_ready", "The room is not ready"),
+    ("room.not_started", "The game has not started yet"),
+    ("room.not_landlord_player", "It is not your turn to bid"),
+    ("room.full", "The room is full"),
+    ("room.no_surrender_vote", "There is no surrender vote in progress"),
+    ("room.not_conceding_side", "Only the conceding side can reject a surrender"),
+    ("lobby.joined", "You have already joined a room"),
+    ("lobby.room_full", "The room is full"),
+    ("lobby.room_exists", "The room already exists"),
+    ("lobby.other", "Lobby error"),
+    ("game.not_enough_players", "Not enough players"),
+    ("game.not_running", "The game has not started yet"),
+    ("game.is_running", "The game is already running"),
+    ("game.not_ready", "Bidding is not in progress"),
+    ("game.not_your_turn", "It is not your turn"),
+    ("game.no_such_cards", "You do not have these cards: {0}"),
+    ("game.wrong_rule", "Your {0} does not beat {1}; you need a {3} higher than {2}{4}"),
+    ("game.bomb_hint", ", or you can bomb with {0}"),
+    ("game.no_rule", "Your play does not match any pattern; the closest is {0}"),
+    ("game.win", "{0} ({1}) won {2} points: {3}"),
+    ("settlement.breakdown", "base {0} × {1} ({2} bombs, {3} rockets{4})"),
+    ("settlement.show_hand", ", show hand ×{0}"),
+    ("settlement.capped", ", capped from {0}"),
+    ("game.your_turn", "You are leading and cannot pass"),
+    ("game.already_shown", "You have already shown your hand"),
+    ("game.last_cards", "Warning: {0} has only {1} card(s) left!"),
+    ("game.escaped", "{0} left the game and loses {1} points at the maximum multiplier; the other players receive {2} points each"),
+    ("advisor.hint", "Suggestion: {0} ({1}% confident): at least {2} plays, {3} controls, {4} weak singles"),
+    ("advisor.call", "bid for landlord"),
+    ("advisor.decline", "pass on landlord"),
+    ("fair.verified", "Deal verified: the seed matches the commitment"),
+    ("fair.invalid_seed", "The revealed seed is invalid"),
+    ("fair.commitment_mismatch", "The revealed seed does not match the commitment"),
+    ("fair.unknown_player", "You did not play in this game"),
+    ("fair.deal_mismatch", "The revealed seed does not deal your hand"),
+    ("fair.not_committed", "The server has not published its commitment yet, so your seed was not accepted"),
+    ("fair.already_dealt", "The cards have already been dealt, so your seed was not used"),
+    ("auth.unknown_user", "No such user"),
+    ("auth.wrong_password", "Wrong password"),
+    ("auth.user_exists", "This name is already registered"),
+    ("auth.invalid_name", "Names may contain only letters, digits and underscores, up to 16 characters"),
+    ("auth.invalid_token", "Your session has expired, please log in again"),
+    ("auth.not_logged_in", "Please log in first"),
+    ("auth.session_taken_over", "Your account has logged in from somewhere else"),
+    ("auth.storage_failed", "The server cannot save your account right now, please try again later"),
+];
@@ -0,0 +1,235 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use crate::card::*;
+use crate::game::{new_deck, Game};
+use crate::observer::GameObserver;
+use crate::rule::*;
+
+/// 从一个座位的视角记录已经出过的牌，推断其他两家的手牌
+pub struct CardTracker {
+    /// 观察者的座位
+    pub seat: usize,
+
+    /// 观察者当前的手牌
+    pub hand: Vec<Card>,
+
+    /// 地主座位，叫完地主之前为 None
+    pub landlord: Option<usize>,
+
+    /// 每个座位的剩余牌数
+    pub remaining: [usize; 3],
+
+    /// 每个座位出过的牌
+    pub played: [Vec<Card>; 3],
+
+    /// 确定在某个座位手中的牌，即地主尚未打出的地主牌与明牌玩家的手牌
+    known: [CardStore; 3],
+
+    /// 每个座位过牌时没有压的牌
+    passed: [Vec<Vec<Card>>; 3],
+
+    /// 最后一手牌及出牌的座位
+    last: Option<(usize, Vec<Card>)>,
+}
+
+impl CardTracker {
+    /// 刚发完牌时的视角
+    pub fn new(seat: usize, hand: &[Card]) -> CardTracker {
+        CardTracker {
+            seat,
+            hand: hand.to_vec(),
+            landlord: None,
+            remaining: [17; 3],
+            played: Default::default(),
+            known: Default::default(),
+            passed: Default::default(),
+            last: None,
+        }
+    }
+
+    /// 地主确定，地主牌是公开的
+    pub fn set_landlord(&mut self, landlord: usize, landlord_cards: &[Card]) {
+        self.landlord = Some(landlord);
+        self.remaining[landlord] += landlord_cards.len();
+        if landlord == self.seat {
+            self.hand.extend_from_slice(landlord_cards);
+            self.hand.sort();
+        } else {
+            // 地主可能已经明牌
+            for card in landlord_cards {
+                self.known[landlord].add(card);
+            }
+        }
+    }
+
+    /// 座位明牌，手牌全部确定
+    pub fn set_shown(&mut self, seat: usize, hand: &[Card]) {
+        if seat != self.seat {
+            self.known[seat] = CardStore::from_cards(hand);
+        }
+    }
+
+    pub fn record_play(&mut self, seat: usize, cards: &[Card]) {
+        self.remaining[seat] -= cards.len();
+        self.played[seat].extend_from_slice(cards);
+        let mut hand = CardStore::from_cards(&self.hand);
+        for card in cards {
+            // 打出的可能正是某张已知的牌，剩下的已知牌仍然一定在手中
+            self.known[seat].remove(card);
+            if seat == self.seat {
+                hand.remove(card);
+            }
+        }
+        self.hand = hand.get_cards();
+        self.last = Some((seat, cards.to_vec()));
+    }
+
+    /// 过牌说明手中没有同牌型的更大的牌，炸弹与火箭除外
+    /// 不压队友的牌是正常的，此时不做推断
+    pub fn record_pass(&mut self, seat: usize) {
+        if let Some((last_seat, cards)) = &self.last {
+            let partners = self.landlord.is_some_and(|x| x != seat && x != *last_seat);
+            if *last_seat != seat && !partners {
+                self.passed[seat].push(cards.clone());
+            }
+        }
+    }
+
+    /// 座位过牌时没有压的牌
+    pub fn passed_on(&self, seat: usize) -> &[Vec<Card>] {
+        &self.passed[seat]
+    }
+
+    /// 观察者看不到的牌，即其他两家手牌的总和
+    pub fn unseen(&self) -> Vec<Card> {
+        let mut store = CardStore::from_cards(&new_deck());
+        for card in self.hand.iter().chain(self.played.iter().flatten()) {
+            store.remove(card);
+        }
+        store.get_cards()
+    }
+
+    /// 手牌是否与座位的过牌记录一致
+    pub fn is_consistent(&self, seat: usize, hand: &[Card]) -> bool {
+        self.passed[seat].iter().all(|last| {
+            valid_plays(&*match_rule(last), hand)
+                .iter()
+                .all(|x| match_rule(x).bomb_priority() > 0)
+        })
+    }
+
+    /// 随机生成一组与已知信息一致的手牌，观察者的座位为自己的手牌
+    /// 使用拒绝采样，`max_attempts` 次都不满足约束时返回 None
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R, max_attempts: usize) -> Option<[Vec<Card>; 3]> {
+        let mut free = CardStore::from_cards(&self.unseen());
+        for known in self.known.iter() {
+            for card in known.get_cards() {
+                free.remove(&card);
+            }
+        }
+        let mut free = free.get_cards();
+        let others = [(self.seat + 1) % 3, (self.seat + 2) % 3];
+        let first = others[0];
+        let first_free = self.remaining[first] - self.known[first].len();
+
+        for _ in 0..max_attempts {
+            free.shuffle(rng);
+            let mut hands: [Vec<Card>; 3] = Default::default();
+            hands[self.seat] = self.hand.clone();
+            hands[others[0]] = free[..first_free].to_vec();
+            hands[others[1]] = free[first_free..].to_vec();
+            for seat in others.iter() {
+                hands[*seat].extend(self.known[*seat].get_cards());
+                hands[*seat].sort();
+            }
+            if others.iter().all(|x| self.is_consistent(*x, &hands[*x])) {
+                return Some(hands);
+            }
+        }
+        None
+    }
+
+    /// 用 `samples` 次采样估计座位的手牌满足 `predicate` 的概率
+    /// 无法采样到一致的手牌时返回 None
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cardgame::{has_bomb, parse_input, CardTracker};
+    /// use rand::rngs::StdRng;
+    /// use rand::SeedableRng;
+    /// let tracker = CardTracker::new(0, &parse_input("33334444555566667").unwrap());
+    /// let mut rng = StdRng::seed_from_u64(0);
+    /// let p = tracker.probability(1, 200, &mut rng, has_bomb).unwrap();
+    /// assert!(p > 0.0 && p < 1.0);
+    /// ```
+    pub fn probability<R, F>(&self, seat: usize, samples: usize, rng: &mut R, predicate: F) -> Option<f64>
+    where
+        R: Rng + ?Sized,
+        F: Fn(&[Card]) -> bool,
+    {
+        let mut hits = 0;
+        for _ in 0..samples {
+            let hands = self.sample(rng, MAX_ATTEMPTS)?;
+            if predicate(&hands[seat]) {
+                hits += 1;
+            }
+        }
+        Some(hits as f64 / samples.max(1) as f64)
+    }
+
+    /// 估计座位手中至少有一张某点数的牌的概率，按 `Card::value` 索引
+    pub fn rank_probabilities<R: Rng + ?Sized>(&self, seat: usize, samples: usize, rng: &mut R) -> Option<[f64; 16]> {
+        let mut counts = [0u32; 16];
+        for _ in 0..samples {
+            let hands = self.sample(rng, MAX_ATTEMPTS)?;
+            let groups = to_card_groups(&hands[seat]);
+            for group in groups.groups.iter() {
+                counts[group.card.value() as usize] += 1;
+            }
+        }
+        let mut probabilities = [0.0; 16];
+        for (p, count) in probabilities.iter_mut().zip(counts.iter()) {
+            *p = *count as f64 / samples.max(1) as f64;
+        }
+        Some(probabilities)
+    }
+}
+
+/// 每次采样的最大尝试次数
+const MAX_ATTEMPTS: usize = 10_000;
+
+/// 手牌中是否有炸弹或火箭
+pub fn has_bomb(cards: &[Card]) -> bool {
+    let groups = to_card_groups(cards);
+    groups.groups.iter().any(|x| x.count == 4)
+        || (groups.find_group_by_card(Card::CardGhost).is_some() && groups.find_group_by_card(Card::CardKing).is_some())
+}
+
+/// 挂在游戏上时自动记录，发牌时按 `seat` 重置
+impl GameObserver for CardTracker {
+    fn on_deal(&mut self, game: &Game) {
+        *self = CardTracker::new(self.seat, &game.players[self.seat].cards);
+        for (seat, player) in game.players.iter().enumerate() {
+            if player.shown {
+                self.set_shown(seat, &player.cards);
+            }
+        }
+    }
+
+    fn on_show_hand(&mut self, game: &Game, seat: usize) {
+        self.set_shown(seat, &game.players[seat].cards);
+    }
+
+    fn on_landlord(&mut self, game: &Game, seat: usize) {
+        self.set_landlord(seat, &game.landlord_cards);
+    }
+
+    fn on_play(&mut self, _game: &Game, seat: usize, cards: &[Card]) {
+        self.record_play(seat, cards);
+    }
+
+    fn on_pass(&mut self, _game: &Game, seat: usize) {
+        self.record_pass(seat);
+    }
+}
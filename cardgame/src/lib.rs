@@ -33,6 +33,18 @@ mod tests {
             assert!(!rule.matches(&parse_input("33").unwrap()));
         }
 
+        /// 顺子、连对与飞机只能被同样宽度的牌压过
+        #[test]
+        fn chain_width() {
+            let rule = rule_from_str("34567");
+            assert!(rule.matches(&parse_input("45678").unwrap()));
+            assert!(!rule.matches(&parse_input("3344556677").unwrap()));
+            assert!(!rule.matches(&parse_input("4455667788").unwrap()));
+            let rule = rule_from_str("334455");
+            assert!(rule.matches(&parse_input("445566").unwrap()));
+            assert!(!rule.matches(&parse_input("444555666").unwrap()));
+        }
+
         #[test]
         fn test_gen_card() {
             let mut cards = CardStore::default();
@@ -77,6 +89,1211 @@ mod tests {
         }
     }
 
+    mod cards {
+        use crate::{gen_cards, parse_input, to_card_groups, CardList, Locale, Localize};
+        use crate::Card::{Card10, CardGhost, CardKing, CardJ};
+
+        #[test]
+        fn aliases() {
+            let expected = vec![Card10, Card10, CardJ, CardGhost, CardKing];
+            for input in ["1010j鬼王", "T t J X D", "10,T、j [BJ][RJ]", "10 10 J 小王 大王"].iter() {
+                assert_eq!(input.parse::<CardList>().unwrap().0, expected);
+            }
+        }
+
+        #[test]
+        fn round_trip() {
+            let cards = CardList(gen_cards());
+            assert_eq!(cards.to_string().parse::<CardList>().unwrap(), cards);
+        }
+
+        #[test]
+        fn invalid_token() {
+            let error = "3 4 1 5".parse::<CardList>().unwrap_err();
+            assert_eq!(error.token, "1");
+            assert_eq!(error.position, 4);
+            // parse_input 在无法识别的字符处停止，不会跳过它
+            assert_eq!(parse_input("341 5"), parse_input("34"));
+            assert_eq!(CardList::parse_lenient("3 4 1 5").0.len(), 3);
+            assert_eq!(error.to_string(), "无法识别第 5 个字符“1”");
+            assert_eq!(error.localize(Locale::EnUs), "Unrecognized character \"1\" at position 5");
+        }
+
+        #[test]
+        fn subtract_groups() {
+            let groups = |x: &str| to_card_groups(&parse_input(x).unwrap());
+            // 只减去右边有的牌，左边多出的牌保留
+            let rest = (groups("334455") - groups("34")).unwrap();
+            assert_eq!(rest.into_cards(), parse_input("3455").unwrap());
+            assert!((groups("3344") - groups("5")).is_none());
+            assert!((groups("34") - groups("33")).is_none());
+        }
+    }
+
+    mod solver {
+        use crate::{all_plays, parse_input, Position, Solver};
+
+        fn hands(a: &str, b: &str, c: &str) -> [Vec<crate::Card>; 3] {
+            [parse_input(a).unwrap(), parse_input(b).unwrap(), parse_input(c).unwrap()]
+        }
+
+        #[test]
+        fn plays() {
+            let plays = all_plays(&parse_input("33344456").unwrap());
+            assert!(plays.contains(&parse_input("33344456").unwrap()));
+            assert!(plays.contains(&parse_input("333444").unwrap()));
+            assert!(plays.contains(&parse_input("3336").unwrap()));
+            assert!(!plays.contains(&parse_input("333").unwrap()));
+        }
+
+        /// 地主无论出哪张单牌都会被农民的 2 压住
+        #[test]
+        fn landlord_loses() {
+            let position = Position {
+                hands: hands("34", "2", "5"),
+                landlord: 0,
+                turn: 0,
+                last: None,
+            };
+            let solution = Solver::new().solve(&position);
+            assert!(!solution.win);
+            assert_eq!(solution.line.last().unwrap().0, 1);
+        }
+
+        #[test]
+        fn farmers_win() {
+            let position = Position {
+                hands: hands("KK", "AA4", "5"),
+                landlord: 0,
+                turn: 1,
+                last: Some((0, parse_input("3").unwrap())),
+            };
+            let solution = Solver::new().solve(&position);
+            assert!(solution.win);
+            assert_ne!(solution.line.last().unwrap().0, 0);
+        }
+    }
+
+    mod puzzle {
+        use crate::error::PuzzleError;
+        use crate::{Game, GameState, Position, Puzzle, Solver};
+
+        #[test]
+        fn load() {
+            let puzzle = Puzzle::from_json(r#"{
+                "name": "test",
+                "hands": ["KK", "AA4", "5"],
+                "landlord": 0,
+                "player": 1,
+                "last": [0, "3"]
+            }"#).unwrap();
+
+            let mut game: Game = puzzle.to_game(["a".into(), "b".into(), "c".into()]).unwrap();
+            assert!(game.state == GameState::Running);
+            let next = Solver::new().best_move(&Position::from_game(&game));
+            assert!(matches!(next, crate::Move::Play(_)));
+            assert!(game.pass().is_ok());
+        }
+
+        #[test]
+        fn unsolvable() {
+            let result = Puzzle::from_json(r#"{
+                "name": "test",
+                "hands": ["34", "2", "5"],
+                "landlord": 0,
+                "player": 0
+            }"#);
+            assert!(matches!(result, Err(PuzzleError::Unsolvable)));
+
+            let result = Puzzle::from_json(r#"{
+                "name": "test",
+                "hands": ["33333", "2", "5"],
+                "landlord": 0,
+                "player": 0
+            }"#);
+            assert!(matches!(result, Err(PuzzleError::TooManyCards(_))));
+        }
+    }
+
+    mod env {
+        use crate::{Bot, Env, GreedyBot, RandomBot, RoomConfig, ACTION_CALL, ACTION_DECLINE, OBSERVATION_SIZE};
+
+        #[test]
+        fn same_seed() {
+            let mut env = Env::new();
+            let first = env.reset(7);
+            assert_eq!(first.observation.len(), OBSERVATION_SIZE);
+            assert_eq!(first.mask.iter().filter(|x| **x).count(), 2);
+
+            let step = env.step(ACTION_CALL).unwrap();
+            let hand = env.game().players[step.seat].cards.clone();
+            assert_eq!(hand.len(), 20);
+            assert!(env.step(0).is_err());
+
+            env.reset(7);
+            env.step(ACTION_CALL).unwrap();
+            assert_eq!(env.game().players[step.seat].cards, hand);
+        }
+
+        #[test]
+        fn shown_hand() {
+            let mut env = Env::new();
+            env.reset(8);
+            // 下家的手牌位于观测的 117 到 131
+            let hidden = env.observation(0);
+            assert!(hidden[117..].iter().all(|x| *x == 0.0));
+            env.show_hand(1).unwrap();
+            let shown = env.observation(0);
+            let total: f32 = shown[117..132].iter().sum();
+            assert_eq!(total * 4.0, 17.0);
+            assert!(shown[132..].iter().all(|x| *x == 0.0));
+        }
+
+        #[test]
+        fn settlement_rewards() {
+            let config = RoomConfig::from_args(&["底分50", "封顶2"]).unwrap();
+            let mut env = Env::with_config(config);
+            let mut bot = GreedyBot;
+            env.reset(9);
+            let mut step = env.step(ACTION_CALL).unwrap();
+            while !step.done {
+                step = env.step(env.bot_action(&mut bot)).unwrap();
+            }
+            let settlement = env.game().settle();
+            assert_eq!(settlement.base_points, 50);
+            let landlord = env.game().landlord_index;
+            assert_eq!(step.rewards[landlord].abs(), settlement.points as f32 * 2.0);
+            assert_eq!(step.rewards.iter().sum::<f32>(), 0.0);
+        }
+
+        #[test]
+        fn all_decline() {
+            let mut env = Env::new();
+            let mut step = env.reset(10);
+            for _ in 0..3 {
+                assert!(!step.done);
+                step = env.step(ACTION_DECLINE).unwrap();
+            }
+            assert!(step.done);
+            assert_eq!(step.rewards, [0.0; 3]);
+            assert!(env.step(ACTION_CALL).is_err());
+        }
+
+        #[test]
+        fn bot_actions() {
+            let mut env = Env::new();
+            let mut bots: Vec<Box<dyn Bot>> = vec![Box::new(RandomBot::new(1)), Box::new(GreedyBot), Box::new(RandomBot::new(2))];
+            for seed in 0..5 {
+                let mut step = env.reset(seed);
+                while !step.done {
+                    let action = env.bot_action(&mut *bots[step.seat]);
+                    // 电脑玩家的动作总是合法的
+                    assert!(step.mask[action]);
+                    step = env.step(action).unwrap();
+                }
+            }
+        }
+    }
+
+    mod bot {
+        use crate::{parse_input, Bot, GreedyBot, RandomBot, Turn};
+
+        fn turn<'a>(hand: &'a [crate::Card], last: Option<&'a [crate::Card]>, partner_played: bool, remaining: [usize; 3]) -> Turn<'a> {
+            Turn {
+                hand,
+                last,
+                is_landlord: false,
+                partner_played,
+                remaining,
+            }
+        }
+
+        #[test]
+        fn random_same_seed() {
+            let hand = parse_input("334455678910JQKA2").unwrap();
+            let last = parse_input("4").unwrap();
+            let facing = turn(&hand, Some(&last), false, [17, 17, 20]);
+            let plays = facing.plays();
+            let (mut first, mut second) = (RandomBot::new(3), RandomBot::new(3));
+            for _ in 0..50 {
+                let play = first.play(&facing);
+                assert_eq!(play, second.play(&facing));
+                if let Some(play) = play {
+                    assert!(plays.contains(&play));
+                }
+            }
+            // 自由出牌时必须出牌
+            let leading = turn(&hand, None, false, [17, 17, 20]);
+            assert!((0..50).all(|_| first.play(&leading).is_some()));
+        }
+
+        #[test]
+        fn greedy_plays() {
+            let mut bot = GreedyBot;
+            let hand = parse_input("3559K").unwrap();
+            assert_eq!(bot.play(&turn(&hand, None, false, [5, 17, 17])), Some(parse_input("3").unwrap()));
+            let last = parse_input("4").unwrap();
+            assert_eq!(bot.play(&turn(&hand, Some(&last), false, [5, 17, 17])), Some(parse_input("5").unwrap()));
+            // 不压队友
+            assert_eq!(bot.play(&turn(&hand, Some(&last), true, [5, 17, 17])), None);
+
+            // 能一次出完时直接出完，即使压的是队友
+            let hand = parse_input("66").unwrap();
+            let last = parse_input("55").unwrap();
+            assert_eq!(bot.play(&turn(&hand, Some(&last), true, [2, 17, 17])), Some(hand.clone()));
+        }
+
+        #[test]
+        fn greedy_holds_bombs() {
+            let mut bot = GreedyBot;
+            let hand = parse_input("55553").unwrap();
+            let last = parse_input("K").unwrap();
+            assert_eq!(bot.play(&turn(&hand, Some(&last), false, [5, 10, 10])), None);
+            assert_eq!(bot.play(&turn(&hand, Some(&last), false, [5, 3, 10])), Some(parse_input("5555").unwrap()));
+        }
+    }
+
+    mod history {
+        use std::sync::mpsc::channel;
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+        use crate::error::{GameError, HistoryError};
+        use crate::{Action, Bot, Game, GameState, GreedyBot, HandHistory, Player, PlayerType, RoomConfig, StatsObserver, Turn};
+
+        /// 用电脑玩家打完一局并记录牌谱
+        fn play(seed: u64) -> HandHistory {
+            let mut history = HandHistory::from_seed(["a".into(), "b".into(), "c".into()], seed);
+            let mut game = history.to_game();
+            let mut bot = GreedyBot;
+
+            history.push(game.landlord_index, Action::Call);
+            game.run().unwrap();
+            while game.state == GameState::Running {
+                let seat = game.index;
+                let result = match bot.play(&Turn::from_game(&game)) {
+                    Some(cards) => {
+                        history.push(seat, Action::Play(cards.clone()));
+                        game.submit_cards(cards).map(|_| ())
+                    }
+                    None => {
+                        history.push(seat, Action::Pass);
+                        game.pass().map(|_| ())
+                    }
+                };
+                if let Err(GameError::Win(_, winner, settlement)) = result {
+                    history.result = Some(crate::HandResult { winner, points: settlement.points });
+                }
+            }
+            history
+        }
+
+        #[test]
+        fn round_trip() {
+            let history = play(3);
+            let text = history.to_string();
+            let parsed: HandHistory = text.parse().unwrap();
+            assert_eq!(parsed, history);
+            assert!(parsed.replay().is_ok());
+        }
+
+        #[test]
+        fn seed_only() {
+            let history = play(5);
+            let text: String = history
+                .to_string()
+                .lines()
+                .filter(|x| !x.starts_with("[Hand") && !x.starts_with("[Bottom") && !x.starts_with("[FirstBidder"))
+                .map(|x| format!("{}\n", x))
+                .collect();
+            let parsed: HandHistory = text.parse().unwrap();
+            assert_eq!(parsed.hands, history.hands);
+            assert!(parsed.replay().is_ok());
+        }
+
+        #[test]
+        fn illegal_move() {
+            let text = r#"
+                [Seat0 "a"]
+                [Seat1 "b"]
+                [Seat2 "c"]
+                [Hand0 "33"]
+                [Hand1 "44"]
+                [Hand2 "55"]
+                [Bottom "6"]
+
+                0 叫地主
+                0 33
+                2 55
+            "#;
+            let history: HandHistory = text.parse().unwrap();
+            assert_eq!(history.actions[1], (0, Action::Play(crate::parse_input("33").unwrap())));
+            assert!(matches!(history.replay(), Err(HistoryError::WrongSeat(3, 2))));
+        }
+
+        /// 记录一局由观察者生成的牌谱，`end` 在出了几手牌后结束游戏
+        fn record<F: FnOnce(&mut Game)>(config: &RoomConfig, end: F) -> HandHistory {
+            let (sender, receiver) = channel();
+            let mut game = Game::new();
+            config.apply(&mut game);
+            for user in ["a", "b", "c"].iter() {
+                game.add_player(Player::new(user.to_string()));
+            }
+            game.add_observer(Box::new(StatsObserver::new(sender)));
+            game.show_hand(1).unwrap();
+            game.start_with_rng(&mut StdRng::seed_from_u64(7)).map(|_| ()).unwrap();
+            game.show_hand(2).unwrap();
+            game.bid(true).unwrap();
+
+            let mut bot = GreedyBot;
+            for _ in 0..4 {
+                let _ = match bot.play(&Turn::from_game(&game)) {
+                    Some(cards) => game.submit_cards(cards).map(|_| ()),
+                    None => game.pass().map(|_| ()),
+                };
+            }
+            end(&mut game);
+            receiver.try_recv().unwrap().history.unwrap()
+        }
+
+        #[test]
+        fn config_and_surrender() {
+            let config = RoomConfig::from_args(&["不洗牌", "炸弹封顶1", "底分50", "封顶8"]).unwrap();
+            let history = record(&config, |game| {
+                game.surrender(PlayerType::Farmer);
+            });
+            let text = history.to_string();
+            assert!(text.contains("[BasePoints \"50\"]"));
+            assert!(text.contains("[ShowBeforeDeal \"1\"]"));
+            assert!(text.contains("2 明牌"));
+            assert_eq!(history.actions.last().unwrap().1, Action::Surrender);
+
+            let parsed: HandHistory = text.parse().unwrap();
+            assert_eq!(parsed, history);
+            let game = parsed.replay().unwrap();
+            assert_eq!(game.base_points, 50);
+            assert_eq!(parsed.result.unwrap().winner, PlayerType::Landlord);
+        }
+
+        #[test]
+        fn escape() {
+            let history = record(&RoomConfig::default(), |game| {
+                game.escape(0);
+            });
+            let parsed: HandHistory = history.to_string().parse().unwrap();
+            assert_eq!(parsed.actions.last(), Some(&(0, Action::Escape)));
+            assert!(parsed.replay().is_ok());
+        }
+    }
+
+    mod errors {
+        use crate::error::{GameError, LobbyError, RoomError};
+        use crate::{closest_kind, parse_input, Game, Locale, Localize, MultiplierBreakdown, PlayDiagnostic, Player, PlayerType, RuleKind, Settlement};
+
+        #[test]
+        fn localize() {
+            let diagnostic = PlayDiagnostic::new(&parse_input("QQ").unwrap(), &parse_input("KK").unwrap(), &parse_input("QQ鬼王").unwrap());
+            let error = GameError::WrongRule(diagnostic);
+            assert_eq!(error.code(), "game.wrong_rule");
+            assert_eq!(error.to_string(), "你出的对子压不过 KK，需要比 K 大的对子，也可以出炸弹 鬼王");
+            assert_eq!(error.localize(Locale::EnUs), "Your pair does not beat KK; you need a pair higher than K, or you can bomb with 鬼王");
+
+            let multiplier = MultiplierBreakdown { show_hand: 1, bombs: 3, rockets: 0, total: 8 };
+            let error = GameError::Win("a".into(), PlayerType::Landlord, Settlement::new(50, multiplier, Some(4)));
+            assert_eq!(error.localize(Locale::EnUs), "a (landlord) won 200 points: base 50 × 4 (3 bombs, 0 rockets, capped from 8)");
+            assert_eq!(LobbyError::RoomErr(RoomError::RoomFull).code(), "room.full");
+            assert_eq!("en_US.UTF-8".parse::<Locale>(), Ok(Locale::EnUs));
+        }
+
+        #[test]
+        fn missing_cards() {
+            let mut game = Game::new();
+            for user in ["a", "b", "c"].iter() {
+                let mut player = Player::new(user.to_string());
+                player.cards = parse_input("3345").unwrap();
+                game.add_player(player);
+            }
+            game.state = crate::GameState::Running;
+            let result = game.submit_cards(parse_input("3336").unwrap());
+            assert_eq!(result, Err(GameError::NoSuchCards(parse_input("36").unwrap())));
+        }
+
+        #[test]
+        fn closest() {
+            assert_eq!(closest_kind(&parse_input("3334").unwrap()), RuleKind::ThreeWithOne);
+            assert_eq!(closest_kind(&parse_input("3456").unwrap()), RuleKind::Chain);
+            assert_eq!(closest_kind(&parse_input("33344").unwrap()), RuleKind::ThreeWithTwo);
+            assert_eq!(closest_kind(&parse_input("334").unwrap()), RuleKind::Two);
+            assert_eq!(closest_kind(&parse_input("33355567").unwrap()), RuleKind::AirplaneWithOneWing);
+            assert_eq!(closest_kind(&parse_input("3344").unwrap()), RuleKind::PairChain);
+        }
+    }
+
+    mod observer {
+        use std::sync::{Arc, Mutex};
+        use crate::{parse_input, Bot, Card, Game, GameObserver, GameState, GreedyBot, Player, PlayerType, Turn};
+
+        /// 把收到的事件记录为字符串
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+
+        impl GameObserver for Recorder {
+            fn on_deal(&mut self, _game: &Game) {
+                self.0.lock().unwrap().push("deal".into());
+            }
+            fn on_bid(&mut self, _game: &Game, seat: usize, call: bool) {
+                self.0.lock().unwrap().push(format!("bid {} {}", seat, call));
+            }
+            fn on_landlord(&mut self, game: &Game, seat: usize) {
+                assert_eq!(game.players[seat].cards.len(), 20);
+                self.0.lock().unwrap().push(format!("landlord {}", seat));
+            }
+            fn on_play(&mut self, game: &Game, seat: usize, cards: &[Card]) {
+                assert_eq!(game.last_index, seat);
+                self.0.lock().unwrap().push(format!("play {} {}", seat, cards.len()));
+            }
+            fn on_last_cards(&mut self, game: &Game, seat: usize, remaining: u32) {
+                assert_eq!(game.players[seat].cards.len(), remaining as usize);
+                self.0.lock().unwrap().push(format!("last {} {}", seat, remaining));
+            }
+            fn on_game_end(&mut self, game: &Game, _seat: usize, _winner: &PlayerType, _points: u32) {
+                assert!(game.state == GameState::WaitingForPlayers);
+                self.0.lock().unwrap().push("end".into());
+            }
+        }
+
+        #[test]
+        fn events() {
+            let events = Arc::new(Mutex::new(vec![]));
+            let mut game = Game::new();
+            for user in ["a", "b", "c"].iter() {
+                game.add_player(Player::new(user.to_string()));
+            }
+            game.add_observer(Box::new(Recorder(events.clone())));
+            game.start().map(|_| ()).unwrap();
+
+            let landlord = game.landlord_index;
+            game.bid(true).unwrap();
+            let mut bot = GreedyBot;
+            while game.state == GameState::Running {
+                let _ = match bot.play(&Turn::from_game(&game)) {
+                    Some(cards) => game.submit_cards(cards),
+                    None => game.pass(),
+                };
+            }
+
+            let events = events.lock().unwrap();
+            assert_eq!(events[0], "deal");
+            assert_eq!(events[1], format!("bid {} true", landlord));
+            assert_eq!(events[2], format!("landlord {}", landlord));
+            assert!(events[3].starts_with(&format!("play {}", landlord)));
+            assert_eq!(events.last().unwrap(), "end");
+        }
+
+        #[test]
+        fn last_cards() {
+            let events = Arc::new(Mutex::new(vec![]));
+            let mut game = Game::new();
+            for user in ["a", "b", "c"].iter() {
+                game.add_player(Player::new(user.to_string()));
+            }
+            game.start().map(|_| ()).unwrap();
+            game.players[0].cards = parse_input("34567").unwrap();
+            game.landlord_index = 0;
+            game.landlord_cards.clear();
+            game.run().unwrap();
+            game.add_observer(Box::new(Recorder(events.clone())));
+
+            game.submit_cards(parse_input("3").unwrap()).unwrap();
+            game.pass().unwrap();
+            game.pass().unwrap();
+            game.submit_cards(parse_input("4").unwrap()).unwrap();
+            game.pass().unwrap();
+            game.pass().unwrap();
+            game.submit_cards(parse_input("5").unwrap()).unwrap();
+            assert_eq!(game.last_cards(0), Some(2));
+
+            let events = events.lock().unwrap();
+            assert_eq!(events[..], ["play 0 1", "play 0 1", "play 0 1", "last 0 2"]);
+        }
+    }
+
+    mod stats {
+        use std::sync::mpsc::channel;
+        use crate::{parse_input, thread_rng, Bot, CardTracker, Game, GameState, GreedyBot, Player, StatsObserver, Turn};
+        use crate::error::GameError;
+        use crate::user::PlayerStats;
+
+        #[test]
+        fn full_game() {
+            let (sender, receiver) = channel();
+            let mut game = Game::new();
+            for user in ["a", "b", "c"].iter() {
+                game.add_player(Player::new(user.to_string()));
+            }
+            game.add_observer(Box::new(StatsObserver::new(sender)));
+            game.start().map(|_| ()).unwrap();
+            let landlord = game.landlord_index;
+            game.bid(true).unwrap();
+
+            let mut bot = GreedyBot;
+            let mut moves = 0;
+            while game.state == GameState::Running {
+                let _ = match bot.play(&Turn::from_game(&game)) {
+                    Some(cards) => game.submit_cards(cards),
+                    None => game.pass(),
+                };
+                moves += 1;
+            }
+
+            let stats = receiver.try_recv().unwrap();
+            assert_eq!(stats.landlord, Some(landlord));
+            assert_eq!(stats.move_millis.len(), moves);
+            assert!(stats.rounds >= 1);
+            assert_eq!(stats.multiplier.total, 1 << (stats.multiplier.bombs + stats.multiplier.rockets));
+            assert_eq!(stats.payouts.iter().sum::<i64>(), 0);
+            assert_eq!(stats.cards_left.iter().filter(|x| **x == 0).count(), 1);
+            assert!(stats.ended_at > 0);
+
+            // 牌谱包括叫地主与每一步，可以重放
+            let history = stats.history.as_ref().unwrap();
+            assert_eq!(history.actions.len(), moves + 1);
+            assert!(history.replay().is_ok());
+            let json = serde_json::to_string(&stats).unwrap();
+            assert_eq!(serde_json::from_str::<crate::MatchStats>(&json).unwrap(), stats);
+
+            let mut player = PlayerStats::default();
+            player.record(&stats, landlord);
+            assert_eq!(player.landlord_games, 1);
+            assert_eq!(player.average_payout(), stats.payouts[landlord] as f64);
+        }
+
+        #[test]
+        fn settlement() {
+            let mut game = Game::new();
+            game.base_points = 10;
+            game.max_multiplier = Some(2);
+            for user in ["a", "b", "c"].iter() {
+                game.add_player(Player::new(user.to_string()));
+            }
+            game.start().map(|_| ()).unwrap();
+            game.players[0].cards = parse_input("3333444455").unwrap();
+            game.landlord_index = 0;
+            game.landlord_cards.clear();
+            game.run().unwrap();
+
+            for cards in ["3333", "4444"].iter() {
+                game.submit_cards(parse_input(cards).unwrap()).unwrap();
+                game.pass().unwrap();
+                game.pass().unwrap();
+            }
+            match game.submit_cards(parse_input("55").unwrap()) {
+                Err(GameError::Win(_, _, settlement)) => {
+                    assert_eq!(settlement.multiplier.bombs, 2);
+                    assert_eq!(settlement.multiplier.total, 4);
+                    assert!(settlement.is_capped());
+                    assert_eq!(settlement.points, 20);
+                }
+                _ => panic!("Landlord should have won"),
+            }
+        }
+
+        #[test]
+        fn show_hand() {
+            let mut game = Game::new();
+            for user in ["a", "b", "c"].iter() {
+                game.add_player(Player::new(user.to_string()));
+            }
+            assert_eq!(game.show_hand(1), Ok(4));
+            game.start().map(|_| ()).unwrap();
+            assert_eq!(game.show_hand(1), Err(GameError::AlreadyShown));
+            // 叫地主时明牌的倍数更小，不再翻倍
+            assert_eq!(game.show_hand(2), Ok(4));
+
+            let mut tracker = CardTracker::new(0, &game.players[0].cards);
+            tracker.set_shown(1, &game.players[1].cards);
+            let hands = tracker.sample(&mut thread_rng(), 10).unwrap();
+            assert_eq!(hands[1], game.players[1].cards);
+
+            game.bid(true).unwrap();
+            assert_eq!(game.show_hand(0), Err(GameError::IsRunning));
+            assert_eq!(game.settle().multiplier.show_hand, 4);
+        }
+
+        #[test]
+        fn escape_while_bidding() {
+            let (sender, receiver) = channel();
+            let mut game = Game::new();
+            for user in ["a", "b", "c"].iter() {
+                game.add_player(Player::new(user.to_string()));
+            }
+            game.add_observer(Box::new(StatsObserver::new(sender)));
+            game.start().map(|_| ()).unwrap();
+            assert!(matches!(game.escape(0), GameError::Escaped(_, _)));
+
+            // 没有地主，不计入任何人的地主局数，牌谱也没有获胜的一方
+            let stats = receiver.try_recv().unwrap();
+            assert_eq!(stats.landlord, None);
+            for seat in 0..3 {
+                let mut player = PlayerStats::default();
+                player.record(&stats, seat);
+                assert_eq!(player.landlord_games, 0);
+            }
+            let history = stats.history.unwrap();
+            assert_eq!(history.result, None);
+            assert!(!history.to_string().contains("[Result"));
+            assert!(history.replay().is_ok());
+        }
+    }
+
+    mod advisor {
+        use crate::{advise_bid, evaluate_hand, parse_input};
+
+        #[test]
+        fn evaluation() {
+            let hand = parse_input("鬼王22223456789JQKA").unwrap();
+            let evaluation = evaluate_hand(&hand);
+            // 火箭、炸弹、3 到 9 的顺子，剩下 J Q K A
+            assert_eq!(evaluation.min_plays, 7);
+            assert_eq!(evaluation.controls, 7);
+            assert_eq!(evaluation.weak_singles, 3);
+
+            let hand = parse_input("333444555666777888").unwrap();
+            assert_eq!(evaluate_hand(&hand).bottom_value, 0.0);
+        }
+
+        #[test]
+        fn confidence() {
+            let strong = advise_bid(&parse_input("鬼王22223456789JQKA").unwrap());
+            let medium = advise_bid(&parse_input("王2AAKKQQ109876554").unwrap());
+            let weak = advise_bid(&parse_input("3345567899JJQK6104").unwrap());
+            assert!(strong.call && !weak.call);
+            assert!(strong.confidence > medium.confidence);
+            assert!(weak.confidence > 0.5 && weak.confidence <= 1.0);
+        }
+    }
+
+    mod decompose {
+        use crate::{decompose, gen_cards, parse_input, match_rule, Card, CardStore, Decomposer, RuleKind};
+
+        #[test]
+        fn min_plays() {
+            let mut decomposer = Decomposer::new();
+            assert_eq!(decomposer.min_plays(&parse_input("33344456").unwrap()), 1);
+            assert_eq!(decomposer.min_plays(&parse_input("3456789").unwrap()), 1);
+            assert_eq!(decomposer.min_plays(&parse_input("34567899").unwrap()), 2);
+        }
+
+        #[test]
+        fn tie_break() {
+            // 两手出完时保留炸弹，而不是拆成三带一
+            let decomposition = decompose(&parse_input("55553").unwrap());
+            assert_eq!(decomposition.plays_of(RuleKind::Bomb).len(), 1);
+            // 三带一时带走最小的单牌
+            let decomposition = decompose(&parse_input("777A3").unwrap());
+            assert_eq!(decomposition.singles(), vec![Card::CardA]);
+        }
+
+        #[test]
+        fn legal_plays() {
+            for _ in 0..20 {
+                let mut hand = gen_cards();
+                hand.truncate(17);
+                let decomposition = decompose(&hand);
+                let mut store = CardStore::default();
+                for play in decomposition.plays.iter() {
+                    assert!(!match_rule(play).is_none());
+                    for card in play.iter() {
+                        store.add(card);
+                    }
+                }
+                assert_eq!(store, CardStore::from_cards(&hand));
+            }
+        }
+    }
+
+    mod inference {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+        use crate::{match_rule, parse_input, valid_plays, CardTracker};
+
+        #[test]
+        fn passed_on_pair() {
+            let mut tracker = CardTracker::new(0, &parse_input("345678910JQKA2鬼王33").unwrap());
+            tracker.set_landlord(0, &parse_input("44K").unwrap());
+            tracker.record_play(0, &parse_input("KK").unwrap());
+            tracker.record_pass(1);
+            assert_eq!(tracker.passed_on(1).len(), 1);
+            assert_eq!(tracker.remaining, [18, 17, 17]);
+            assert_eq!(tracker.unseen().len(), 34);
+
+            let mut rng = StdRng::seed_from_u64(1);
+            for _ in 0..20 {
+                let hands = tracker.sample(&mut rng, 10_000).unwrap();
+                assert_eq!(hands[1].len(), 17);
+                assert_eq!(hands[2].len(), 17);
+                // 下家压不过一对 K，除非用炸弹
+                let pairs = valid_plays(&*match_rule(&parse_input("KK").unwrap()), &hands[1]);
+                assert!(pairs.iter().all(|x| match_rule(x).bomb_priority() > 0));
+            }
+        }
+
+        #[test]
+        fn known_landlord_cards() {
+            let mut tracker = CardTracker::new(1, &parse_input("33445566778899101010").unwrap());
+            tracker.set_landlord(0, &parse_input("鬼王2").unwrap());
+            let mut rng = StdRng::seed_from_u64(2);
+            let p = tracker.probability(0, 50, &mut rng, |hand| hand.contains(&crate::Card::CardKing));
+            assert_eq!(p, Some(1.0));
+        }
+    }
+
+    mod fair {
+        use crate::{Room, RoomState};
+        use crate::error::FairnessError;
+
+        #[test]
+        fn commit_reveal() {
+            let mut room = Room::new("fair".to_string());
+            for user in ["a", "b", "c"].iter() {
+                room.push(user.to_string());
+            }
+            let commitment = room.seed.commit();
+            room.seed.add_client_seed("a".to_string(), 7).unwrap();
+            room.state = RoomState::Ready;
+            room.start_game().map(|_| ()).unwrap();
+            let hands: Vec<_> = room.game.players.iter().map(|x| x.cards.clone()).collect();
+
+            let reveal = room.reveal_seed();
+            assert_ne!(room.seed.commitment(), commitment);
+            for (user, cards) in ["a", "b", "c"].iter().zip(hands.iter()) {
+                assert_eq!(reveal.verify(&commitment, user, cards), Ok(()));
+            }
+            assert_eq!(reveal.deal().unwrap().landlord_cards, room.game.landlord_cards);
+
+            let mut forged = reveal.clone();
+            forged.client_seeds.clear();
+            assert_eq!(forged.verify(&commitment, "a", &hands[0]), Err(FairnessError::DealMismatch));
+            assert_eq!(reveal.verify(&"0".repeat(64), "a", &hands[0]), Err(FairnessError::CommitmentMismatch));
+            assert_eq!(reveal.verify(&commitment, "d", &hands[0]), Err(FairnessError::UnknownPlayer));
+        }
+
+        /// 只接受公布承诺之后、发牌之前提交的随机数
+        #[test]
+        fn seed_window() {
+            let mut room = Room::new("fair".to_string());
+            for user in ["a", "b", "c"].iter() {
+                room.push(user.to_string());
+            }
+            assert_eq!(room.seed.add_client_seed("a".to_string(), 7), Err(FairnessError::NotCommitted));
+            room.seed.commit();
+            assert_eq!(room.seed.add_client_seed("b".to_string(), 8), Ok(()));
+            room.state = RoomState::Ready;
+            room.start_game().map(|_| ()).unwrap();
+            assert_eq!(room.seed.add_client_seed("c".to_string(), 9), Err(FairnessError::AlreadyDealt));
+
+            let reveal = room.reveal_seed();
+            assert_eq!(reveal.client_seeds, vec![("b".to_string(), 8)]);
+            // 下一局的种子需要重新公布承诺
+            assert_eq!(room.seed.add_client_seed("a".to_string(), 7), Err(FairnessError::NotCommitted));
+        }
+    }
+
+    mod surrender {
+        use crate::error::{GameError, RoomError};
+        use std::sync::mpsc::channel;
+        use crate::{parse_input, Game, Player, PlayerType, Room, RoomState, StatsObserver, SurrenderVote, ESCAPE_MULTIPLIER};
+
+        fn running_room() -> (Room, Vec<String>) {
+            let mut room = Room::new("surrender".to_string());
+            for user in ["a", "b", "c"].iter() {
+                room.push(user.to_string());
+            }
+            room.state = RoomState::Ready;
+            room.start_game().map(|_| ()).unwrap();
+            room.game.bid(true).unwrap();
+            let landlord = room.game.landlord_index;
+            let seats = [landlord, (landlord + 1) % 3, (landlord + 2) % 3];
+            let users = seats.iter().map(|x| room.users[*x].clone()).collect();
+            (room, users)
+        }
+
+        #[test]
+        fn farmers() {
+            let (mut room, users) = running_room();
+            assert_eq!(room.surrender_vote(&users[1], true), Ok(SurrenderVote::Pending(1)));
+            assert_eq!(room.surrender_vote(&users[1], true), Ok(SurrenderVote::Pending(1)));
+            assert_eq!(room.surrender_vote(&users[2], false), Ok(SurrenderVote::Rejected));
+            assert_eq!(room.surrender_vote(&users[1], true), Ok(SurrenderVote::Pending(1)));
+            match room.surrender_vote(&users[2], true) {
+                Ok(SurrenderVote::Accepted(GameError::Win(user, PlayerType::Landlord, settlement))) => {
+                    assert_eq!(user, users[0]);
+                    assert_eq!(settlement.points, 100);
+                }
+                other => panic!("Unexpected result: {:?}", other),
+            }
+            assert!(room.state == RoomState::Ready);
+            assert!(room.surrender_vote(&users[1], true).is_err());
+        }
+
+        #[test]
+        fn rejections() {
+            let (mut room, users) = running_room();
+            // 没有投票时不能否决
+            assert_eq!(room.surrender_vote(&users[1], false), Err(RoomError::NoSurrenderVote));
+            assert_eq!(room.surrender_vote(&users[1], true), Ok(SurrenderVote::Pending(1)));
+            // 地主不能否决农民的投降
+            assert_eq!(room.surrender_vote(&users[0], false), Err(RoomError::NotConcedingSide));
+            assert!(room.state == RoomState::WaitingForSurrender(vec![users[1].clone()]));
+            assert_eq!(room.surrender_vote(&users[2], false), Ok(SurrenderVote::Rejected));
+            assert_eq!(room.surrender_vote(&users[2], false), Err(RoomError::NoSurrenderVote));
+        }
+
+        #[test]
+        fn cancelled_by_move() {
+            let (mut room, users) = running_room();
+            assert_eq!(room.surrender_vote(&users[1], true), Ok(SurrenderVote::Pending(1)));
+            // 被拒绝的操作不取消投票
+            assert!(room.pass().is_err());
+            assert!(room.state != RoomState::Ready);
+
+            let landlord = room.game.landlord_index;
+            let card = room.game.players[landlord].cards[0];
+            room.submit_cards(vec![card]).unwrap();
+            assert!(room.state == RoomState::Ready);
+            assert_eq!(room.surrender_vote(&users[2], true), Ok(SurrenderVote::Pending(1)));
+            room.pass().unwrap();
+            assert!(room.state == RoomState::Ready);
+        }
+
+        #[test]
+        fn landlord() {
+            let (mut room, users) = running_room();
+            match room.surrender_vote(&users[0], true) {
+                Ok(SurrenderVote::Accepted(GameError::Win(_, winner, _))) => assert_eq!(winner, PlayerType::Farmer),
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+
+        #[test]
+        fn escape() {
+            let (mut room, users) = running_room();
+            let (sender, receiver) = channel();
+            room.game.add_observer(Box::new(StatsObserver::new(sender)));
+            match room.escape(&users[1]) {
+                Some(GameError::Escaped(user, settlement)) => {
+                    assert_eq!(user, users[1]);
+                    assert_eq!(settlement.points, 100 * ESCAPE_MULTIPLIER);
+                }
+                other => panic!("Unexpected result: {:?}", other),
+            }
+            assert!(room.escape("nobody").is_none());
+            assert_eq!(room.escape(&users[0]), Some(GameError::NotRunning));
+
+            let stats = receiver.try_recv().unwrap();
+            let seat = room.users.iter().position(|x| x == &users[1]).unwrap();
+            assert_eq!(stats.escaped, Some(seat));
+            assert_eq!(stats.payouts.iter().sum::<i64>(), 0);
+            assert_eq!(stats.payouts[seat], -200 * ESCAPE_MULTIPLIER as i64);
+        }
+
+        #[test]
+        fn escape_after_bombs() {
+            let mut game = Game::new();
+            for user in ["a", "b", "c"].iter() {
+                game.add_player(Player::new(user.to_string()));
+            }
+            game.show_hand(0).unwrap();
+            game.start().map(|_| ()).unwrap();
+            game.players[0].cards = parse_input("333344445555666677778").unwrap();
+            game.landlord_index = 0;
+            game.landlord_cards.clear();
+            game.run().unwrap();
+            for cards in ["3333", "4444", "5555", "6666", "7777"].iter() {
+                game.submit_cards(parse_input(cards).unwrap()).unwrap();
+                game.pass().unwrap();
+                game.pass().unwrap();
+            }
+            // 明牌 4 倍、5 个炸弹，已经超过逃跑的倍率
+            assert_eq!(game.settle().multiplier.total, 128);
+            match game.escape(1) {
+                GameError::Escaped(_, settlement) => {
+                    assert_eq!(settlement.multiplier.total, 128);
+                    assert_eq!(settlement.points, game.settle().points);
+                }
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+    }
+
+    mod reconnect {
+        use crate::{GameState, Room, RoomState};
+
+        #[test]
+        fn snapshot() {
+            let mut room = Room::new("reconnect".to_string());
+            for user in ["a", "b", "c"].iter() {
+                room.push(user.to_string());
+            }
+            assert!(room.snapshot("a").is_none());
+            room.state = RoomState::Ready;
+            room.start_game().map(|_| ()).unwrap();
+
+            let bidder = room.game.landlord_index;
+            let snapshot = room.snapshot("b").unwrap();
+            assert_eq!(snapshot.state, GameState::WaitingForLandlord);
+            assert_eq!(snapshot.current, bidder);
+            assert!(snapshot.landlord_cards.is_empty());
+
+            room.game.bid(true).unwrap();
+            let card = room.game.current_player().cards[0];
+            room.game.submit_cards(vec![card]).unwrap();
+            let seat = (bidder + 1) % 3;
+            let snapshot = room.snapshot(&room.users[seat].clone()).unwrap();
+            assert_eq!(snapshot.seat, seat);
+            assert_eq!(snapshot.cards, room.game.players[seat].cards);
+            assert_eq!(snapshot.cards_left[bidder], 19);
+            assert_eq!(snapshot.landlord, bidder);
+            assert_eq!(snapshot.landlord_cards.len(), 3);
+            assert_eq!(snapshot.current, seat);
+            assert_eq!(snapshot.last_play, Some((bidder, vec![card])));
+            assert!(room.snapshot("nobody").is_none());
+        }
+    }
+
+    mod auth {
+        use crate::error::AuthError;
+        use crate::user::UserManager;
+
+        #[test]
+        fn sessions() {
+            let dir = std::env::temp_dir().join(format!("cardgame-auth-{}", std::process::id()));
+            let path = dir.to_str().unwrap().to_string();
+            let mut users = UserManager::new(path.clone());
+
+            assert_eq!(users.register("../a", "pw"), Err(AuthError::InvalidName));
+            assert_eq!(users.login("a", "pw"), Err(AuthError::UnknownUser));
+            let token = users.register("a", "pw").unwrap();
+            assert_eq!(users.register("a", "other"), Err(AuthError::UserExists));
+            assert_eq!(users.authenticate(&token), Ok("a".to_string()));
+
+            // 重新登入后原来的令牌失效
+            assert_eq!(users.login("a", "wrong"), Err(AuthError::WrongPassword));
+            let new_token = users.login("a", "pw").unwrap();
+            assert_eq!(users.authenticate(&token), Err(AuthError::InvalidToken));
+            users.logout(&new_token);
+            assert_eq!(users.authenticate(&new_token), Err(AuthError::InvalidToken));
+
+            // 密码保存在文件中
+            let mut users = UserManager::new(path);
+            assert!(users.login("a", "pw").is_ok());
+
+            // 没有密码的老用户默认不能被注册
+            users.update_user("old", |user| user.score = 500).unwrap();
+            users.flush().unwrap();
+            assert_eq!(users.register("old", "pw"), Err(AuthError::UserExists));
+            users.allow_legacy_claims(true);
+            assert!(users.register("old", "pw").is_ok());
+            assert_eq!(users.get_user("old").unwrap().score, 500);
+
+            let _ = std::fs::remove_dir_all(&dir);
+            let _ = std::fs::remove_file(dir.with_extension("auth.json"));
+        }
+    }
+
+    mod store {
+        use std::io::Write;
+        use crate::user::UserManager;
+        use crate::{open_store, MatchStats};
+
+        fn record_and_reload(config: &str) {
+            let mut users = UserManager::with_store(open_store(config).unwrap());
+            users.register("a", "pw").unwrap();
+            let stats = MatchStats {
+                players: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+                payouts: [200, -100, -100],
+                ..Default::default()
+            };
+            assert_eq!(users.record_match(&stats).unwrap(), 1);
+            assert_eq!(users.record_match(&stats).unwrap(), 2);
+
+            let ids: Vec<u64> = users.read_matches().unwrap().iter().map(|x| x.id).collect();
+            assert_eq!(ids, vec![1, 2]);
+            let recent: Vec<u64> = users.recent_matches("b").unwrap().iter().map(|x| x.id).collect();
+            assert_eq!(recent, vec![2, 1]);
+            assert!(users.recent_matches("d").unwrap().is_empty());
+            assert_eq!(users.find_match(1).unwrap(), Some(crate::MatchStats { id: 1, ..stats }));
+            assert_eq!(users.find_match(3).unwrap(), None);
+            assert_eq!(users.get_user("a").unwrap().score, 400);
+            assert_eq!(users.get_user("b").unwrap().stats.games, 2);
+            users.read().unwrap();
+            assert_eq!(users.get_user("c").unwrap().score, -200);
+
+            // 只保存修改过的用户
+            assert_eq!(users.flush().unwrap(), 3);
+            assert!(!users.is_dirty());
+            assert_eq!(users.flush().unwrap(), 0);
+        }
+
+        #[test]
+        fn memory() {
+            record_and_reload("memory");
+        }
+
+        #[test]
+        fn file() {
+            let dir = std::env::temp_dir().join(format!("cardgame-store-{}", std::process::id()));
+            let path = dir.to_str().unwrap().to_string();
+            let mut users = UserManager::new(path.clone());
+            users.update_user("a", |user| user.score = 100).unwrap();
+            assert_eq!(users.flush().unwrap(), 1);
+            drop(users);
+
+            // 损坏的文件与崩溃留下的临时文件不影响读取
+            std::fs::write(dir.join("b"), "{").unwrap();
+            std::fs::write(dir.join("c.tmp"), "{").unwrap();
+            let mut users = UserManager::new(path);
+            users.read().unwrap();
+            assert_eq!(users.get_user("a").unwrap().score, 100);
+            // 没有变化的修改不需要保存
+            users.update_user("a", |user| user.score = 100).unwrap();
+            assert!(!users.is_dirty());
+
+            // 损坏的用户返回错误，不会被新用户覆盖
+            assert!(users.get_user("b").is_err());
+            assert!(users.update_user("b", |user| user.score = 100).is_err());
+            assert!(users.register("b", "pw").is_err());
+            let stats = MatchStats {
+                players: vec!["a".to_string(), "b".to_string(), "d".to_string()],
+                payouts: [200, -100, -100],
+                ..Default::default()
+            };
+            assert!(users.record_match(&stats).is_err());
+            assert_eq!(users.get_user("a").unwrap().score, 100);
+            assert_eq!(users.flush().unwrap(), 0);
+            drop(users);
+            assert_eq!(std::fs::read_to_string(dir.join("b")).unwrap(), "{");
+
+            let _ = std::fs::remove_dir_all(&dir);
+        }
+
+        #[test]
+        fn match_log() {
+            let dir = std::env::temp_dir().join(format!("cardgame-matches-{}", std::process::id()));
+            let config = format!("file:{}", dir.to_str().unwrap());
+            record_and_reload(&config);
+
+            // 崩溃时只写了一半的最后一行，以及删除后需要重建的索引
+            let log = dir.with_extension("matches.jsonl");
+            let mut file = std::fs::OpenOptions::new().append(true).open(&log).unwrap();
+            write!(file, "{{\"id\":3").unwrap();
+            std::fs::remove_dir_all(dir.with_extension("index")).unwrap();
+
+            let mut store = open_store(&config).unwrap();
+            let stats = MatchStats {
+                players: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+                ..Default::default()
+            };
+            assert_eq!(store.append_match(&stats).unwrap(), 4);
+            assert_eq!(store.load_match(3).unwrap(), None);
+            assert_eq!(store.load_match(4).unwrap(), Some(MatchStats { id: 4, ..stats.clone() }));
+            let recent: Vec<u64> = store.load_user_matches("b", 2).unwrap().iter().map(|x| x.id).collect();
+            assert_eq!(recent, vec![4, 2]);
+
+            // 写入对局记录后、写入索引前崩溃
+            writeln!(file, "{}", serde_json::to_string(&MatchStats { id: 5, ..stats.clone() }).unwrap()).unwrap();
+            let store = open_store(&config).unwrap();
+            let recent: Vec<u64> = store.load_user_matches("c", 20).unwrap().iter().map(|x| x.id).collect();
+            assert_eq!(recent, vec![5, 4, 2, 1]);
+
+            let _ = std::fs::remove_dir_all(&dir);
+            let _ = std::fs::remove_dir_all(dir.with_extension("index"));
+            let _ = std::fs::remove_file(dir.with_extension("auth.json"));
+            let _ = std::fs::remove_file(log);
+        }
+
+        #[cfg(feature = "sqlite")]
+        #[test]
+        fn sqlite() {
+            record_and_reload("sqlite::memory:");
+        }
+    }
+
+    mod deal {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+        use crate::{gen_cards_with_mode, has_bomb, new_deck, parse_input, CardStore, DealMode, Game, Player, Room, RoomConfig, RoomState};
+
+        fn count_bomb_hands(mode: DealMode) -> usize {
+            let mut rng = StdRng::seed_from_u64(3);
+            (0..200)
+                .map(|_| gen_cards_with_mode(mode, &mut rng))
+                .map(|cards| cards[..51].chunks(17).filter(|x| has_bomb(x)).count())
+                .sum()
+        }
+
+        #[test]
+        fn deal_sizes() {
+            let mut game = Game::new();
+            for user in ["a", "b", "c"].iter() {
+                game.add_player(Player::new(user.to_string()));
+            }
+            game.start_with_rng(&mut StdRng::seed_from_u64(1)).map(|_| ()).unwrap();
+            let mut cards = game.landlord_cards.clone();
+            for player in game.players.iter() {
+                assert_eq!(player.cards.len(), 17);
+                cards.extend(player.cards.iter());
+            }
+            assert_eq!(game.landlord_cards.len(), 3);
+            assert_eq!(CardStore::from_cards(&cards), CardStore::from_cards(&new_deck()));
+        }
+
+        #[test]
+        fn no_shuffle() {
+            let mut rng = StdRng::seed_from_u64(4);
+            for _ in 0..50 {
+                let cards = gen_cards_with_mode(DealMode::NoShuffle, &mut rng);
+                assert_eq!(CardStore::from_cards(&cards), CardStore::from_cards(&new_deck()));
+            }
+            assert!(count_bomb_hands(DealMode::NoShuffle) > count_bomb_hands(DealMode::Shuffle) * 2);
+        }
+
+        #[test]
+        fn room_config() {
+            let config = RoomConfig::from_args(&["不洗牌"]).unwrap();
+            let mut room = Room::with_config("deal".to_string(), config);
+            for user in ["a", "b", "c"].iter() {
+                room.push(user.to_string());
+            }
+            room.state = RoomState::Ready;
+            let commitment = room.seed.commitment();
+            room.start_game().map(|_| ()).unwrap();
+            let cards = room.game.players[1].cards.clone();
+            assert_eq!(room.reveal_seed().verify(&commitment, "b", &cards), Ok(()));
+        }
+
+        #[test]
+        fn bomb_cap() {
+            let mut game = Game::new();
+            game.bomb_cap = Some(1);
+            for user in ["a", "b", "c"].iter() {
+                game.add_player(Player::new(user.to_string()));
+            }
+            game.start().map(|_| ()).unwrap();
+            game.players[0].cards = parse_input("33334444鬼王5").unwrap();
+            game.landlord_index = 0;
+            game.landlord_cards.clear();
+            game.run().unwrap();
+
+            game.submit_cards(parse_input("3333").unwrap()).unwrap();
+            game.pass().unwrap();
+            game.pass().unwrap();
+            game.submit_cards(parse_input("4444").unwrap()).unwrap();
+            game.pass().unwrap();
+            game.pass().unwrap();
+            game.submit_cards(parse_input("鬼王").unwrap()).unwrap();
+            assert_eq!(game.score_multiplier, 2);
+        }
+    }
+
     /// 测试不符合规则的乱敲
     #[test]
     fn random_inputs() {
@@ -88,7 +1305,16 @@ mod tests {
 }
 
 pub enum Signal {
-    Greet, Matchmake
+    Greet, Matchmake,
+
+    /// 检查断线超过宽限时间的玩家
+    CheckEscapes,
+
+    /// 定期保存修改过的玩家数据
+    FlushUsers,
+
+    /// 保存玩家数据并关闭服务器
+    Shutdown,
 }
 
 pub mod card;
@@ -99,6 +1325,21 @@ pub mod player;
 pub mod rule;
 pub mod user;
 pub mod error;
+pub mod solver;
+pub mod puzzle;
+pub mod env;
+pub mod bot;
+pub mod history;
+pub mod i18n;
+pub mod diagnostic;
+pub mod observer;
+pub mod stats;
+pub mod advisor;
+pub mod decompose;
+pub mod inference;
+pub mod fair;
+pub mod auth;
+pub mod store;
 
 pub use card::*;
 pub use game::*;
@@ -106,5 +1347,20 @@ pub use lobby::*;
 pub use messages::*;
 pub use player::*;
 pub use rule::*;
+pub use solver::*;
+pub use puzzle::*;
+pub use env::*;
+pub use bot::*;
+pub use history::*;
+pub use i18n::*;
+pub use diagnostic::*;
+pub use observer::*;
+pub use stats::*;
+pub use advisor::*;
+pub use decompose::*;
+pub use inference::*;
+pub use fair::*;
+pub use store::*;
 pub use rand::thread_rng;
+pub use rand::Rng;
 pub use rand::seq::SliceRandom;
@@ -1,10 +1,18 @@
 use crate::user::UserId;
-use crate::{Game, Player};
+use crate::{Card, DealMode, DealReveal, FairSeed, Game, GameSnapshot, GameState, Player, PlayerType, BASE_POINTS};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+#[cfg(debug_assertions)]
+use crate::observer::DebugObserver;
 use serde::{Deserialize, Serialize};
 use std::slice::Iter;
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
-use crate::error::RoomError;
+use std::time::Duration;
+use crate::error::{GameError, RoomError};
+
+/// 默认的断线重连时间，游戏进行中断线超过这个时间仍未回来按逃跑结算
+pub const ESCAPE_GRACE: Duration = Duration::from_secs(60);
 
 pub struct Room {
     pub name: String,
@@ -12,21 +20,130 @@ pub struct Room {
     pub game: Game,
     pub game_name: String,
     pub state: RoomState,
+
+    /// 下一局的洗牌种子
+    pub seed: FairSeed,
+
+    /// 房间设置
+    pub config: RoomConfig,
+}
+
+/// 房间设置，创建房间时指定
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct RoomConfig {
+    /// 发牌方式
+    pub deal_mode: DealMode,
+
+    /// 炸弹封顶，炸弹与火箭最多翻倍的次数，None 为不封顶
+    pub bomb_cap: Option<u32>,
+
+    /// 底分
+    pub base_points: u32,
+
+    /// 结算时的封顶倍率，None 为不封顶
+    pub max_multiplier: Option<u32>,
+
+    /// 游戏进行中断线后保留座位的时间
+    pub reconnect_grace: Duration,
+}
+
+impl Default for RoomConfig {
+    fn default() -> Self {
+        RoomConfig {
+            deal_mode: DealMode::Shuffle,
+            bomb_cap: None,
+            base_points: BASE_POINTS,
+            max_multiplier: None,
+            reconnect_grace: ESCAPE_GRACE,
+        }
+    }
+}
+
+impl RoomConfig {
+    /// 把发牌与结算规则设置到游戏中
+    pub fn apply(&self, game: &mut Game) {
+        game.deal_mode = self.deal_mode;
+        game.bomb_cap = self.bomb_cap;
+        game.base_points = self.base_points;
+        game.max_multiplier = self.max_multiplier;
+    }
+
+    /// 从玩家输入的参数解析设置，无法识别的参数返回 None
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cardgame::{DealMode, RoomConfig};
+    /// # use std::time::Duration;
+    /// let config = RoomConfig::from_args(&["不洗牌", "炸弹封顶3", "底分50", "封顶64", "重连30"]).unwrap();
+    /// assert_eq!(config.deal_mode, DealMode::NoShuffle);
+    /// assert_eq!(config.bomb_cap, Some(3));
+    /// assert_eq!(config.base_points, 50);
+    /// assert_eq!(config.max_multiplier, Some(64));
+    /// assert_eq!(config.reconnect_grace, Duration::from_secs(30));
+    /// assert!(RoomConfig::from_args(&["洗牌"]).is_none());
+    /// ```
+    pub fn from_args(args: &[&str]) -> Option<RoomConfig> {
+        let mut config = RoomConfig::default();
+        for arg in args {
+            if *arg == "不洗牌" {
+                config.deal_mode = DealMode::NoShuffle;
+            } else if let Some(cap) = arg.strip_prefix("炸弹封顶") {
+                config.bomb_cap = Some(cap.parse().ok()?);
+            } else if let Some(points) = arg.strip_prefix("底分") {
+                config.base_points = points.parse().ok()?;
+            } else if let Some(max) = arg.strip_prefix("封顶") {
+                config.max_multiplier = Some(max.parse().ok()?);
+            } else if let Some(secs) = arg.strip_prefix("重连") {
+                config.reconnect_grace = Duration::from_secs(secs.parse().ok()?);
+            } else {
+                return None;
+            }
+        }
+        Some(config)
+    }
 }
 
 #[derive(Serialize, Deserialize, Eq, PartialEq, Clone)]
 pub enum RoomState {
-    Waiting, Ready, WaitingForRematch(u32)
+    Waiting, Ready, WaitingForRematch(u32),
+
+    /// 农民发起投降投票，参数为已同意的农民
+    WaitingForSurrender(Vec<UserId>),
+}
+
+/// 投降投票的结果
+#[derive(Clone, PartialEq, Debug)]
+pub enum SurrenderVote {
+    /// 等待队友同意，参数为已同意的人数
+    Pending(u32),
+
+    /// 有人不同意，投票取消
+    Rejected,
+
+    /// 投降成立，参数为按对方获胜结算的 `GameError::Win`
+    Accepted(GameError),
 }
 
 impl Room {
     pub fn new(name: String) -> Room {
+        Room::with_config(name, RoomConfig::default())
+    }
+
+    pub fn with_config(name: String, config: RoomConfig) -> Room {
+        let mut game = Game::new();
+        config.apply(&mut game);
+        #[cfg(debug_assertions)]
+        game.add_observer(Box::new(DebugObserver));
+
         Room {
             name,
             users: vec![],
-            game: Game::new(),
+            game,
             game_name: String::new(),
-            state: RoomState::Waiting
+            state: RoomState::Waiting,
+            seed: FairSeed::new(),
+            config,
         }
     }
 
@@ -41,8 +158,97 @@ impl Room {
         if self.state != RoomState::Ready {
             Err(RoomError::NotReady)
         } else {
-            self.game.start().map_err(|_| RoomError::NotReady) // TODO 处理错误
+            let mut rng = StdRng::seed_from_u64(self.seed.deal_seed());
+            self.game.start_with_rng(&mut rng).map_err(|_| RoomError::NotReady) // TODO 处理错误
+        }
+    }
+
+    /// 出牌，出牌成功后取消进行中的投降投票
+    pub fn submit_cards(&mut self, cards: Vec<Card>) -> Result<String, GameError> {
+        let result = self.game.submit_cards(cards);
+        self.cancel_surrender(&result);
+        result
+    }
+
+    /// 过牌，过牌成功后取消进行中的投降投票
+    pub fn pass(&mut self) -> Result<String, GameError> {
+        let result = self.game.pass();
+        self.cancel_surrender(&result);
+        result
+    }
+
+    fn cancel_surrender(&mut self, result: &Result<String, GameError>) {
+        let moved = matches!(result, Ok(_) | Err(GameError::Win(..)));
+        if moved && matches!(self.state, RoomState::WaitingForSurrender(_)) {
+            self.state = RoomState::Ready;
+        }
+    }
+
+    /// 投降投票
+    /// 地主投降立即成立，农民需要两人都同意，农民不同意时取消投票
+    pub fn surrender_vote(&mut self, user: &str, vote: bool) -> Result<SurrenderVote, RoomError> {
+        if self.game.state != GameState::Running {
+            return Err(RoomError::NotStarted);
+        }
+        let seat = self.users.iter().position(|x| x == user).ok_or(RoomError::NotReady)?;
+        let side = self.game.players[seat].player_type.clone();
+        let voters = match &self.state {
+            RoomState::WaitingForSurrender(voters) => Some(voters.clone()),
+            _ => None,
+        };
+        if !vote {
+            if voters.is_none() {
+                return Err(RoomError::NoSurrenderVote);
+            }
+            // 投票只在农民之间进行，地主不能替对方否决
+            if side != PlayerType::Farmer {
+                return Err(RoomError::NotConcedingSide);
+            }
+            self.state = RoomState::Ready;
+            return Ok(SurrenderVote::Rejected);
         }
+
+        let mut voters = voters.unwrap_or_default();
+        if side == PlayerType::Farmer && !voters.iter().any(|x| x == user) {
+            voters.push(user.to_string());
+        }
+        if side == PlayerType::Landlord || voters.len() == 2 {
+            self.state = RoomState::Ready;
+            Ok(SurrenderVote::Accepted(self.game.surrender(side)))
+        } else {
+            let count = voters.len() as u32;
+            self.state = RoomState::WaitingForSurrender(voters);
+            Ok(SurrenderVote::Pending(count))
+        }
+    }
+
+    /// 玩家逃跑，按最高倍率判负
+    /// 返回 `GameError::Escaped`，玩家不在房间里时返回 None
+    pub fn escape(&mut self, user: &str) -> Option<GameError> {
+        let seat = self.users.iter().position(|x| x == user)?;
+        self.state = RoomState::Ready;
+        Some(self.game.escape(seat))
+    }
+
+    /// 玩家视角的牌局，玩家不在房间里或游戏没有开始时返回 None
+    pub fn snapshot(&self, user: &str) -> Option<GameSnapshot> {
+        let seat = self.users.iter().position(|x| x == user)?;
+        self.game.snapshot(seat)
+    }
+
+    /// 明牌玩家的手牌
+    pub fn shown_hands(&self) -> Vec<(UserId, Vec<Card>)> {
+        self.game.players
+            .iter()
+            .filter(|x| x.shown)
+            .map(|x| (x.user.clone(), x.cards.clone()))
+            .collect()
+    }
+
+    /// 公开这局的洗牌种子，并为下一局换一个新的种子
+    pub fn reveal_seed(&mut self) -> DealReveal {
+        let seed = std::mem::take(&mut self.seed);
+        seed.reveal(self.users.clone(), self.config.deal_mode)
     }
 }
 
@@ -0,0 +1,113 @@
+use crate::card::Card;
+use crate::game::{Game, GameState};
+use crate::player::PlayerType;
+
+/// 游戏事件的观察者
+/// 通过 `Game::add_observer` 注册，所有回调都在游戏状态更新之后调用，默认不做任何事
+pub trait GameObserver {
+    /// 发完牌，尚未叫地主
+    fn on_deal(&mut self, _game: &Game) {}
+
+    /// 玩家选择叫或不叫地主
+    fn on_bid(&mut self, _game: &Game, _seat: usize, _call: bool) {}
+
+    /// 地主已确定，地主牌已加入地主手牌
+    fn on_landlord(&mut self, _game: &Game, _seat: usize) {}
+
+    /// 玩家出牌
+    fn on_play(&mut self, _game: &Game, _seat: usize, _cards: &[Card]) {}
+
+    /// 玩家过牌
+    fn on_pass(&mut self, _game: &Game, _seat: usize) {}
+
+    /// 报单、报双：玩家出牌后只剩一张或两张牌，参数为剩余的牌数
+    fn on_last_cards(&mut self, _game: &Game, _seat: usize, _remaining: u32) {}
+
+    /// 玩家明牌，发牌前明牌时手牌为空，发完牌后通过 `Player::shown` 查看
+    fn on_show_hand(&mut self, _game: &Game, _seat: usize) {}
+
+    /// 积分倍率改变，参数为新的倍率
+    fn on_multiplier(&mut self, _game: &Game, _multiplier: u32) {}
+
+    /// 一方投降，之后按另一方获胜调用 `on_game_end`
+    fn on_surrender(&mut self, _game: &Game, _side: &PlayerType) {}
+
+    /// 有玩家出完牌，游戏结束
+    /// 参数：出完牌的座位，获胜的一方，获得的积分
+    fn on_game_end(&mut self, _game: &Game, _seat: usize, _winner: &PlayerType, _points: u32) {}
+
+    /// 玩家逃跑，游戏结束
+    /// 参数：逃跑的座位，另外两位玩家各获得的积分
+    fn on_escape(&mut self, _game: &Game, _seat: usize, _points: u32) {}
+}
+
+/// 在控制台打印牌局，调试时使用
+pub struct DebugObserver;
+
+impl DebugObserver {
+    fn print_cards(game: &Game) {
+        for p in game.players.iter() {
+            print!("{}: ", p.user);
+            for c in p.cards.iter() {
+                print!("[{}]", c.to_string());
+            }
+            println!();
+        }
+    }
+
+    fn print_player(game: &Game) {
+        if game.state == GameState::Running {
+            println!("轮到 {} 出牌", game.current_player().user);
+        }
+    }
+}
+
+impl GameObserver for DebugObserver {
+    fn on_deal(&mut self, game: &Game) {
+        DebugObserver::print_cards(game);
+    }
+
+    fn on_landlord(&mut self, game: &Game, _seat: usize) {
+        DebugObserver::print_cards(game);
+        DebugObserver::print_player(game);
+    }
+
+    fn on_play(&mut self, game: &Game, seat: usize, cards: &[Card]) {
+        print!("{} 出牌：", game.players[seat].user);
+        for c in cards.iter() {
+            print!("[{}]", c.to_string());
+        }
+        println!();
+        DebugObserver::print_cards(game);
+        DebugObserver::print_player(game);
+    }
+
+    fn on_pass(&mut self, game: &Game, _seat: usize) {
+        DebugObserver::print_player(game);
+    }
+
+    fn on_last_cards(&mut self, game: &Game, seat: usize, remaining: u32) {
+        println!("{} 只剩 {} 张牌", game.players[seat].user, remaining);
+    }
+
+    fn on_show_hand(&mut self, game: &Game, seat: usize) {
+        println!("{} 明牌，倍率 {}", game.players[seat].user, game.score_multiplier);
+    }
+
+    fn on_game_end(&mut self, game: &Game, _seat: usize, winner: &PlayerType, _points: u32) {
+        match winner {
+            PlayerType::Landlord => {
+                println!("地主赢了！");
+            }
+            PlayerType::Farmer => {
+                println!("农民赢了！");
+            }
+        }
+        DebugObserver::print_cards(game);
+    }
+
+    fn on_escape(&mut self, game: &Game, seat: usize, points: u32) {
+        println!("{} 逃跑了，其他玩家各获得 {} 分", game.players[seat].user, points);
+        DebugObserver::print_cards(game);
+    }
+}
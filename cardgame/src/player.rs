@@ -1,17 +1,28 @@
 use crate::card::Card;
 use serde::{Deserialize, Serialize};
 use crate::user::UserId;
+use crate::i18n::Localize;
 
 /// 玩家类型
-#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug, Default)]
 pub enum PlayerType {
     /// 农民
+    #[default]
     Farmer,
 
     /// 地主
     Landlord,
 }
 
+impl Localize for PlayerType {
+    fn code(&self) -> &'static str {
+        match self {
+            PlayerType::Farmer => "player.farmer",
+            PlayerType::Landlord => "player.landlord",
+        }
+    }
+}
+
 /// 游戏玩家
 /// 生命周期：整个游戏流程
 #[derive(PartialEq)]
@@ -19,6 +30,9 @@ pub struct Player {
     pub user: UserId,
     pub cards: Vec<Card>,
     pub player_type: PlayerType,
+
+    /// 是否明牌
+    pub shown: bool,
 }
 
 impl Player {
@@ -27,6 +41,7 @@ impl Player {
             user,
             cards: vec![],
             player_type: PlayerType::Farmer,
+            shown: false,
         }
     }
 }
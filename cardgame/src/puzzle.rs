@@ -0,0 +1,182 @@
+use crate::card::*;
+use crate::error::PuzzleError;
+use crate::game::{Game, GameState};
+use crate::player::{Player, PlayerType};
+use crate::rule::*;
+use crate::solver::{Position, Solver};
+use crate::user::UserId;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// 残局文件名和读取结果
+pub type PuzzleEntry = (String, Result<Puzzle, PuzzleError>);
+
+/// 残局
+/// 从 JSON 文件读取，手牌使用 `CardList` 的写法
+///
+/// ```json
+/// {
+///     "name": "一手定胜负",
+///     "hands": ["KK3", "AA4", "5"],
+///     "landlord": 0,
+///     "player": 0
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Puzzle {
+    /// 残局名称
+    pub name: String,
+
+    /// 残局说明
+    #[serde(default)]
+    pub description: String,
+
+    /// 三家手牌，按出牌顺序排列
+    pub hands: [String; 3],
+
+    /// 地主座位
+    pub landlord: usize,
+
+    /// 玩家座位，其余座位由电脑控制
+    pub player: usize,
+
+    /// 先出牌的座位，默认为玩家
+    #[serde(default)]
+    pub turn: Option<usize>,
+
+    /// 桌面上最后出的牌，参数为座位和牌
+    #[serde(default)]
+    pub last: Option<(usize, String)>,
+}
+
+impl Puzzle {
+    /// 读取并校验残局
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Puzzle, PuzzleError> {
+        let str = fs::read_to_string(path)?;
+        Puzzle::from_json(&str)
+    }
+
+    /// 读取目录下所有的残局，按文件名排序
+    /// 无法读取或无解的残局会连同文件名一起返回错误
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<PuzzleEntry>, PuzzleError> {
+        let mut entries = fs::read_dir(dir)?
+            .map(|res| res.map(|e| e.path()))
+            .collect::<Result<Vec<_>, std::io::Error>>()?;
+        entries.retain(|x| x.extension().is_some_and(|ext| ext == "json"));
+        entries.sort();
+
+        Ok(entries
+            .into_iter()
+            .map(|path| {
+                let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+                (name, Puzzle::load(&path))
+            })
+            .collect())
+    }
+
+    /// 从 JSON 字符串读取并校验残局
+    pub fn from_json(str: &str) -> Result<Puzzle, PuzzleError> {
+        let puzzle: Puzzle = serde_json::from_str(str)?;
+        puzzle.verify()?;
+        Ok(puzzle)
+    }
+
+    /// 校验残局是否合法，并用求解器确认玩家一方必胜
+    pub fn verify(&self) -> Result<(), PuzzleError> {
+        let position = self.position()?;
+
+        let mut total = CardStore::default();
+        for cards in position.hands.iter().chain(position.last.iter().map(|(_, x)| x)) {
+            for card in cards.iter() {
+                total.add(card);
+                let max = if *card == Card::CardGhost || *card == Card::CardKing { 1 } else { 4 };
+                if total.get_card_count(card) > max {
+                    return Err(PuzzleError::TooManyCards(*card));
+                }
+            }
+        }
+        if let Some((_, cards)) = &position.last {
+            if match_rule(cards).is_none() {
+                return Err(PuzzleError::InvalidCards(self.last.as_ref().unwrap().1.clone()));
+            }
+        }
+
+        let solution = Solver::new().solve(&position);
+        let same_side = (position.turn == self.landlord) == (self.player == self.landlord);
+        if solution.win == same_side {
+            Ok(())
+        } else {
+            Err(PuzzleError::Unsolvable)
+        }
+    }
+
+    /// 残局的初始局面
+    pub fn position(&self) -> Result<Position, PuzzleError> {
+        for seat in [self.landlord, self.player, self.turn()].iter() {
+            if *seat > 2 {
+                return Err(PuzzleError::InvalidSeat(*seat));
+            }
+        }
+
+        let mut hands: [Vec<Card>; 3] = Default::default();
+        for (i, hand) in self.hands.iter().enumerate() {
+            hands[i] = parse_cards(hand)?;
+        }
+
+        let last = match &self.last {
+            None => None,
+            Some((seat, cards)) => {
+                if *seat > 2 {
+                    return Err(PuzzleError::InvalidSeat(*seat));
+                }
+                Some((*seat, parse_cards(cards)?))
+            }
+        };
+
+        Ok(Position {
+            hands,
+            landlord: self.landlord,
+            turn: self.turn(),
+            last,
+        })
+    }
+
+    pub fn turn(&self) -> usize {
+        self.turn.unwrap_or(self.player)
+    }
+
+    /// 以残局局面创建一局已经开始的游戏
+    ///
+    /// # Arguments
+    ///
+    /// * `users`: 按座位排列的玩家名称
+    pub fn to_game(&self, users: [UserId; 3]) -> Result<Game, PuzzleError> {
+        let position = self.position()?;
+        let mut game = Game::new();
+        for (i, user) in users.iter().enumerate() {
+            let mut player = Player::new(user.clone());
+            player.cards = position.hands[i].clone();
+            player.cards.sort();
+            if i == position.landlord {
+                player.player_type = PlayerType::Landlord;
+            }
+            game.add_player(player);
+        }
+
+        game.landlord_index = position.landlord;
+        game.index = position.turn;
+        game.last_index = position.turn;
+        if let Some((seat, cards)) = position.last {
+            game.last_index = seat;
+            game.last_rule = match_rule(&cards);
+            game.last_cards = cards;
+        }
+        game.state = GameState::Running;
+        Ok(game)
+    }
+}
+
+fn parse_cards(input: &str) -> Result<Vec<Card>, PuzzleError> {
+    Ok(input.parse::<CardList>()?.0)
+}
@@ -1,4 +1,52 @@
 use crate::card::*;
+use crate::i18n::Localize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// 牌型
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RuleKind {
+    #[default]
+    None,
+    One,
+    Two,
+    ThreeWithOne,
+    ThreeWithTwo,
+    FourWithTwo,
+    Bomb,
+    Rocket,
+
+    /// 顺子
+    Chain,
+
+    /// 连对
+    PairChain,
+
+    /// 不带翅膀的飞机
+    TripleChain,
+    AirplaneWithOneWing,
+    AirplaneWithTwoWings,
+}
+
+impl Localize for RuleKind {
+    fn code(&self) -> &'static str {
+        match self {
+            RuleKind::None => "rule.none",
+            RuleKind::One => "rule.one",
+            RuleKind::Two => "rule.two",
+            RuleKind::ThreeWithOne => "rule.three_with_one",
+            RuleKind::ThreeWithTwo => "rule.three_with_two",
+            RuleKind::FourWithTwo => "rule.four_with_two",
+            RuleKind::Bomb => "rule.bomb",
+            RuleKind::Rocket => "rule.rocket",
+            RuleKind::Chain => "rule.chain",
+            RuleKind::PairChain => "rule.pair_chain",
+            RuleKind::TripleChain => "rule.triple_chain",
+            RuleKind::AirplaneWithOneWing => "rule.airplane_one_wing",
+            RuleKind::AirplaneWithTwoWings => "rule.airplane_two_wings",
+        }
+    }
+}
 
 /// 出牌规则
 pub trait Rule {
@@ -8,6 +56,19 @@ pub trait Rule {
     /// 规则名称
     fn to_string(&self) -> &str;
 
+    /// 牌型
+    fn kind(&self) -> RuleKind;
+
+    /// 比较大小时使用的点数，例如三带一中的三张、顺子中最小的一张
+    fn rank(&self) -> Card {
+        Card::Unknown
+    }
+
+    /// 顺子、连对与飞机的长度，其余牌型为 1
+    fn length(&self) -> u32 {
+        1
+    }
+
     /// 是否为空规则
     fn is_none(&self) -> bool {
         false
@@ -57,10 +118,13 @@ pub struct RuleBomb {
 /// 火箭
 pub struct RuleRocket;
 
-/// 顺子
+/// 顺子、连对以及不带翅膀的飞机
 pub struct RuleChain {
     first: Card,
     count: u32,
+
+    /// 每个点数的张数
+    width: u32,
 }
 
 /// 单翼飞机
@@ -85,6 +149,9 @@ impl Rule for RuleNone {
     fn to_string(&self) -> &str {
         "无"
     }
+    fn kind(&self) -> RuleKind {
+        RuleKind::None
+    }
     fn is_none(&self) -> bool {
         true
     }
@@ -107,6 +174,12 @@ impl Rule for RuleOne {
     fn to_string(&self) -> &str {
         "单牌"
     }
+    fn kind(&self) -> RuleKind {
+        RuleKind::One
+    }
+    fn rank(&self) -> Card {
+        self.card
+    }
 }
 
 impl RuleTwo {
@@ -128,6 +201,12 @@ impl Rule for RuleTwo {
     fn to_string(&self) -> &str {
         "对子"
     }
+    fn kind(&self) -> RuleKind {
+        RuleKind::Two
+    }
+    fn rank(&self) -> Card {
+        self.card
+    }
 }
 impl RuleThreeWithOne {
     fn try_new(cards: &[Card]) -> Option<RuleThreeWithOne> {
@@ -153,6 +232,12 @@ impl Rule for RuleThreeWithOne {
     fn to_string(&self) -> &str {
         "三带一"
     }
+    fn kind(&self) -> RuleKind {
+        RuleKind::ThreeWithOne
+    }
+    fn rank(&self) -> Card {
+        self.first
+    }
 }
 impl RuleThreeWithTwo {
     fn try_new(cards: &[Card]) -> Option<RuleThreeWithTwo> {
@@ -178,6 +263,12 @@ impl Rule for RuleThreeWithTwo {
     fn to_string(&self) -> &str {
         "三带二"
     }
+    fn kind(&self) -> RuleKind {
+        RuleKind::ThreeWithTwo
+    }
+    fn rank(&self) -> Card {
+        self.first
+    }
 }
 impl RuleFourWithTwo {
     fn try_new(cards: &[Card]) -> Option<RuleFourWithTwo> {
@@ -204,6 +295,12 @@ impl Rule for RuleFourWithTwo {
     fn to_string(&self) -> &str {
         "四带二"
     }
+    fn kind(&self) -> RuleKind {
+        RuleKind::FourWithTwo
+    }
+    fn rank(&self) -> Card {
+        self.first
+    }
 }
 impl RuleBomb {
     fn try_new(cards: &[Card]) -> Option<RuleBomb> {
@@ -226,6 +323,12 @@ impl Rule for RuleBomb {
     fn to_string(&self) -> &str {
         "炸弹"
     }
+    fn kind(&self) -> RuleKind {
+        RuleKind::Bomb
+    }
+    fn rank(&self) -> Card {
+        self.card
+    }
     fn bomb_priority(&self) -> u32 {
         1
     }
@@ -250,6 +353,12 @@ impl Rule for RuleRocket {
     fn to_string(&self) -> &str {
         "火箭"
     }
+    fn kind(&self) -> RuleKind {
+        RuleKind::Rocket
+    }
+    fn rank(&self) -> Card {
+        Card::CardKing
+    }
     fn bomb_priority(&self) -> u32 {
         2
     }
@@ -291,6 +400,7 @@ impl RuleChain {
         Some(RuleChain {
             first: first.card,
             count: type_len,
+            width: count,
         })
     }
 }
@@ -301,10 +411,27 @@ impl Rule for RuleChain {
             return false;
         }
         let rule = rule.unwrap();
-        rule.first > self.first && rule.count == self.count
+        rule.first > self.first && rule.count == self.count && rule.width == self.width
     }
     fn to_string(&self) -> &str {
-        "顺子"
+        match self.width {
+            1 => "顺子",
+            2 => "连对",
+            _ => "飞机",
+        }
+    }
+    fn kind(&self) -> RuleKind {
+        match self.width {
+            1 => RuleKind::Chain,
+            2 => RuleKind::PairChain,
+            _ => RuleKind::TripleChain,
+        }
+    }
+    fn rank(&self) -> Card {
+        self.first
+    }
+    fn length(&self) -> u32 {
+        self.count
     }
 }
 impl RuleAirplaneWithOneWing {
@@ -359,6 +486,15 @@ impl Rule for RuleAirplaneWithOneWing {
     fn to_string(&self) -> &str {
         "单翼飞机"
     }
+    fn kind(&self) -> RuleKind {
+        RuleKind::AirplaneWithOneWing
+    }
+    fn rank(&self) -> Card {
+        self.first
+    }
+    fn length(&self) -> u32 {
+        self.count
+    }
 }
 impl RuleAirplaneWithTwoWings {
     fn try_new(cards: &[Card]) -> Option<RuleAirplaneWithTwoWings> {
@@ -414,6 +550,15 @@ impl Rule for RuleAirplaneWithTwoWings {
     fn to_string(&self) -> &str {
         "双翼飞机"
     }
+    fn kind(&self) -> RuleKind {
+        RuleKind::AirplaneWithTwoWings
+    }
+    fn rank(&self) -> Card {
+        self.first
+    }
+    fn length(&self) -> u32 {
+        self.count
+    }
 }
 
 pub fn match_rule(cards: &[Card]) -> Box<dyn Rule + Send + Sync + 'static> {
@@ -502,6 +647,155 @@ pub fn rule_matches(rule: &dyn Rule, to_match: &[Card]) -> bool {
     }
 }
 
+/// 列出手牌中所有合法的出牌组合，结果中的每组牌均已排序且互不相同。
+///
+/// 顺子、连对与飞机只会生成到 A 为止。
+///
+/// # Examples
+///
+/// ```
+/// use cardgame::{all_plays, parse_input};
+/// let plays = all_plays(&parse_input("3334").unwrap());
+/// assert!(plays.contains(&parse_input("3334").unwrap()));
+/// assert!(plays.contains(&parse_input("33").unwrap()));
+/// ```
+pub fn all_plays(cards: &[Card]) -> Vec<Vec<Card>> {
+    let mut counts = [0u32; 16];
+    for c in cards {
+        counts[c.value() as usize] += 1;
+    }
+
+    let mut candidates: Vec<Vec<Card>> = vec![];
+    let card = |i: usize| Card::from_value(i as u32);
+
+    for i in 1..16 {
+        let count = counts[i];
+        if count >= 1 {
+            candidates.push(vec![card(i)]);
+        }
+        if count >= 2 {
+            candidates.push(vec![card(i); 2]);
+        }
+        if count == 4 {
+            candidates.push(vec![card(i); 4]);
+        }
+        if count >= 3 {
+            for (j, &other) in counts.iter().enumerate().take(16).skip(1) {
+                if j == i {
+                    continue;
+                }
+                if other >= 1 {
+                    let mut play = vec![card(i); 3];
+                    play.push(card(j));
+                    candidates.push(play);
+                }
+                if other >= 2 {
+                    let mut play = vec![card(i); 3];
+                    play.append(&mut vec![card(j); 2]);
+                    candidates.push(play);
+                }
+            }
+        }
+        if count == 4 {
+            for (j, &other) in counts.iter().enumerate().take(16).skip(1) {
+                if j != i && other >= 2 {
+                    let mut play = vec![card(i); 4];
+                    play.append(&mut vec![card(j); 2]);
+                    candidates.push(play);
+                }
+            }
+        }
+    }
+
+    if counts[Card::CardGhost.value() as usize] > 0 && counts[Card::CardKing.value() as usize] > 0 {
+        candidates.push(vec![Card::CardGhost, Card::CardKing]);
+    }
+
+    // 顺子、连对、飞机
+    let last = Card::CardA.value() as usize;
+    for (width, min_len) in [(1u32, 5usize), (2, 3), (3, 2)].iter() {
+        for start in 1..=last {
+            let mut end = start;
+            while end <= last && counts[end] >= *width {
+                let len = end - start + 1;
+                if len >= *min_len {
+                    let mut play = vec![];
+                    for i in start..=end {
+                        play.append(&mut vec![card(i); *width as usize]);
+                    }
+                    if *width == 3 {
+                        append_wings(&counts, start, end, &play, &mut candidates);
+                    }
+                    candidates.push(play);
+                }
+                end += 1;
+            }
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut plays: Vec<Vec<Card>> = vec![];
+    for mut play in candidates.into_iter() {
+        play.sort();
+        if seen.insert(CardStore::from_cards(&play)) && !match_rule(&play).is_none() {
+            plays.push(play);
+        }
+    }
+    plays
+}
+
+/// 为飞机生成翅膀
+fn append_wings(counts: &[u32; 16], start: usize, end: usize, body: &[Card], candidates: &mut Vec<Vec<Card>>) {
+    let len = end - start + 1;
+    let mut spare = *counts;
+    for count in spare.iter_mut().take(end + 1).skip(start) {
+        *count = 0;
+    }
+
+    // 单翼：任选 len 张其它点数的牌
+    let mut wings = vec![];
+    choose_wings(&spare, 1, len, 1, &mut vec![], &mut wings);
+    // 双翼：任选 len 个其它点数的对子
+    let mut pairs = vec![];
+    choose_wings(&spare, 2, len, 1, &mut vec![], &mut pairs);
+
+    for wing in wings.into_iter().chain(pairs) {
+        let mut play = body.to_vec();
+        play.extend(wing);
+        candidates.push(play);
+    }
+}
+
+fn choose_wings(spare: &[u32; 16], width: u32, remaining: usize, from: usize, current: &mut Vec<Card>, result: &mut Vec<Vec<Card>>) {
+    if remaining == 0 {
+        result.push(current.clone());
+        return;
+    }
+    for i in from..16 {
+        // 单翼可以从同一点数取多张，双翼每个点数只取一对
+        let max = if width == 1 { spare[i] as usize } else { (spare[i] / 2).min(1) as usize };
+        for taken in 1..=max.min(remaining) {
+            for _ in 0..taken * width as usize {
+                current.push(Card::from_value(i as u32));
+            }
+            choose_wings(spare, width, remaining - taken, i + 1, current, result);
+            for _ in 0..taken * width as usize {
+                current.pop();
+            }
+        }
+    }
+}
+
+/// 列出手牌中所有能压过当前规则的出牌组合
+///
+/// 若当前规则为空规则，则返回所有合法的出牌组合。
+pub fn valid_plays(rule: &dyn Rule, cards: &[Card]) -> Vec<Vec<Card>> {
+    all_plays(cards)
+        .into_iter()
+        .filter(|x| rule_matches(rule, x))
+        .collect()
+}
+
 /*
 impl Rule<Rule> for Rule {
     fn matches(&self, cards: &[Card]) -> bool {
@@ -0,0 +1,280 @@
+use crate::card::*;
+use crate::game::Game;
+use crate::rule::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// 明牌残局局面
+/// 座位顺序与 `Game::players` 一致
+#[derive(Clone, Debug, PartialEq)]
+pub struct Position {
+    /// 三名玩家的手牌
+    pub hands: [Vec<Card>; 3],
+
+    /// 地主座位
+    pub landlord: usize,
+
+    /// 当前出牌的座位
+    pub turn: usize,
+
+    /// 最后出牌的座位和牌
+    /// 为 None 或座位等于 `turn` 时当前玩家可以任意出牌
+    pub last: Option<(usize, Vec<Card>)>,
+}
+
+impl Position {
+    /// 从正在进行的游戏中取得局面
+    pub fn from_game(game: &Game) -> Position {
+        let last = if game.last_cards.is_empty() || game.last_index == game.index {
+            None
+        } else {
+            Some((game.last_index, game.last_cards.clone()))
+        };
+        Position {
+            hands: [
+                game.players[0].cards.clone(),
+                game.players[1].cards.clone(),
+                game.players[2].cards.clone(),
+            ],
+            landlord: game.landlord_index,
+            turn: game.index,
+            last,
+        }
+    }
+
+    /// 当前玩家是否可以任意出牌
+    pub fn is_leading(&self) -> bool {
+        match &self.last {
+            None => true,
+            Some((seat, _)) => *seat == self.turn,
+        }
+    }
+
+    fn to_key(&self) -> Key {
+        let mut key = Key {
+            hands: [
+                CardStore::from_cards(&self.hands[0]),
+                CardStore::from_cards(&self.hands[1]),
+                CardStore::from_cards(&self.hands[2]),
+            ],
+            turn: self.turn as u8,
+            last_seat: self.turn as u8,
+            last: CardStore::default(),
+        };
+        if let Some((seat, cards)) = &self.last {
+            key.last_seat = *seat as u8;
+            key.last = CardStore::from_cards(cards);
+        }
+        key.normalize();
+        key
+    }
+}
+
+/// 一步操作
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Move {
+    /// 出牌
+    Play(Vec<Card>),
+
+    /// 过牌
+    Pass,
+}
+
+/// 求解结果
+#[derive(Clone, Debug)]
+pub struct Solution {
+    /// 当前出牌方（地主或农民）是否必胜
+    pub win: bool,
+
+    /// 双方都按最优策略行动时的完整出牌路线，直到有人出完牌
+    /// 参数：座位，操作
+    pub line: Vec<(usize, Move)>,
+}
+
+/// 用作置换表键值的紧凑局面
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Key {
+    hands: [CardStore; 3],
+    turn: u8,
+    last_seat: u8,
+    last: CardStore,
+}
+
+impl Key {
+    fn is_leading(&self) -> bool {
+        self.last_seat == self.turn
+    }
+
+    /// 自由出牌时忽略上一手牌，使相同局面共享置换表
+    fn normalize(&mut self) {
+        if self.is_leading() {
+            self.last = CardStore::default();
+        }
+    }
+
+    fn next_seat(&self) -> u8 {
+        (self.turn + 1) % 3
+    }
+
+    /// 当前玩家可以执行的操作，出牌多的排在前面，过牌在最后
+    fn moves(&self) -> Vec<Move> {
+        let hand = self.hands[self.turn as usize].get_cards();
+        let mut plays = if self.is_leading() {
+            all_plays(&hand)
+        } else {
+            let last = self.last.get_cards();
+            valid_plays(&*match_rule(&last), &hand)
+        };
+        plays.sort_by_key(|x| std::cmp::Reverse(x.len()));
+
+        let mut moves: Vec<Move> = plays.into_iter().map(Move::Play).collect();
+        if !self.is_leading() {
+            moves.push(Move::Pass);
+        }
+        moves
+    }
+
+    /// 执行操作，若出完牌则返回 Err(出完牌的座位)
+    fn apply(&self, mv: &Move) -> Result<Key, usize> {
+        let mut next = *self;
+        next.turn = self.next_seat();
+        if let Move::Play(cards) = mv {
+            let hand = &mut next.hands[self.turn as usize];
+            for c in cards.iter() {
+                hand.remove(c);
+            }
+            if hand.is_empty() {
+                return Err(self.turn as usize);
+            }
+            next.last_seat = self.turn;
+            next.last = CardStore::from_cards(cards);
+        }
+        next.normalize();
+        Ok(next)
+    }
+}
+
+/// 明牌残局求解器
+/// 使用带置换表的 alpha-beta 搜索，适用于每人十张牌左右的残局
+pub struct Solver {
+    /// 地主座位
+    landlord: usize,
+
+    /// 置换表，记录局面下地主是否必胜
+    memo: HashMap<Key, bool>,
+
+    /// 搜索过的节点数
+    pub nodes: u64,
+}
+
+impl Solver {
+    pub fn new() -> Solver {
+        Solver {
+            landlord: 0,
+            memo: HashMap::new(),
+            nodes: 0,
+        }
+    }
+
+    /// 求解局面，返回当前出牌方能否必胜以及获胜路线
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cardgame::{parse_input, Move, Position, Solver};
+    /// let position = Position {
+    ///     hands: [parse_input("33").unwrap(), parse_input("2").unwrap(), parse_input("5").unwrap()],
+    ///     landlord: 0,
+    ///     turn: 0,
+    ///     last: None,
+    /// };
+    /// let solution = Solver::new().solve(&position);
+    /// assert!(solution.win);
+    /// assert_eq!(solution.line, vec![(0, Move::Play(parse_input("33").unwrap()))]);
+    /// ```
+    pub fn solve(&mut self, position: &Position) -> Solution {
+        self.set_landlord(position.landlord);
+
+        let mut key = position.to_key();
+        let side = self.is_landlord(key.turn);
+        let win = self.landlord_wins(&key) == side;
+
+        let mut line = vec![];
+        loop {
+            let seat = key.turn as usize;
+            let mv = self.choose(&key);
+            let next = key.apply(&mv);
+            line.push((seat, mv));
+            match next {
+                Ok(next) => key = next,
+                Err(_) => break,
+            }
+        }
+
+        Solution { win, line }
+    }
+
+    /// 当前出牌玩家的最佳操作
+    pub fn best_move(&mut self, position: &Position) -> Move {
+        self.set_landlord(position.landlord);
+        self.choose(&position.to_key())
+    }
+
+    fn set_landlord(&mut self, landlord: usize) {
+        if self.landlord != landlord {
+            self.memo.clear();
+            self.landlord = landlord;
+        }
+    }
+
+    fn is_landlord(&self, seat: u8) -> bool {
+        seat as usize == self.landlord
+    }
+
+    /// 选择对当前玩家最有利的操作，必败时选择第一个操作
+    fn choose(&mut self, key: &Key) -> Move {
+        let side = self.is_landlord(key.turn);
+        let moves = key.moves();
+        for mv in moves.iter() {
+            let landlord_wins = match key.apply(mv) {
+                Err(seat) => self.is_landlord(seat as u8),
+                Ok(next) => self.landlord_wins(&next),
+            };
+            if landlord_wins == side {
+                return mv.clone();
+            }
+        }
+        moves.into_iter().next().unwrap_or(Move::Pass)
+    }
+
+    /// 地主是否必胜
+    /// 地主为极大方，农民为极小方，找到一个对己方必胜的操作即可剪枝
+    fn landlord_wins(&mut self, key: &Key) -> bool {
+        if let Some(result) = self.memo.get(key) {
+            return *result;
+        }
+        self.nodes += 1;
+
+        let side = self.is_landlord(key.turn);
+        let mut result = !side;
+        for mv in key.moves().iter() {
+            let landlord_wins = match key.apply(mv) {
+                Err(seat) => self.is_landlord(seat as u8),
+                Ok(next) => self.landlord_wins(&next),
+            };
+            if landlord_wins == side {
+                result = side;
+                break;
+            }
+        }
+
+        self.memo.insert(*key, result);
+        result
+    }
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Solver::new()
+    }
+}
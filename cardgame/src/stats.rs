@@ -0,0 +1,320 @@
+use std::sync::mpsc::Sender;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use crate::card::Card;
+use crate::game::{Game, GameState};
+use crate::history::{escape_winner, Action, HandHistory, HandResult};
+use crate::i18n::{format_message, Locale, Localize};
+use crate::observer::GameObserver;
+use crate::player::PlayerType;
+use crate::rule::*;
+use crate::user::UserId;
+use serde::{Deserialize, Serialize};
+
+/// 积分倍率的组成
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct MultiplierBreakdown {
+    /// 明牌的倍数，没有明牌时为 1
+    pub show_hand: u32,
+
+    /// 翻倍的炸弹数，每次 *2，超过炸弹封顶的不计
+    pub bombs: u32,
+
+    /// 翻倍的火箭数，每次 *2，超过炸弹封顶的不计
+    pub rockets: u32,
+
+    /// 最终倍率
+    pub total: u32,
+}
+
+impl Default for MultiplierBreakdown {
+    fn default() -> Self {
+        MultiplierBreakdown {
+            show_hand: 1,
+            bombs: 0,
+            rockets: 0,
+            total: 1,
+        }
+    }
+}
+
+/// 一局游戏的结算
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Settlement {
+    /// 底分
+    pub base_points: u32,
+
+    /// 积分倍率的组成
+    pub multiplier: MultiplierBreakdown,
+
+    /// 封顶倍率，None 为不封顶
+    pub max_multiplier: Option<u32>,
+
+    /// 获胜方获得的积分，即底分乘以封顶后的倍率
+    pub points: u32,
+}
+
+impl Settlement {
+    pub fn new(base_points: u32, multiplier: MultiplierBreakdown, max_multiplier: Option<u32>) -> Settlement {
+        let mut settlement = Settlement {
+            base_points,
+            multiplier,
+            max_multiplier,
+            points: 0,
+        };
+        settlement.points = base_points.saturating_mul(settlement.effective_multiplier());
+        settlement
+    }
+
+    /// 封顶后实际使用的倍率
+    pub fn effective_multiplier(&self) -> u32 {
+        match self.max_multiplier {
+            Some(max) => self.multiplier.total.min(max),
+            None => self.multiplier.total,
+        }
+    }
+
+    /// 倍率是否超过了封顶
+    pub fn is_capped(&self) -> bool {
+        self.effective_multiplier() < self.multiplier.total
+    }
+}
+
+impl Localize for Settlement {
+    fn code(&self) -> &'static str {
+        "settlement.breakdown"
+    }
+
+    fn args(&self, locale: Locale) -> Vec<String> {
+        let mut notes = String::new();
+        if self.multiplier.show_hand > 1 {
+            notes += &format_message(locale, "settlement.show_hand", &[self.multiplier.show_hand.to_string()]);
+        }
+        if self.is_capped() {
+            notes += &format_message(locale, "settlement.capped", &[self.multiplier.total.to_string()]);
+        }
+        vec![
+            self.base_points.to_string(),
+            self.effective_multiplier().to_string(),
+            self.multiplier.bombs.to_string(),
+            self.multiplier.rockets.to_string(),
+            notes,
+        ]
+    }
+}
+
+/// 一局游戏的统计数据
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct MatchStats {
+    /// 对局编号，保存时由存储后端分配，从 1 开始
+    #[serde(default)]
+    pub id: u64,
+
+    /// 结束时间，UNIX 时间戳（秒）
+    #[serde(default)]
+    pub ended_at: u64,
+
+    /// 按座位排列的玩家
+    pub players: Vec<UserId>,
+
+    /// 地主的座位，叫地主时有人逃跑则为 None
+    pub landlord: Option<usize>,
+
+    /// 轮数，每次有玩家自由出牌算一轮
+    pub rounds: u32,
+
+    /// 每个座位打出的炸弹数
+    pub bombs: [u32; 3],
+
+    /// 每个座位打出的火箭数
+    pub rockets: [u32; 3],
+
+    /// 游戏结束时每个座位剩余的牌数
+    pub cards_left: [u32; 3],
+
+    /// 每一步的座位与思考时间（毫秒），包括过牌
+    pub move_millis: Vec<(usize, u64)>,
+
+    /// 积分倍率的组成
+    pub multiplier: MultiplierBreakdown,
+
+    /// 获胜的一方
+    pub winner: PlayerType,
+
+    /// 获胜方获得的积分
+    pub points: u32,
+
+    /// 每个座位的积分变化，地主输赢两倍积分
+    pub payouts: [i64; 3],
+
+    /// 逃跑玩家的座位，逃跑时 `winner` 没有意义
+    #[serde(default)]
+    pub escaped: Option<usize>,
+
+    /// 牌谱：发牌、叫地主与每一步出牌
+    #[serde(default)]
+    pub history: Option<HandHistory>,
+}
+
+impl MatchStats {
+    /// 座位对应的玩家是否获胜
+    pub fn is_winner(&self, seat: usize) -> bool {
+        self.payouts[seat] > 0
+    }
+
+    /// 玩家的座位
+    pub fn seat_of(&self, user: &str) -> Option<usize> {
+        self.players.iter().position(|x| x == user)
+    }
+
+    /// 去掉牌谱，用于对局列表
+    pub fn without_history(&self) -> MatchStats {
+        MatchStats {
+            history: None,
+            ..self.clone()
+        }
+    }
+
+    /// 记录结束时间并发出统计数据
+    fn finish(mut self, sender: &Sender<MatchStats>) {
+        self.ended_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_secs())
+            .unwrap_or(0);
+        // 接收方已经不存在时丢弃统计数据
+        let _ = sender.send(self);
+    }
+}
+
+/// 收集一局游戏的统计数据，游戏结束时通过 `sender` 发出
+/// 重置游戏后继续统计下一局
+pub struct StatsObserver {
+    sender: Sender<MatchStats>,
+    stats: MatchStats,
+    last_seat: Option<usize>,
+    last_move: Instant,
+}
+
+impl StatsObserver {
+    pub fn new(sender: Sender<MatchStats>) -> StatsObserver {
+        StatsObserver {
+            sender,
+            stats: MatchStats::default(),
+            last_seat: None,
+            last_move: Instant::now(),
+        }
+    }
+
+    /// 记录当前座位的思考时间
+    fn record_move(&mut self, seat: usize) {
+        let millis = self.last_move.elapsed().as_millis() as u64;
+        self.stats.move_millis.push((seat, millis));
+        self.last_move = Instant::now();
+    }
+}
+
+impl GameObserver for StatsObserver {
+    fn on_deal(&mut self, game: &Game) {
+        self.stats = MatchStats {
+            players: game.players.iter().map(|x| x.user.clone()).collect(),
+            history: Some(HandHistory::from_game(game)),
+            ..Default::default()
+        };
+        self.last_seat = None;
+        self.last_move = Instant::now();
+    }
+
+    fn on_bid(&mut self, _game: &Game, seat: usize, call: bool) {
+        if let Some(history) = &mut self.stats.history {
+            history.push(seat, if call { Action::Call } else { Action::Decline });
+        }
+    }
+
+    fn on_landlord(&mut self, _game: &Game, seat: usize) {
+        self.stats.landlord = Some(seat);
+        self.last_move = Instant::now();
+    }
+
+    fn on_play(&mut self, _game: &Game, seat: usize, cards: &[Card]) {
+        self.record_move(seat);
+        if let Some(history) = &mut self.stats.history {
+            history.push(seat, Action::Play(cards.to_vec()));
+        }
+        // 上一手牌也是自己出的，说明其他人都过了
+        if self.last_seat.is_none() || self.last_seat == Some(seat) {
+            self.stats.rounds += 1;
+        }
+        self.last_seat = Some(seat);
+
+        match match_rule(cards).kind() {
+            RuleKind::Bomb => self.stats.bombs[seat] += 1,
+            RuleKind::Rocket => self.stats.rockets[seat] += 1,
+            _ => {}
+        }
+    }
+
+    fn on_pass(&mut self, _game: &Game, seat: usize) {
+        self.record_move(seat);
+        if let Some(history) = &mut self.stats.history {
+            history.push(seat, Action::Pass);
+        }
+    }
+
+    fn on_show_hand(&mut self, game: &Game, seat: usize) {
+        // 发牌前明牌由 `HandHistory::from_game` 记录
+        if game.state != GameState::WaitingForLandlord {
+            return;
+        }
+        if let Some(history) = &mut self.stats.history {
+            history.push(seat, Action::ShowHand);
+        }
+    }
+
+    fn on_surrender(&mut self, game: &Game, side: &PlayerType) {
+        let seat = match side {
+            PlayerType::Landlord => game.landlord_index,
+            PlayerType::Farmer => (game.landlord_index + 1) % 3,
+        };
+        if let Some(history) = &mut self.stats.history {
+            history.push(seat, Action::Surrender);
+        }
+    }
+
+    fn on_game_end(&mut self, game: &Game, _seat: usize, winner: &PlayerType, points: u32) {
+        let mut stats = std::mem::take(&mut self.stats);
+        stats.multiplier = game.settle().multiplier;
+        stats.winner = winner.clone();
+        stats.points = points;
+        for (i, player) in game.players.iter().enumerate() {
+            stats.cards_left[i] = player.cards.len() as u32;
+            let points = match player.player_type {
+                PlayerType::Landlord => points as i64 * 2,
+                PlayerType::Farmer => points as i64,
+            };
+            stats.payouts[i] = if &player.player_type == winner { points } else { -points };
+        }
+        if let Some(history) = &mut stats.history {
+            history.result = Some(HandResult { winner: winner.clone(), points });
+        }
+        stats.finish(&self.sender);
+    }
+
+    fn on_escape(&mut self, game: &Game, seat: usize, points: u32) {
+        let mut stats = std::mem::take(&mut self.stats);
+        stats.multiplier = game.settle().multiplier;
+        stats.points = points;
+        stats.escaped = Some(seat);
+        for (i, player) in game.players.iter().enumerate() {
+            stats.cards_left[i] = player.cards.len() as u32;
+            stats.payouts[i] = if i == seat { -(points as i64) * 2 } else { points as i64 };
+        }
+        if let Some(history) = &mut stats.history {
+            history.push(seat, Action::Escape);
+            // 还没有地主时没有获胜的一方，不记录结果
+            if stats.landlord.is_some() {
+                let winner = escape_winner(&game.players[seat].player_type);
+                history.result = Some(HandResult { winner, points });
+            }
+        }
+        stats.finish(&self.sender);
+    }
+}
@@ -0,0 +1,527 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use crate::auth::Credentials;
+use crate::stats::MatchStats;
+use crate::user::{User, UserId};
+
+/// 玩家数据的存储后端
+/// 保存用户数据（积分、长期统计）、密码哈希与对局记录，由 `UserManager` 负责缓存
+pub trait UserStore: Send + Sync {
+    /// 读取用户数据，用户不存在时返回 None
+    fn load_user(&self, id: &str) -> Result<Option<User>, anyhow::Error>;
+
+    /// 读取所有用户数据
+    fn load_users(&self) -> Result<Vec<User>, anyhow::Error>;
+
+    /// 保存用户数据，已存在时覆盖
+    fn save_user(&mut self, user: &User) -> Result<(), anyhow::Error>;
+
+    /// 读取所有用户的密码哈希
+    fn load_credentials(&self) -> Result<HashMap<UserId, Credentials>, anyhow::Error>;
+
+    /// 保存一位用户的密码哈希
+    fn save_credentials(&mut self, id: &str, credentials: &Credentials) -> Result<(), anyhow::Error>;
+
+    /// 追加一局的统计数据，返回分配的对局编号
+    fn append_match(&mut self, stats: &MatchStats) -> Result<u64, anyhow::Error>;
+
+    /// 按时间顺序读取所有对局统计
+    fn load_matches(&self) -> Result<Vec<MatchStats>, anyhow::Error>;
+
+    /// 按编号读取一局的统计
+    fn load_match(&self, id: u64) -> Result<Option<MatchStats>, anyhow::Error> {
+        Ok(self.load_matches()?.into_iter().find(|x| x.id == id))
+    }
+
+    /// 读取玩家最近的 `limit` 局，最近的在前
+    fn load_user_matches(&self, user: &str, limit: usize) -> Result<Vec<MatchStats>, anyhow::Error> {
+        Ok(self.load_matches()?
+            .into_iter()
+            .rev()
+            .filter(|x| x.seat_of(user).is_some())
+            .take(limit)
+            .collect())
+    }
+}
+
+/// 默认的存储设置，与原来的用户数据目录相同
+pub const DEFAULT_STORE: &str = "file:users";
+
+/// 读取环境变量 `CARDGAME_STORE` 作为存储设置，没有设置时使用 `DEFAULT_STORE`
+pub fn store_config_from_env() -> String {
+    std::env::var("CARDGAME_STORE").unwrap_or_else(|_| DEFAULT_STORE.to_string())
+}
+
+/// 按设置打开存储后端
+///
+/// * `memory`：只保存在内存中，用于测试
+/// * `file:目录`：每位用户一个 JSON 文件，密码与对局记录保存在目录旁边
+/// * `sqlite:文件`：嵌入式 SQLite 数据库，需要启用 `sqlite` 特性
+///
+/// # Examples
+///
+/// ```
+/// use cardgame::open_store;
+/// assert!(open_store("memory").is_ok());
+/// assert!(open_store("redis://localhost").is_err());
+/// ```
+pub fn open_store(config: &str) -> Result<Box<dyn UserStore>, anyhow::Error> {
+    match config.split_once(':') {
+        _ if config == "memory" => Ok(Box::new(MemoryStore::default())),
+        Some(("file", path)) => Ok(Box::new(FileStore::new(path.to_string())?)),
+        #[cfg(feature = "sqlite")]
+        Some(("sqlite", path)) => Ok(Box::new(SqliteStore::open(path)?)),
+        _ => Err(anyhow::anyhow!("Unknown user store: {}", config)),
+    }
+}
+
+/// 只保存在内存中的存储后端
+#[derive(Default)]
+pub struct MemoryStore {
+    users: HashMap<UserId, User>,
+    credentials: HashMap<UserId, Credentials>,
+    matches: Vec<MatchStats>,
+}
+
+impl UserStore for MemoryStore {
+    fn load_user(&self, id: &str) -> Result<Option<User>, anyhow::Error> {
+        Ok(self.users.get(id).cloned())
+    }
+
+    fn load_users(&self) -> Result<Vec<User>, anyhow::Error> {
+        Ok(self.users.values().cloned().collect())
+    }
+
+    fn save_user(&mut self, user: &User) -> Result<(), anyhow::Error> {
+        self.users.insert(user.id.clone(), user.clone());
+        Ok(())
+    }
+
+    fn load_credentials(&self) -> Result<HashMap<UserId, Credentials>, anyhow::Error> {
+        Ok(self.credentials.clone())
+    }
+
+    fn save_credentials(&mut self, id: &str, credentials: &Credentials) -> Result<(), anyhow::Error> {
+        self.credentials.insert(id.to_string(), credentials.clone());
+        Ok(())
+    }
+
+    fn append_match(&mut self, stats: &MatchStats) -> Result<u64, anyhow::Error> {
+        let id = self.matches.len() as u64 + 1;
+        self.matches.push(MatchStats { id, ..stats.clone() });
+        Ok(id)
+    }
+
+    fn load_matches(&self) -> Result<Vec<MatchStats>, anyhow::Error> {
+        Ok(self.matches.clone())
+    }
+}
+
+/// 文件存储后端
+/// 用户数据目录下每位用户一个 JSON 文件，密码文件 `目录.auth.json` 与对局记录 `目录.matches.jsonl`
+/// 与目录同级，避免被当作用户数据读取。对局编号为对局记录的行号
+/// `目录.index` 下每位玩家一个索引文件，按顺序记录参与的对局编号
+pub struct FileStore {
+    /// 用户数据目录
+    path: String,
+
+    /// 每局记录在对局记录文件中的起始位置，下标为对局编号减一
+    offsets: Vec<u64>,
+
+    /// 对局记录文件的长度
+    log_len: u64,
+}
+
+impl FileStore {
+    pub fn new(path: String) -> Result<FileStore, std::io::Error> {
+        let p = Path::new(&path);
+        if !p.is_dir() {
+            fs::create_dir_all(p)?;
+        }
+        let mut store = FileStore { path, offsets: vec![], log_len: 0 };
+        store.open_match_log()?;
+        Ok(store)
+    }
+
+    /// 读取对局记录每一行的起始位置并检查索引
+    /// 写入途中崩溃时最后一行可能不完整，补上换行，之后追加的记录不会与它连在一起
+    fn open_match_log(&mut self) -> Result<(), std::io::Error> {
+        let path = self.match_log_path();
+        let mut log = if path.is_file() { fs::read(&path)? } else { vec![] };
+        if !log.is_empty() && !log.ends_with(b"\n") {
+            println!("对局记录 {} 的最后一行不完整，已补上换行", path.display());
+            OpenOptions::new().append(true).open(&path)?.write_all(b"\n")?;
+            log.push(b'\n');
+        }
+
+        let mut start = 0;
+        for (i, byte) in log.iter().enumerate() {
+            if *byte == b'\n' {
+                self.offsets.push(start as u64);
+                start = i + 1;
+            }
+        }
+        self.log_len = log.len() as u64;
+
+        let lines: Vec<&[u8]> = log.split(|x| *x == b'\n').take(self.offsets.len()).collect();
+        if !self.index_path().is_dir() {
+            self.build_index(&lines)?;
+        } else if let Some(stats) = lines.last().and_then(|x| serde_json::from_slice::<MatchStats>(x).ok()) {
+            // 追加记录后、写入索引前崩溃时，只有最后一局可能不在索引中
+            let id = lines.len() as u64;
+            for user in stats.players.iter() {
+                if self.read_index(user)?.last() != Some(&id) {
+                    self.append_index(user, id)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 按对局记录建立所有玩家的索引，先写入临时目录，完成后改名
+    fn build_index(&self, lines: &[&[u8]]) -> Result<(), std::io::Error> {
+        let mut index: HashMap<&str, String> = HashMap::new();
+        let parsed: Vec<Option<MatchStats>> = lines.iter().map(|x| serde_json::from_slice(x).ok()).collect();
+        for (i, stats) in parsed.iter().enumerate() {
+            if let Some(stats) = stats {
+                for user in stats.players.iter() {
+                    index.entry(user).or_default().push_str(&format!("{}\n", i + 1));
+                }
+            }
+        }
+
+        let temp = self.index_path().with_extension("index.tmp");
+        if temp.is_dir() {
+            fs::remove_dir_all(&temp)?;
+        }
+        fs::create_dir_all(&temp)?;
+        for (user, ids) in index {
+            fs::write(temp.join(user), ids)?;
+        }
+        fs::rename(temp, self.index_path())
+    }
+
+    /// 读取玩家参与的对局编号，从早到晚
+    fn read_index(&self, user: &str) -> Result<Vec<u64>, std::io::Error> {
+        let path = self.index_path().join(user);
+        if !path.is_file() {
+            return Ok(vec![]);
+        }
+        Ok(fs::read_to_string(path)?
+            .lines()
+            .filter_map(|x| x.trim().parse().ok())
+            .collect())
+    }
+
+    fn append_index(&self, user: &str, id: u64) -> Result<(), std::io::Error> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.index_path().join(user))?;
+        writeln!(file, "{}", id)
+    }
+
+    /// 按编号读取一行对局记录，无法解析的记录为 None
+    fn read_match(&self, id: u64) -> Result<Option<MatchStats>, std::io::Error> {
+        if id == 0 || id > self.offsets.len() as u64 {
+            return Ok(None);
+        }
+        let start = self.offsets[id as usize - 1];
+        let end = self.offsets.get(id as usize).copied().unwrap_or(self.log_len);
+        let mut file = fs::File::open(self.match_log_path())?;
+        file.seek(SeekFrom::Start(start))?;
+        let mut line = vec![0; (end - start) as usize];
+        file.read_exact(&mut line)?;
+        Ok(serde_json::from_slice::<MatchStats>(&line).ok().map(|x| MatchStats { id, ..x }))
+    }
+
+    fn user_path(&self, id: &str) -> PathBuf {
+        Path::new(&self.path).join(id)
+    }
+
+    fn credentials_path(&self) -> PathBuf {
+        Path::new(&self.path).with_extension("auth.json")
+    }
+
+    fn match_log_path(&self) -> PathBuf {
+        Path::new(&self.path).with_extension("matches.jsonl")
+    }
+
+    fn index_path(&self) -> PathBuf {
+        Path::new(&self.path).with_extension("index")
+    }
+}
+
+/// 先写入同目录下的临时文件再改名，写入途中崩溃也不会留下只写了一半的文件
+/// 用户名不能包含 `.`，临时文件不会与用户数据重名
+fn write_atomic(path: &Path, contents: &str) -> Result<(), std::io::Error> {
+    let temp = path.with_extension("tmp");
+    let mut file = fs::File::create(&temp)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(temp, path)
+}
+
+impl UserStore for FileStore {
+    fn load_user(&self, id: &str) -> Result<Option<User>, anyhow::Error> {
+        let path = self.user_path(id);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let str = fs::read_to_string(path)?;
+        Ok(Some(serde_json::from_str(&str)?))
+    }
+
+    fn load_users(&self) -> Result<Vec<User>, anyhow::Error> {
+        let mut users = vec![];
+        for entry in fs::read_dir(&self.path)? {
+            let path = entry?.path();
+            // 跳过写入途中崩溃留下的临时文件
+            if !path.is_file() || path.extension() == Some("tmp".as_ref()) {
+                continue;
+            }
+            // 一个文件损坏不影响读取其他用户
+            match fs::read_to_string(&path).map_err(anyhow::Error::from)
+                .and_then(|str| Ok(serde_json::from_str(&str)?)) {
+                Ok(user) => users.push(user),
+                Err(err) => println!("跳过损坏的用户数据 {}: {}", path.display(), err),
+            }
+        }
+        Ok(users)
+    }
+
+    fn save_user(&mut self, user: &User) -> Result<(), anyhow::Error> {
+        write_atomic(&self.user_path(&user.id), &serde_json::to_string(user)?)?;
+        Ok(())
+    }
+
+    fn load_credentials(&self) -> Result<HashMap<UserId, Credentials>, anyhow::Error> {
+        let path = self.credentials_path();
+        if !path.is_file() {
+            return Ok(HashMap::new());
+        }
+        let str = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&str)?)
+    }
+
+    fn save_credentials(&mut self, id: &str, credentials: &Credentials) -> Result<(), anyhow::Error> {
+        let mut all = self.load_credentials()?;
+        all.insert(id.to_string(), credentials.clone());
+        write_atomic(&self.credentials_path(), &serde_json::to_string(&all)?)?;
+        Ok(())
+    }
+
+    fn append_match(&mut self, stats: &MatchStats) -> Result<u64, anyhow::Error> {
+        let id = self.offsets.len() as u64 + 1;
+        let line = serde_json::to_string(&MatchStats { id, ..stats.clone() })?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.match_log_path())?;
+        writeln!(file, "{}", line)?;
+        self.offsets.push(self.log_len);
+        self.log_len += line.len() as u64 + 1;
+        for user in stats.players.iter() {
+            self.append_index(user, id)?;
+        }
+        Ok(id)
+    }
+
+    fn load_matches(&self) -> Result<Vec<MatchStats>, anyhow::Error> {
+        let path = self.match_log_path();
+        let log = if path.is_file() { fs::read_to_string(path)? } else { String::new() };
+        // 旧的对局记录没有编号，按行号补上
+        Ok(log
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| serde_json::from_str(line).ok().map(|x| MatchStats { id: i as u64 + 1, ..x }))
+            .collect())
+    }
+
+    fn load_match(&self, id: u64) -> Result<Option<MatchStats>, anyhow::Error> {
+        Ok(self.read_match(id)?)
+    }
+
+    fn load_user_matches(&self, user: &str, limit: usize) -> Result<Vec<MatchStats>, anyhow::Error> {
+        let mut result = vec![];
+        for id in self.read_index(user)?.into_iter().rev().take(limit) {
+            result.extend(self.read_match(id)?);
+        }
+        Ok(result)
+    }
+}
+
+/// SQLite 存储后端，数据以 JSON 保存，方便与文件存储互相迁移
+#[cfg(feature = "sqlite")]
+pub struct SqliteStore {
+    connection: std::sync::Mutex<rusqlite::Connection>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStore {
+    /// 打开数据库文件，`:memory:` 为内存数据库
+    pub fn open(path: &str) -> Result<SqliteStore, anyhow::Error> {
+        let connection = rusqlite::Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS users (id TEXT PRIMARY KEY, data TEXT NOT NULL);
+             CREATE TABLE IF NOT EXISTS credentials (id TEXT PRIMARY KEY, data TEXT NOT NULL);
+             CREATE TABLE IF NOT EXISTS matches (id INTEGER PRIMARY KEY AUTOINCREMENT, data TEXT NOT NULL);
+             CREATE TABLE IF NOT EXISTS match_players (match_id INTEGER NOT NULL, user TEXT NOT NULL);
+             CREATE INDEX IF NOT EXISTS match_players_user ON match_players (user, match_id);",
+        )?;
+        let store = SqliteStore {
+            connection: std::sync::Mutex::new(connection),
+        };
+        store.index_matches()?;
+        Ok(store)
+    }
+
+    /// 为还没有索引的对局记录补上玩家索引，用于升级旧的数据库
+    fn index_matches(&self) -> Result<(), anyhow::Error> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        let pending: Vec<(i64, String)> = {
+            let mut statement = transaction.prepare(
+                "SELECT id, data FROM matches WHERE id > (SELECT IFNULL(MAX(match_id), 0) FROM match_players) ORDER BY id",
+            )?;
+            let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<Result<_, _>>()?
+        };
+        for (id, data) in pending {
+            let stats: MatchStats = serde_json::from_str(&data)?;
+            insert_players(&transaction, id, &stats)?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, rusqlite::Connection> {
+        self.connection.lock().unwrap()
+    }
+
+    /// 读取一张表的所有 JSON 数据
+    fn load_all<T: serde::de::DeserializeOwned>(&self, sql: &str) -> Result<Vec<T>, anyhow::Error> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(sql)?;
+        let rows = statement.query_map([], |row| row.get::<_, String>(0))?;
+        let mut result = vec![];
+        for data in rows {
+            result.push(serde_json::from_str(&data?)?);
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl UserStore for SqliteStore {
+    fn load_user(&self, id: &str) -> Result<Option<User>, anyhow::Error> {
+        use rusqlite::OptionalExtension;
+        let data: Option<String> = self.connection()
+            .query_row("SELECT data FROM users WHERE id = ?1", [id], |row| row.get(0))
+            .optional()?;
+        match data {
+            Some(data) => Ok(Some(serde_json::from_str(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn load_users(&self) -> Result<Vec<User>, anyhow::Error> {
+        self.load_all("SELECT data FROM users")
+    }
+
+    fn save_user(&mut self, user: &User) -> Result<(), anyhow::Error> {
+        self.connection().execute(
+            "INSERT OR REPLACE INTO users (id, data) VALUES (?1, ?2)",
+            rusqlite::params![user.id, serde_json::to_string(user)?],
+        )?;
+        Ok(())
+    }
+
+    fn load_credentials(&self) -> Result<HashMap<UserId, Credentials>, anyhow::Error> {
+        let connection = self.connection();
+        let mut statement = connection.prepare("SELECT id, data FROM credentials")?;
+        let rows = statement.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+        let mut result = HashMap::new();
+        for row in rows {
+            let (id, data) = row?;
+            result.insert(id, serde_json::from_str(&data)?);
+        }
+        Ok(result)
+    }
+
+    fn save_credentials(&mut self, id: &str, credentials: &Credentials) -> Result<(), anyhow::Error> {
+        self.connection().execute(
+            "INSERT OR REPLACE INTO credentials (id, data) VALUES (?1, ?2)",
+            rusqlite::params![id, serde_json::to_string(credentials)?],
+        )?;
+        Ok(())
+    }
+
+    fn append_match(&mut self, stats: &MatchStats) -> Result<u64, anyhow::Error> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO matches (data) VALUES (?1)",
+            rusqlite::params![serde_json::to_string(stats)?],
+        )?;
+        let id = transaction.last_insert_rowid();
+        insert_players(&transaction, id, stats)?;
+        transaction.commit()?;
+        Ok(id as u64)
+    }
+
+    fn load_matches(&self) -> Result<Vec<MatchStats>, anyhow::Error> {
+        let connection = self.connection();
+        let mut statement = connection.prepare("SELECT id, data FROM matches ORDER BY id")?;
+        let rows = statement.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?;
+        let mut result = vec![];
+        for row in rows {
+            let (id, data) = row?;
+            result.push(MatchStats { id: id as u64, ..serde_json::from_str(&data)? });
+        }
+        Ok(result)
+    }
+
+    fn load_match(&self, id: u64) -> Result<Option<MatchStats>, anyhow::Error> {
+        use rusqlite::OptionalExtension;
+        let data: Option<String> = self.connection()
+            .query_row("SELECT data FROM matches WHERE id = ?1", [id as i64], |row| row.get(0))
+            .optional()?;
+        match data {
+            Some(data) => Ok(Some(MatchStats { id, ..serde_json::from_str(&data)? })),
+            None => Ok(None),
+        }
+    }
+
+    fn load_user_matches(&self, user: &str, limit: usize) -> Result<Vec<MatchStats>, anyhow::Error> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(
+            "SELECT matches.id, matches.data FROM match_players JOIN matches ON matches.id = match_players.match_id
+             WHERE match_players.user = ?1 ORDER BY matches.id DESC LIMIT ?2",
+        )?;
+        let rows = statement.query_map(rusqlite::params![user, limit as i64], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut result = vec![];
+        for row in rows {
+            let (id, data) = row?;
+            result.push(MatchStats { id: id as u64, ..serde_json::from_str(&data)? });
+        }
+        Ok(result)
+    }
+}
+
+/// 记录一局的玩家，用于按玩家查询对局
+#[cfg(feature = "sqlite")]
+fn insert_players(connection: &rusqlite::Connection, id: i64, stats: &MatchStats) -> Result<(), anyhow::Error> {
+    for user in stats.players.iter() {
+        connection.execute(
+            "INSERT INTO match_players (match_id, user) VALUES (?1, ?2)",
+            rusqlite::params![id, user],
+        )?;
+    }
+    Ok(())
+}
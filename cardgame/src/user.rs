@@ -1,7 +1,10 @@
-use std::collections::HashMap;
-use std::fs;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 use serde::{Serialize, Deserialize};
+use crate::auth::{is_valid_name, new_session_token, Credentials};
+use crate::error::AuthError;
+use crate::stats::MatchStats;
+use crate::store::{FileStore, UserStore};
 
 /**
  * 游戏玩家数据，用于统计积分和用户信息。
@@ -15,6 +18,73 @@ pub struct User {
 
     /// 积分
     pub score: i64,
+
+    /// 长期统计数据
+    #[serde(default)]
+    pub stats: PlayerStats,
+
+    /// 游戏进行中逃跑的次数
+    #[serde(default)]
+    pub escapes: u32,
+}
+
+/// 玩家的长期统计数据
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub struct PlayerStats {
+    /// 总局数
+    pub games: u32,
+
+    /// 获胜局数
+    pub wins: u32,
+
+    /// 当地主的局数
+    pub landlord_games: u32,
+
+    /// 当地主获胜的局数
+    pub landlord_wins: u32,
+
+    /// 打出的炸弹数
+    pub bombs: u32,
+
+    /// 打出的火箭数
+    pub rockets: u32,
+
+    /// 累计积分变化
+    pub total_payout: i64,
+}
+
+impl PlayerStats {
+    /// 地主胜率，没有当过地主时为 0
+    pub fn landlord_win_rate(&self) -> f64 {
+        if self.landlord_games == 0 {
+            0.0
+        } else {
+            self.landlord_wins as f64 / self.landlord_games as f64
+        }
+    }
+
+    /// 平均每局的积分变化
+    pub fn average_payout(&self) -> f64 {
+        if self.games == 0 {
+            0.0
+        } else {
+            self.total_payout as f64 / self.games as f64
+        }
+    }
+
+    /// 计入一局游戏中 `seat` 座位的数据
+    pub fn record(&mut self, stats: &MatchStats, seat: usize) {
+        let win = stats.is_winner(seat);
+        self.games += 1;
+        self.wins += win as u32;
+        if stats.landlord == Some(seat) {
+            self.landlord_games += 1;
+            self.landlord_wins += win as u32;
+        }
+        self.bombs += stats.bombs[seat];
+        self.rockets += stats.rockets[seat];
+        self.total_payout += stats.payouts[seat];
+    }
 }
 
 /// 用户标识符
@@ -24,7 +94,9 @@ impl User {
     pub fn new(id: String) -> User {
         User {
             id,
-            score: 0
+            score: 0,
+            stats: PlayerStats::default(),
+            escapes: 0,
         }
     }
 }
@@ -47,108 +119,248 @@ pub enum UserState {
     Playing(String)
 }
 
+/// 定期保存玩家数据的间隔
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 查询对局记录时最多返回的局数
+pub const HISTORY_LIMIT: usize = 20;
+
+/// 读取环境变量 `CARDGAME_CLAIM_LEGACY`，为 `1` 或 `true` 时允许认领没有密码的老用户
+pub fn legacy_claims_from_env() -> bool {
+    matches!(std::env::var("CARDGAME_CLAIM_LEGACY").as_deref(), Ok("1") | Ok("true"))
+}
+
 /// 玩家数据管理
-/// TODO 数据库
+/// 数据保存在 `UserStore` 中，读取过的用户缓存在内存里
+/// 修改过的用户由 `flush` 定期写入存储，关闭服务器前需要再调用一次 `flush`
+/// 读取失败的用户不会进入缓存，也就不会被覆盖
 pub struct UserManager {
-    /// 用户数据目录
-    path: String,
+    /// 存储后端
+    store: Box<dyn UserStore>,
 
     /// 用户缓存
-    cache: HashMap<String, User>
+    cache: HashMap<String, User>,
+
+    /// 修改后尚未保存的用户
+    dirty: HashSet<UserId>,
+
+    /// 用户的密码哈希，与用户数据分开保存
+    credentials: HashMap<UserId, Credentials>,
+
+    /// 会话令牌对应的用户，只保存在内存中，服务器重启后需要重新登入
+    sessions: HashMap<String, UserId>,
+
+    /// 是否允许用原来的名字注册已有数据但没有密码的老用户
+    claim_legacy: bool,
 }
 
 impl UserManager {
+    /// 使用文件存储，`path` 为用户数据目录
     pub fn new(path: String) -> UserManager {
-        let p = Path::new(&path);
-        if !p.is_dir() {
-            fs::create_dir(p).expect("Unable to create user directory");
-        }
+        let store = FileStore::new(path).expect("Unable to create user directory");
+        UserManager::with_store(Box::new(store))
+    }
 
+    pub fn with_store(store: Box<dyn UserStore>) -> UserManager {
+        // 密码损坏时不能当作没有密码，否则任何人都可以重新注册别人的账号
+        let credentials = store.load_credentials().expect("Unable to read credentials");
         UserManager {
-            path,
-            cache: HashMap::new()
+            store,
+            cache: HashMap::new(),
+            dirty: HashSet::new(),
+            credentials,
+            sessions: HashMap::new(),
+            claim_legacy: false,
         }
     }
 
-    pub fn get_user(&self, id: &str) -> Option<User> {
-        if self.cache.contains_key(id) {
-            self.cache.get(id).cloned()
-        } else {
-            let user = self.read_user(id);
-            if let Ok(user) = user { // 存在这个文件
-                Some(user)
-            } else {
-                Some(User::new(id.to_string()))
+    /// 允许认领老用户：旧版本的账号都没有密码，谁先注册这个名字谁就得到原来的积分
+    /// 只应在迁移期间打开，每次认领都会打印日志
+    pub fn allow_legacy_claims(&mut self, allow: bool) {
+        self.claim_legacy = allow;
+    }
+
+    /// 注册并登入，返回会话令牌
+    /// 已有数据但没有设置密码的老用户默认不能注册，`allow_legacy_claims` 打开后可以认领并保留积分
+    pub fn register(&mut self, id: &str, password: &str) -> Result<String, AuthError> {
+        if !is_valid_name(id) {
+            return Err(AuthError::InvalidName);
+        }
+        if self.credentials.contains_key(id) {
+            return Err(AuthError::UserExists);
+        }
+        // 先读取用户数据，数据损坏时不能注册，否则会用新用户覆盖原来的积分
+        let legacy = match self.read_user(id) {
+            Ok(user) => user.is_some(),
+            Err(err) => {
+                println!("{}", err);
+                return Err(AuthError::StorageFailed);
+            }
+        };
+        if legacy {
+            if !self.claim_legacy {
+                return Err(AuthError::UserExists);
             }
+            println!("老用户 {} 被认领", id);
+        }
+        if let Err(err) = self.load_user(id) {
+            println!("{}", err);
+            return Err(AuthError::StorageFailed);
+        }
+        let credentials = Credentials::new(password);
+        if let Err(err) = self.store.save_credentials(id, &credentials) {
+            println!("无法保存 {} 的密码: {}", id, err);
+            return Err(AuthError::StorageFailed);
+        }
+        self.credentials.insert(id.to_string(), credentials);
+        Ok(self.new_session(id))
+    }
+
+    /// 用密码登入，返回新的会话令牌，这个用户之前的令牌失效
+    pub fn login(&mut self, id: &str, password: &str) -> Result<String, AuthError> {
+        let credentials = self.credentials.get(id).ok_or(AuthError::UnknownUser)?;
+        if !credentials.verify(password) {
+            return Err(AuthError::WrongPassword);
+        }
+        Ok(self.new_session(id))
+    }
+
+    /// 用会话令牌登入，返回令牌对应的用户
+    pub fn authenticate(&self, token: &str) -> Result<UserId, AuthError> {
+        self.sessions.get(token).cloned().ok_or(AuthError::InvalidToken)
+    }
+
+    /// 使会话令牌失效
+    pub fn logout(&mut self, token: &str) {
+        self.sessions.remove(token);
+    }
+
+    /// 每个用户同时只有一个有效的会话
+    fn new_session(&mut self, id: &str) -> String {
+        self.sessions.retain(|_, user| user != id);
+        let token = new_session_token();
+        self.sessions.insert(token.clone(), id.to_string());
+        token
+    }
+
+    /// 读取用户数据，没有数据的用户返回新用户，数据损坏时返回错误
+    pub fn get_user(&self, id: &str) -> Result<User, anyhow::Error> {
+        match self.cache.get(id) {
+            Some(user) => Ok(user.clone()),
+            None => Ok(self.read_user(id)?.unwrap_or_else(|| User::new(id.to_string()))),
         }
     }
 
     pub fn insert_user(&mut self, id: String, user: User) {
+        self.dirty.insert(id.clone());
         self.cache.insert(id, user);
-        self.write();
     }
 
-    pub fn get_user_mut(&mut self, id: &str) -> &mut User {
-        if !self.cache.contains_key(id) {
-            let user = self.read_user_or_create(id);
-            self.cache.insert(id.to_string(), user);
+    /// 修改用户数据，数据有变化时这个用户会在下次 `flush` 时保存
+    /// 数据损坏时返回错误，不会修改
+    pub fn update_user<F>(&mut self, id: &str, f: F) -> Result<(), anyhow::Error>
+        where F: FnOnce(&mut User) {
+        let user = self.load_user(id)?;
+        let before = user.clone();
+        f(user);
+        if user.score != before.score || user.stats != before.stats || user.escapes != before.escapes {
+            self.dirty.insert(id.to_string());
+        }
+        Ok(())
+    }
 
+    /// 结算一局游戏：更新玩家积分与统计数据，并保存这局的统计，返回对局编号
+    pub fn record_match(&mut self, stats: &MatchStats) -> Result<u64, anyhow::Error> {
+        // 先读取所有玩家，有人的数据损坏时整局都不结算
+        for id in stats.players.iter() {
+            self.load_user(id)?;
+        }
+        for (seat, id) in stats.players.iter().enumerate() {
+            self.update_user(id, |user| {
+                user.score += stats.payouts[seat];
+                user.stats.record(stats, seat);
+                if stats.escaped == Some(seat) {
+                    user.escapes += 1;
+                }
+            })?;
         }
-        self.cache.get_mut(id).unwrap()
+
+        self.store.append_match(stats)
     }
 
-    pub fn write(&self) {
-        self.create_dir_if_not_exists();
+    /// 读取所有对局统计
+    pub fn read_matches(&self) -> Result<Vec<MatchStats>, anyhow::Error> {
+        self.store.load_matches()
+    }
 
-        let mut counter = 0;
-        for user in self.cache.values().into_iter() {
-            self.write_user(user);
-            counter += 1;
-        }
-        println!("Saved {} users", counter);
+    /// 玩家最近的对局，最近的在前，最多 `HISTORY_LIMIT` 局，不含牌谱
+    pub fn recent_matches(&self, id: &str) -> Result<Vec<MatchStats>, anyhow::Error> {
+        Ok(self.store.load_user_matches(id, HISTORY_LIMIT)?
+            .iter()
+            .map(|x| x.without_history())
+            .collect())
     }
 
-    pub fn read(&mut self) -> Result<(), std::io::Error> {
-        self.create_dir_if_not_exists();
+    /// 按编号读取一局的完整记录
+    pub fn find_match(&self, id: u64) -> Result<Option<MatchStats>, anyhow::Error> {
+        self.store.load_match(id)
+    }
 
-        let entries = fs::read_dir(&self.path)?
-            .map(|res| res.map(|e| e.path()))
-            .collect::<Result<Vec<_>, std::io::Error>>()?;
+    /// 是否有尚未保存的用户
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
 
-        for file in entries.into_iter() {
-            let str = fs::read_to_string(file)?;
-            let user: User = serde_json::from_str(&str).unwrap();
-            self.cache.insert(user.id.clone(), user);
-            drop(str);
+    /// 保存修改过的用户，返回保存的数量
+    /// 出错时没有保存的用户仍然标记为修改过，下次再试
+    pub fn flush(&mut self) -> Result<usize, anyhow::Error> {
+        let mut counter = 0;
+        for id in self.dirty.iter().cloned().collect::<Vec<_>>() {
+            if let Some(user) = self.cache.get(&id) {
+                self.store.save_user(user)?;
+            }
+            self.dirty.remove(&id);
+            counter += 1;
         }
-        Ok(())
+        Ok(counter)
     }
 
-    fn create_dir_if_not_exists(&self) {
-        let p = Path::new(&self.path);
-        if !p.is_dir() {
-            fs::create_dir(p).expect("Unable to create user directory");
+    /// 读取所有用户到缓存，损坏的数据会被跳过，尚未保存的修改不会被覆盖
+    pub fn read(&mut self) -> Result<(), anyhow::Error> {
+        for user in self.store.load_users()? {
+            if !self.dirty.contains(&user.id) {
+                self.cache.insert(user.id.clone(), user);
+            }
         }
+        Ok(())
     }
 
-    fn read_user_or_create(&self, id: &str) -> User {
-        if let Ok(user) = self.read_user(id) {
-            user
-        } else {
-            User::new(id.to_string())
-        }
+    fn read_user(&self, id: &str) -> Result<Option<User>, anyhow::Error> {
+        self.store.load_user(id)
+            .map_err(|err| anyhow::anyhow!("无法读取 {} 的数据: {}", id, err))
     }
 
-    /// 从文件读取用户数据
-    fn read_user(&self, id: &str) -> Result<User, anyhow::Error> {
-        let path = Path::new(&self.path).join(id);
-        let str = fs::read_to_string(path)?;
-        serde_json::from_str(&str).map_err(anyhow::Error::from)
+    /// 把用户读入缓存，没有数据的用户创建后标记为修改过
+    fn load_user(&mut self, id: &str) -> Result<&mut User, anyhow::Error> {
+        if !self.cache.contains_key(id) {
+            let user = match self.read_user(id)? {
+                Some(user) => user,
+                None => {
+                    self.dirty.insert(id.to_string());
+                    User::new(id.to_string())
+                }
+            };
+            self.cache.insert(id.to_string(), user);
+        }
+        Ok(self.cache.get_mut(id).unwrap())
     }
+}
 
-    /// 写入文件
-    fn write_user(&self, user: &User) {
-        let path = Path::new(&self.path).join(&user.id);
-        fs::write(path, serde_json::to_string(user).unwrap()).expect("Unable to write user");
+impl Drop for UserManager {
+    /// 没有正常关闭时尽量保存剩余的修改，服务器关闭时应当先调用 `flush`
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            println!("无法保存玩家数据: {}", err);
+        }
     }
-}
\ No newline at end of file
+}